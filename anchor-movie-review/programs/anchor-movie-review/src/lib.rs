@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::pubkey::MAX_SEED_LEN;
 use mpl_token_metadata::instruction::create_metadata_accounts_v2;
+use unicode_normalization::UnicodeNormalization;
 
 declare_id!("7iTggggVhJfZmP2qSUtmAdvU46uGshgXQaWAetcv7gwo");
 
@@ -38,6 +41,9 @@ pub mod anchor_movie_review {
         movie_comment_counter.counter = 0;
         msg!("Counter: {}", movie_comment_counter.counter);
 
+        let reward_config = &ctx.accounts.reward_config;
+        let amount = reward_config.amount(reward_config.review_reward, &ctx.accounts.reward_mint)?;
+
         let seeds = &["mint".as_bytes(), &[*ctx.bumps.get("reward_mint").unwrap()]];
 
         let signer = [&seeds[..]];
@@ -52,7 +58,7 @@ pub mod anchor_movie_review {
             &signer,
         );
 
-        token::mint_to(cpi_ctx, 10000000)?;
+        token::mint_to(cpi_ctx, amount)?;
         msg!("Minted Tokens");
 
         Ok(())
@@ -93,7 +99,7 @@ pub mod anchor_movie_review {
     ) -> Result<()> {
         msg!("Creating reward token...");
 
-        let seeds = &["mint".as_bytes(), &[*ctx.bumps.get("reward_bump").unwrap()]];
+        let seeds = &["mint".as_bytes(), &[*ctx.bumps.get("reward_mint").unwrap()]];
         let signer = [&seeds[..]];
 
         let account_info = vec![
@@ -131,6 +137,35 @@ pub mod anchor_movie_review {
         Ok(())
     }
 
+    pub fn initialize_reward_config(ctx: Context<InitializeRewardConfig>) -> Result<()> {
+        msg!("Initializing reward config...");
+
+        let reward_config = &mut ctx.accounts.reward_config;
+        reward_config.admin = ctx.accounts.authority.key();
+        reward_config.review_reward = RewardConfig::DEFAULT_REVIEW_REWARD;
+        reward_config.comment_reward = RewardConfig::DEFAULT_COMMENT_REWARD;
+        reward_config.emit_updated();
+
+        Ok(())
+    }
+
+    pub fn update_reward_config(
+        ctx: Context<UpdateRewardConfig>,
+        new_admin: Pubkey,
+        review_reward: u64,
+        comment_reward: u64,
+    ) -> Result<()> {
+        msg!("Updating reward config...");
+
+        let reward_config = &mut ctx.accounts.reward_config;
+        reward_config.admin = new_admin;
+        reward_config.review_reward = review_reward;
+        reward_config.comment_reward = comment_reward;
+        reward_config.emit_updated();
+
+        Ok(())
+    }
+
     pub fn add_comment(ctx: Context<AddComment>, comment: String) -> Result<()> {
         msg!("Creating comment account...");
         msg!("Comment: {}", comment);
@@ -145,6 +180,9 @@ pub mod anchor_movie_review {
 
         movie_comment_counter.counter += 1;
 
+        let reward_config = &ctx.accounts.reward_config;
+        let amount = reward_config.amount(reward_config.comment_reward, &ctx.accounts.reward_mint)?;
+
         let seeds = &["mint".as_bytes(), &[*ctx.bumps.get("reward_mint").unwrap()]];
 
         let signer = [&seeds[..]];
@@ -159,19 +197,70 @@ pub mod anchor_movie_review {
             &signer,
         );
 
-        token::mint_to(cpi_ctx, 5000000)?;
+        token::mint_to(cpi_ctx, amount)?;
         msg!("Minted Tokens");
 
         Ok(())
     }
 }
 
+/// Same normalization as `movie-review-comments/src/title.rs`, which documents
+/// the seed scheme.
+pub fn normalize_title(title: &str) -> String {
+    title.trim().to_lowercase().nfc().collect()
+}
+
+/// Review seed for `title`, as in `movie-review-comments/src/title.rs`.
+pub fn title_hash(title: &str) -> [u8; 32] {
+    hashv(&[normalize_title(title).as_bytes()]).to_bytes()
+}
+
+/// Whether `review` is the review `reviewer` posted under `title`, seeded with
+/// the title hash or, for reviews created before that, the raw title. Raw
+/// titles longer than a seed never made it on chain.
+pub fn is_review_address(review: &Pubkey, title: &str, reviewer: &Pubkey) -> bool {
+    let (address, _) =
+        Pubkey::find_program_address(&[title_hash(title).as_ref(), reviewer.as_ref()], &ID);
+    if address == *review {
+        return true;
+    }
+
+    if title.len() > MAX_SEED_LEN {
+        return false;
+    }
+    let (legacy_address, _) =
+        Pubkey::find_program_address(&[title.as_bytes(), reviewer.as_ref()], &ID);
+    legacy_address == *review
+}
+
+/// Comment PDAs are seeded with the comment index encoded big-endian, the
+/// canonical encoding shared with the native movie review programs.
+pub fn comment_index(index: u64) -> [u8; 8] {
+    index.to_be_bytes()
+}
+
+/// Addresses the comment at `index` under `movie_review` may live at, canonical
+/// one first. Comments created before this program adopted the big-endian
+/// index are seeded little-endian, so clients should take the first candidate
+/// that exists. Both encodings agree for some indexes.
+pub fn comment_address_candidates(movie_review: &Pubkey, index: u64) -> Vec<Pubkey> {
+    let mut candidates = Vec::with_capacity(2);
+    for index_seed in [comment_index(index), index.to_le_bytes()] {
+        let (address, _) =
+            Pubkey::find_program_address(&[movie_review.as_ref(), &index_seed], &ID);
+        if !candidates.contains(&address) {
+            candidates.push(address);
+        }
+    }
+    candidates
+}
+
 #[derive(Accounts)]
 #[instruction(title:String, description:String)]
 pub struct AddMovieReview<'info> {
     #[account(
         init,
-        seeds = [title.as_bytes(), initializer.key().as_ref()],
+        seeds = [title_hash(&title).as_ref(), initializer.key().as_ref()],
         bump,
         payer = initializer,
         space = 8 + 32 + 1 + 4 + title.len() + 4 + description.len()
@@ -190,6 +279,8 @@ pub struct AddMovieReview<'info> {
         bump
     )]
     pub reward_mint: Account<'info, Mint>,
+    #[account(seeds = ["reward_config".as_bytes()], bump)]
+    pub reward_config: Account<'info, RewardConfig>,
     #[account(
         init_if_needed,
         payer = initializer,
@@ -208,11 +299,16 @@ pub struct AddMovieReview<'info> {
 #[derive(Accounts)]
 #[instruction(title: String, description: String)]
 pub struct UpdateMovieReview<'info> {
+    // Checked against the stored title so reviews seeded with the raw title
+    // before title hashing can still be updated.
     #[account(
 			mut,
-			seeds = [title.as_bytes(), initializer.key().as_ref()], 
-			bump,
-			realloc = 8 + 32 + 1 + 4 + title.len() + 4 + description.len(), 
+			constraint = is_review_address(
+				&movie_review.key(),
+				&movie_review.title,
+				&initializer.key()
+			) @ ErrorCode::InvalidReviewAddress,
+			realloc = 8 + 32 + 1 + 4 + movie_review.title.len() + 4 + description.len(), 
 			realloc::payer = initializer, 
 			realloc::zero = true
 		)]
@@ -247,12 +343,47 @@ pub struct CreateTokenReward<'info> {
     pub token_metadata_program: AccountInfo<'info>,
 }
 
+// Only the program's upgrade authority may appoint the first reward admin.
+#[derive(Accounts)]
+pub struct InitializeRewardConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        seeds = ["reward_config".as_bytes()],
+        bump,
+        space = 8 + 32 + 8 + 8
+    )]
+    pub reward_config: Account<'info, RewardConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::AnchorMovieReview>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ ErrorCode::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRewardConfig<'info> {
+    #[account(
+        mut,
+        seeds = ["reward_config".as_bytes()],
+        bump,
+        has_one = admin @ ErrorCode::Unauthorized
+    )]
+    pub reward_config: Account<'info, RewardConfig>,
+    pub admin: Signer<'info>,
+}
+
 #[derive(Accounts)]
 #[instruction(comment: String)]
 pub struct AddComment<'info> {
     #[account(
         init,
-        seeds = [movie_review.key().as_ref(), &movie_comment_counter.counter.to_le_bytes()],
+        seeds = [movie_review.key().as_ref(), &comment_index(movie_comment_counter.counter)],
         bump,
         payer = initializer,
         space = 8 + 32 + 32 + 4 + comment.len() + 8
@@ -270,6 +401,8 @@ pub struct AddComment<'info> {
         bump
     )]
     pub reward_mint: Account<'info, Mint>,
+    #[account(seeds = ["reward_config".as_bytes()], bump)]
+    pub reward_config: Account<'info, RewardConfig>,
     #[account(
         init_if_needed,
         payer = initializer,
@@ -315,8 +448,83 @@ pub struct MovieComment {
     pub count: u64,        // 8
 }
 
+/// Per-action rewards in whole tokens, scaled by the mint's decimals when
+/// minting. Only `admin` can change them.
+#[account]
+pub struct RewardConfig {
+    pub admin: Pubkey,       // 32
+    pub review_reward: u64,  // 8
+    pub comment_reward: u64, // 8
+}
+
+impl RewardConfig {
+    pub const DEFAULT_REVIEW_REWARD: u64 = 10;
+    pub const DEFAULT_COMMENT_REWARD: u64 = 5;
+
+    /// `tokens` in base units of `mint`.
+    pub fn amount(&self, tokens: u64, mint: &Mint) -> Result<u64> {
+        10u64
+            .checked_pow(mint.decimals.into())
+            .and_then(|unit| unit.checked_mul(tokens))
+            .ok_or_else(|| error!(ErrorCode::RewardOverflow))
+    }
+
+    fn emit_updated(&self) {
+        emit!(RewardConfigUpdated {
+            admin: self.admin,
+            review_reward: self.review_reward,
+            comment_reward: self.comment_reward,
+        });
+    }
+}
+
+#[event]
+pub struct RewardConfigUpdated {
+    pub admin: Pubkey,
+    pub review_reward: u64,
+    pub comment_reward: u64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Rating greater than 5 or less than 1")]
     InvalidRating,
+    #[msg("Only the reward config admin can do this")]
+    Unauthorized,
+    #[msg("Reward amount does not fit in a u64")]
+    RewardOverflow,
+    #[msg("Account is not the signer's review of this title")]
+    InvalidReviewAddress,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn review_address_accepts_hashed_and_legacy_seeds() {
+        let reviewer = Pubkey::new_unique();
+        let title = "Amélie";
+        let (hashed, _) =
+            Pubkey::find_program_address(&[title_hash(title).as_ref(), reviewer.as_ref()], &ID);
+        let (legacy, _) = Pubkey::find_program_address(&[title.as_bytes(), reviewer.as_ref()], &ID);
+
+        assert!(is_review_address(&hashed, title, &reviewer));
+        assert!(is_review_address(&legacy, title, &reviewer));
+        // Someone else's review, or this reviewer's review of another title.
+        assert!(!is_review_address(&legacy, title, &Pubkey::new_unique()));
+        assert!(!is_review_address(&legacy, "Amelie", &reviewer));
+    }
+
+    #[test]
+    fn review_address_of_a_long_title_is_only_hashed() {
+        let reviewer = Pubkey::new_unique();
+        let title = "Dr. Strangelove or: How I Learned to Stop Worrying and Love the Bomb";
+        assert!(title.len() > MAX_SEED_LEN);
+
+        let (hashed, _) =
+            Pubkey::find_program_address(&[title_hash(title).as_ref(), reviewer.as_ref()], &ID);
+        assert!(is_review_address(&hashed, title, &reviewer));
+        assert!(!is_review_address(&Pubkey::new_unique(), title, &reviewer));
+    }
 }
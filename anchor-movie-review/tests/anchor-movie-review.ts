@@ -1,17 +1,186 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
+import { expect } from "chai";
+import { createHash } from "crypto";
 import { AnchorMovieReview } from "../target/types/anchor_movie_review";
 
+const TOKEN_METADATA_PROGRAM_ID = new anchor.web3.PublicKey(
+  "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+);
+const BPF_LOADER_UPGRADEABLE_ID = new anchor.web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
 describe("anchor-movie-review", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace
     .AnchorMovieReview as Program<AnchorMovieReview>;
+  const wallet = provider.wallet.publicKey;
+
+  const pda = (seeds: Buffer[], programId = program.programId) =>
+    anchor.web3.PublicKey.findProgramAddressSync(seeds, programId)[0];
+
+  const rewardMint = pda([Buffer.from("mint")]);
+  const rewardConfig = pda([Buffer.from("reward_config")]);
+  // The test validator deploys the program with the wallet as its upgrade
+  // authority.
+  const programData = pda(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE_ID
+  );
+  const tokenAccount = anchor.utils.token.associatedAddress({
+    mint: rewardMint,
+    owner: wallet,
+  });
+
+  // The reward mint has 6 decimals.
+  const tokens = (amount: number) => amount * 10 ** 6;
+
+  const balance = async () => {
+    const { value } = await provider.connection.getTokenAccountBalance(
+      tokenAccount
+    );
+    return Number(value.amount);
+  };
+
+  const fundedIntruder = async () => {
+    const intruder = anchor.web3.Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(
+      intruder.publicKey,
+      anchor.web3.LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(signature);
+    return intruder;
+  };
+
+  const expectError = async (request: Promise<unknown>, code: string) => {
+    try {
+      await request;
+      expect.fail(`expected ${code}`);
+    } catch (err) {
+      expect(err.error.errorCode.code).to.equal(code);
+    }
+  };
+
+  it("creates the reward mint", async () => {
+    await program.methods
+      .createRewardMint("", "Movie Review Token", "MRT")
+      .accounts({
+        rewardMint,
+        user: wallet,
+        metadata: pda(
+          [
+            Buffer.from("metadata"),
+            TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+            rewardMint.toBuffer(),
+          ],
+          TOKEN_METADATA_PROGRAM_ID
+        ),
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .rpc();
+  });
+
+  it("only lets the upgrade authority initialize the reward config", async () => {
+    const intruder = await fundedIntruder();
+    await expectError(
+      program.methods
+        .initializeRewardConfig()
+        .accounts({
+          rewardConfig,
+          authority: intruder.publicKey,
+          program: program.programId,
+          programData,
+        })
+        .signers([intruder])
+        .rpc(),
+      "Unauthorized"
+    );
+
+    await program.methods
+      .initializeRewardConfig()
+      .accounts({
+        rewardConfig,
+        authority: wallet,
+        program: program.programId,
+        programData,
+      })
+      .rpc();
+
+    const config = await program.account.rewardConfig.fetch(rewardConfig);
+    expect(config.admin.equals(wallet)).to.be.true;
+    expect(config.reviewReward.toNumber()).to.equal(10);
+    expect(config.commentReward.toNumber()).to.equal(5);
+  });
+
+  it("only lets the admin update the reward config", async () => {
+    const intruder = await fundedIntruder();
+    await expectError(
+      program.methods
+        .updateRewardConfig(
+          intruder.publicKey,
+          new anchor.BN(1_000),
+          new anchor.BN(1_000)
+        )
+        .accounts({ rewardConfig, admin: intruder.publicKey })
+        .signers([intruder])
+        .rpc(),
+      "Unauthorized"
+    );
+
+    await program.methods
+      .updateRewardConfig(wallet, new anchor.BN(3), new anchor.BN(2))
+      .accounts({ rewardConfig, admin: wallet })
+      .rpc();
+
+    const config = await program.account.rewardConfig.fetch(rewardConfig);
+    expect(config.admin.equals(wallet)).to.be.true;
+    expect(config.reviewReward.toNumber()).to.equal(3);
+    expect(config.commentReward.toNumber()).to.equal(2);
+  });
+
+  it("pays the configured review and comment rewards", async () => {
+    const title = "Inception";
+    // Seeded like `title_hash` for an ASCII title.
+    const titleHash = createHash("sha256")
+      .update(title.trim().toLowerCase().normalize("NFC"))
+      .digest();
+    const movieReview = pda([titleHash, wallet.toBuffer()]);
+    const movieCommentCounter = pda([
+      Buffer.from("counter"),
+      movieReview.toBuffer(),
+    ]);
+
+    await program.methods
+      .addMovieReview(title, "Dreams within dreams", 5)
+      .accounts({
+        movieReview,
+        movieCommentCounter,
+        rewardMint,
+        rewardConfig,
+        tokenAccount,
+        initializer: wallet,
+      })
+      .rpc();
+    expect(await balance()).to.equal(tokens(3));
 
-  it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    // The first comment, at index 0 encoded big-endian.
+    const movieComment = pda([movieReview.toBuffer(), Buffer.alloc(8)]);
+    await program.methods
+      .addComment("Still spinning")
+      .accounts({
+        movieComment,
+        movieReview,
+        movieCommentCounter,
+        rewardMint,
+        rewardConfig,
+        tokenAccount,
+        initializer: wallet,
+      })
+      .rpc();
+    expect(await balance()).to.equal(tokens(3 + 2));
   });
 });
@@ -0,0 +1,473 @@
+use crate::{
+    NftMultiplier, RedeemAll, StakeError, StakeMany, StakeState, UnstakeMany, UserStakeInfo,
+};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program::{self, CreateAccount};
+use anchor_spl::token::{self, Approve, Mint, Revoke, TokenAccount};
+use mpl_token_metadata::{
+    instruction::{freeze_delegated_account, thaw_delegated_account},
+    pda::find_metadata_account,
+    state::{Metadata as MetadataAccount, TokenMetadataAccount},
+    ID as MetadataTokenId,
+};
+use std::slice::ChunksExact;
+
+/// Most NFTs one `stake_many`, `unstake_many` or `redeem_all` can touch. Eight
+/// `stake_many` tuples plus the fixed accounts stay under the 64-account lock
+/// limit, and each freeze or thaw CPI costs tens of thousands of compute
+/// units, so callers should also raise the compute-unit limit.
+///
+/// Every account key takes 32 bytes of a legacy transaction, so only 4
+/// `stake_many`, 5 `unstake_many` or all 8 `redeem_all` tuples fit in its
+/// 1232 bytes. Fuller batches have to be sent as versioned transactions that
+/// load the per-NFT accounts from an address lookup table.
+pub const MAX_BATCH: usize = 8;
+
+/// Token account, mint, edition, stake state, metadata, multiplier.
+pub const STAKE_TUPLE_LEN: usize = 6;
+/// Token account, mint, edition, stake state.
+pub const UNSTAKE_TUPLE_LEN: usize = 4;
+/// Token account, stake state.
+pub const REDEEM_TUPLE_LEN: usize = 2;
+
+/// Splits remaining accounts into one `tuple_len` chunk per NFT.
+pub fn nft_tuples<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+    tuple_len: usize,
+) -> Result<ChunksExact<'a, AccountInfo<'info>>> {
+    let nft_count = accounts.len() / tuple_len;
+    require!(
+        nft_count > 0 && nft_count * tuple_len == accounts.len(),
+        StakeError::InvalidBatchAccounts
+    );
+    require!(nft_count <= MAX_BATCH, StakeError::BatchTooLarge);
+
+    Ok(accounts.chunks_exact(tuple_len))
+}
+
+/// Stakes the NFT in one `stake_many` tuple, creating its stake state if
+/// this is the first time it is staked.
+pub fn stake_nft<'info>(
+    accounts: &StakeMany<'info>,
+    program_id: &Pubkey,
+    nft: &[AccountInfo<'info>],
+    lock_tier: crate::LockTier,
+    now: i64,
+    authority_bump: u8,
+) -> Result<()> {
+    let (token_info, mint_info, edition_info, stake_state_info, metadata_info, multiplier_info) =
+        (&nft[0], &nft[1], &nft[2], &nft[3], &nft[4], &nft[5]);
+    let user = &accounts.user;
+
+    load_token_account(token_info, user.key, Some(mint_info.key))?;
+    require_keys_eq!(*mint_info.owner, token::ID, StakeError::InvalidNftAccounts);
+    Mint::try_deserialize(&mut &mint_info.data.borrow()[..])?;
+    require_keys_eq!(
+        *edition_info.owner,
+        MetadataTokenId,
+        StakeError::InvalidNftAccounts
+    );
+
+    require_keys_eq!(
+        *metadata_info.key,
+        find_metadata_account(mint_info.key).0,
+        StakeError::InvalidNftAccounts
+    );
+    let metadata = MetadataAccount::from_account_info(metadata_info)?;
+    accounts.stake_pool.check_nft(&metadata)?;
+
+    let (multiplier_pda, _) =
+        Pubkey::find_program_address(&[b"multiplier", mint_info.key.as_ref()], program_id);
+    require_keys_eq!(
+        *multiplier_info.key,
+        multiplier_pda,
+        StakeError::InvalidNftAccounts
+    );
+    let multiplier_bps = if multiplier_info.data_is_empty() {
+        NftMultiplier::BASE_BPS
+    } else {
+        require_keys_eq!(
+            *multiplier_info.owner,
+            *program_id,
+            StakeError::InvalidNftAccounts
+        );
+        NftMultiplier::try_deserialize(&mut &multiplier_info.data.borrow()[..])?.multiplier_bps
+    };
+
+    let (stake_state_pda, stake_state_bump) =
+        Pubkey::find_program_address(&[user.key.as_ref(), token_info.key.as_ref()], program_id);
+    require_keys_eq!(
+        *stake_state_info.key,
+        stake_state_pda,
+        StakeError::InvalidNftAccounts
+    );
+    let mut stake_state = if stake_state_info.data_is_empty() {
+        let space = std::mem::size_of::<UserStakeInfo>() + 8;
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                accounts.system_program.to_account_info(),
+                CreateAccount {
+                    from: user.to_account_info(),
+                    to: stake_state_info.clone(),
+                },
+                &[&[
+                    user.key.as_ref(),
+                    token_info.key.as_ref(),
+                    &[stake_state_bump],
+                ]],
+            ),
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            program_id,
+        )?;
+        UserStakeInfo::default()
+    } else {
+        require_keys_eq!(
+            *stake_state_info.owner,
+            *program_id,
+            StakeError::InvalidNftAccounts
+        );
+        UserStakeInfo::try_deserialize(&mut &stake_state_info.data.borrow()[..])?
+    };
+    require!(
+        stake_state.stake_state == StakeState::Unstaked,
+        StakeError::AlreadyStaked
+    );
+
+    token::approve(
+        CpiContext::new(
+            accounts.token_program.to_account_info(),
+            Approve {
+                to: token_info.clone(),
+                delegate: accounts.program_authority.to_account_info(),
+                authority: user.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+
+    invoke_signed(
+        &freeze_delegated_account(
+            accounts.metadata_program.key(),
+            accounts.program_authority.key(),
+            token_info.key(),
+            edition_info.key(),
+            mint_info.key(),
+        ),
+        &[
+            accounts.program_authority.to_account_info(),
+            token_info.clone(),
+            edition_info.clone(),
+            mint_info.clone(),
+            accounts.metadata_program.to_account_info(),
+        ],
+        &[&[b"authority", &[authority_bump]]],
+    )?;
+
+    stake_state.token_account = token_info.key();
+    stake_state.user_pubkey = user.key();
+    stake_state.stake_state = StakeState::Staked;
+    stake_state.stake_start_time = now;
+    stake_state.last_stake_redeem = now;
+    stake_state.multiplier_bps = multiplier_bps;
+    stake_state.lock_tier = lock_tier;
+    stake_state.is_initialized = true;
+    stake_state.try_serialize(&mut &mut stake_state_info.data.borrow_mut()[..])
+}
+
+/// Releases the NFT in one `unstake_many` tuple and returns the reward owed
+/// for it, after any early-unstake penalty.
+pub fn unstake_nft<'info>(
+    accounts: &UnstakeMany<'info>,
+    program_id: &Pubkey,
+    nft: &[AccountInfo<'info>],
+    now: i64,
+    authority_bump: u8,
+) -> Result<u64> {
+    let (token_info, mint_info, edition_info, stake_state_info) =
+        (&nft[0], &nft[1], &nft[2], &nft[3]);
+    let user = &accounts.user;
+
+    load_token_account(token_info, user.key, Some(mint_info.key))?;
+    require_keys_eq!(
+        *edition_info.owner,
+        MetadataTokenId,
+        StakeError::InvalidNftAccounts
+    );
+    let mut stake_state = load_stake_state(program_id, user.key, token_info, stake_state_info)?;
+
+    invoke_signed(
+        &thaw_delegated_account(
+            accounts.metadata_program.key(),
+            accounts.program_authority.key(),
+            token_info.key(),
+            edition_info.key(),
+            mint_info.key(),
+        ),
+        &[
+            accounts.program_authority.to_account_info(),
+            token_info.clone(),
+            edition_info.clone(),
+            mint_info.clone(),
+            accounts.metadata_program.to_account_info(),
+        ],
+        &[&[b"authority", &[authority_bump]]],
+    )?;
+
+    token::revoke(CpiContext::new(
+        accounts.token_program.to_account_info(),
+        Revoke {
+            source: token_info.clone(),
+            authority: user.to_account_info(),
+        },
+    ))?;
+
+    let redeem_amount = accounts.stake_pool.claimable_reward(&stake_state, now)?;
+    stake_state.last_stake_redeem = now;
+    stake_state.stake_state = StakeState::Unstaked;
+    stake_state.try_serialize(&mut &mut stake_state_info.data.borrow_mut()[..])?;
+
+    Ok(redeem_amount)
+}
+
+/// Marks the rewards of the NFT in one `redeem_all` tuple as redeemed and
+/// returns the amount owed, after any early-unstake penalty.
+pub fn redeem_nft<'info>(
+    accounts: &RedeemAll<'info>,
+    program_id: &Pubkey,
+    nft: &[AccountInfo<'info>],
+    now: i64,
+) -> Result<u64> {
+    let (token_info, stake_state_info) = (&nft[0], &nft[1]);
+    let user = &accounts.user;
+
+    load_token_account(token_info, user.key, None)?;
+    let mut stake_state = load_stake_state(program_id, user.key, token_info, stake_state_info)?;
+
+    let redeem_amount = accounts.stake_pool.claimable_reward(&stake_state, now)?;
+    stake_state.last_stake_redeem = now;
+    stake_state.try_serialize(&mut &mut stake_state_info.data.borrow_mut()[..])?;
+
+    Ok(redeem_amount)
+}
+
+fn load_token_account(
+    token_info: &AccountInfo,
+    user: &Pubkey,
+    mint: Option<&Pubkey>,
+) -> Result<TokenAccount> {
+    require_keys_eq!(*token_info.owner, token::ID, StakeError::InvalidNftAccounts);
+    let token_account = TokenAccount::try_deserialize(&mut &token_info.data.borrow()[..])?;
+    require_keys_eq!(token_account.owner, *user, StakeError::InvalidNftAccounts);
+    if let Some(mint) = mint {
+        require_keys_eq!(token_account.mint, *mint, StakeError::InvalidNftAccounts);
+    }
+
+    Ok(token_account)
+}
+
+/// Loads a staked NFT's state with the same checks as `Redeem` and `Unstake`.
+fn load_stake_state(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    token_info: &AccountInfo,
+    stake_state_info: &AccountInfo,
+) -> Result<UserStakeInfo> {
+    let (stake_state_pda, _) =
+        Pubkey::find_program_address(&[user.as_ref(), token_info.key.as_ref()], program_id);
+    require_keys_eq!(
+        *stake_state_info.key,
+        stake_state_pda,
+        StakeError::InvalidNftAccounts
+    );
+
+    require_keys_eq!(
+        *stake_state_info.owner,
+        *program_id,
+        StakeError::InvalidNftAccounts
+    );
+    let stake_state = UserStakeInfo::try_deserialize(&mut &stake_state_info.data.borrow()[..])?;
+    require_keys_eq!(
+        stake_state.user_pubkey,
+        *user,
+        StakeError::InvalidNftAccounts
+    );
+    require_keys_eq!(
+        stake_state.token_account,
+        token_info.key(),
+        StakeError::InvalidNftAccounts
+    );
+    require!(stake_state.is_initialized, StakeError::UninitializedAccount);
+    require!(
+        stake_state.stake_state == StakeState::Staked,
+        StakeError::InvalidStakeState
+    );
+
+    Ok(stake_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_spl::token::spl_token::state::{Account as SplTokenAccount, AccountState};
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+    }
+
+    impl TestAccount {
+        fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+            TestAccount {
+                key: Pubkey::new_unique(),
+                owner,
+                lamports: 1_000_000,
+                data,
+            }
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                false,
+                true,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn token_account(owner: Pubkey, mint: Pubkey) -> TestAccount {
+        let mut data = vec![0; SplTokenAccount::LEN];
+        SplTokenAccount {
+            mint,
+            owner,
+            amount: 1,
+            state: AccountState::Initialized,
+            ..SplTokenAccount::default()
+        }
+        .pack_into_slice(&mut data);
+        TestAccount::new(token::ID, data)
+    }
+
+    /// `user`'s stake state for `token_account`, at its PDA.
+    fn stake_state(user: Pubkey, token_account: Pubkey, state: StakeState) -> TestAccount {
+        let mut data = vec![];
+        UserStakeInfo {
+            token_account,
+            user_pubkey: user,
+            stake_state: state,
+            is_initialized: true,
+            ..UserStakeInfo::default()
+        }
+        .try_serialize(&mut data)
+        .unwrap();
+        let mut account = TestAccount::new(crate::ID, data);
+        account.key =
+            Pubkey::find_program_address(&[user.as_ref(), token_account.as_ref()], &crate::ID).0;
+        account
+    }
+
+    fn invalid_nft_accounts() -> Error {
+        StakeError::InvalidNftAccounts.into()
+    }
+
+    fn stake_state_error(user: &Pubkey, token: &AccountInfo, stake_state: &AccountInfo) -> Error {
+        load_stake_state(&crate::ID, user, token, stake_state)
+            .err()
+            .expect("stake state should be rejected")
+    }
+
+    #[test]
+    fn nft_tuples_must_be_whole_and_within_the_batch_limit() {
+        let mut accounts: Vec<TestAccount> = (0..REDEEM_TUPLE_LEN * (MAX_BATCH + 1))
+            .map(|_| TestAccount::new(Pubkey::default(), vec![]))
+            .collect();
+        let infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+
+        let tuples = nft_tuples(&infos[..REDEEM_TUPLE_LEN * 2], REDEEM_TUPLE_LEN).unwrap();
+        assert_eq!(tuples.len(), 2);
+        let tuples = nft_tuples(&infos[..REDEEM_TUPLE_LEN * MAX_BATCH], REDEEM_TUPLE_LEN).unwrap();
+        assert_eq!(tuples.len(), MAX_BATCH);
+
+        for partial in [&infos[..0], &infos[..REDEEM_TUPLE_LEN + 1]] {
+            assert_eq!(
+                nft_tuples(partial, REDEEM_TUPLE_LEN).unwrap_err(),
+                StakeError::InvalidBatchAccounts.into()
+            );
+        }
+        assert_eq!(
+            nft_tuples(&infos, REDEEM_TUPLE_LEN).unwrap_err(),
+            StakeError::BatchTooLarge.into()
+        );
+    }
+
+    #[test]
+    fn token_account_must_hold_the_users_nft() {
+        let (user, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut owned = token_account(user, mint);
+        assert!(load_token_account(&owned.info(), &user, Some(&mint)).is_ok());
+        assert!(load_token_account(&owned.info(), &user, None).is_ok());
+
+        let other = Pubkey::new_unique();
+        assert_eq!(
+            load_token_account(&owned.info(), &other, Some(&mint)).unwrap_err(),
+            invalid_nft_accounts()
+        );
+        assert_eq!(
+            load_token_account(&owned.info(), &user, Some(&other)).unwrap_err(),
+            invalid_nft_accounts()
+        );
+
+        // Token account layout, but not owned by the token program.
+        let mut forged = token_account(user, mint);
+        forged.owner = Pubkey::new_unique();
+        assert_eq!(
+            load_token_account(&forged.info(), &user, Some(&mint)).unwrap_err(),
+            invalid_nft_accounts()
+        );
+    }
+
+    #[test]
+    fn stake_state_must_be_the_users_staked_nft() {
+        let user = Pubkey::new_unique();
+        let mut token = token_account(user, Pubkey::new_unique());
+        let mut other_token = token_account(user, Pubkey::new_unique());
+        let mut staked = stake_state(user, token.key, StakeState::Staked);
+        assert!(load_stake_state(&crate::ID, &user, &token.info(), &staked.info()).is_ok());
+
+        // Tuples that pair the NFT with someone else's or another NFT's state.
+        let mut others = stake_state(Pubkey::new_unique(), token.key, StakeState::Staked);
+        let mut other_nfts = stake_state(user, other_token.key, StakeState::Staked);
+        for stake_state_info in [others.info(), other_nfts.info()] {
+            assert_eq!(
+                stake_state_error(&user, &token.info(), &stake_state_info),
+                invalid_nft_accounts()
+            );
+        }
+        assert_eq!(
+            stake_state_error(&user, &other_token.info(), &staked.info()),
+            invalid_nft_accounts()
+        );
+
+        let mut forged = stake_state(user, token.key, StakeState::Staked);
+        forged.owner = Pubkey::new_unique();
+        assert_eq!(
+            stake_state_error(&user, &token.info(), &forged.info()),
+            invalid_nft_accounts()
+        );
+
+        let mut unstaked = stake_state(user, token.key, StakeState::Unstaked);
+        assert_eq!(
+            stake_state_error(&user, &token.info(), &unstaked.info()),
+            StakeError::InvalidStakeState.into()
+        );
+    }
+}
@@ -7,20 +7,36 @@ use anchor_spl::{
 };
 use mpl_token_metadata::{
     instruction::{freeze_delegated_account, thaw_delegated_account},
+    state::{Metadata as MetadataAccount, TokenMetadataAccount},
     ID as MetadataTokenId,
 };
+
+pub mod batch;
+pub mod rewards;
+
 declare_id!("GPxuYM4Y8zpNqJC7YiL97yiaXRoQ1cdHey3c85hRviXP");
 
 #[program]
 pub mod anchor_nft_staking {
     use super::*;
 
-    pub fn stake(ctx: Context<Stake>) -> Result<()> {
+    pub fn stake(ctx: Context<Stake>, lock_tier: LockTier) -> Result<()> {
         require!(
             ctx.accounts.stake_state.stake_state == StakeState::Unstaked,
             StakeError::AlreadyStaked
         );
 
+        msg!("Checking NFT collection...");
+        let metadata = MetadataAccount::from_account_info(&ctx.accounts.nft_metadata)?;
+        ctx.accounts.stake_pool.check_nft(&metadata)?;
+
+        let multiplier_bps = if ctx.accounts.nft_multiplier.data_is_empty() {
+            NftMultiplier::BASE_BPS
+        } else {
+            Account::<NftMultiplier>::try_from(&ctx.accounts.nft_multiplier.to_account_info())?
+                .multiplier_bps
+        };
+
         let clock = Clock::get().unwrap();
         msg!("Approving delegation...");
 
@@ -59,7 +75,167 @@ pub mod anchor_nft_staking {
         ctx.accounts.stake_state.stake_state = StakeState::Staked;
         ctx.accounts.stake_state.stake_start_time = clock.unix_timestamp;
         ctx.accounts.stake_state.last_stake_redeem = clock.unix_timestamp;
+        ctx.accounts.stake_state.multiplier_bps = multiplier_bps;
+        ctx.accounts.stake_state.lock_tier = lock_tier;
         ctx.accounts.stake_state.is_initialized = true;
+        msg!(
+            "Staked with {:?} lock until {}",
+            lock_tier,
+            ctx.accounts.stake_state.lock_expires_at()
+        );
+
+        Ok(())
+    }
+
+    pub fn stake_many<'info>(
+        ctx: Context<'_, '_, '_, 'info, StakeMany<'info>>,
+        lock_tier: LockTier,
+    ) -> Result<()> {
+        let tuples = batch::nft_tuples(ctx.remaining_accounts, batch::STAKE_TUPLE_LEN)?;
+        msg!("Staking {} NFTs...", tuples.len());
+
+        let clock = Clock::get()?;
+        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
+        for nft in tuples {
+            batch::stake_nft(
+                ctx.accounts,
+                ctx.program_id,
+                nft,
+                lock_tier,
+                clock.unix_timestamp,
+                authority_bump,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unstake_many<'info>(ctx: Context<'_, '_, '_, 'info, UnstakeMany<'info>>) -> Result<()> {
+        let tuples = batch::nft_tuples(ctx.remaining_accounts, batch::UNSTAKE_TUPLE_LEN)?;
+        msg!("Unstaking {} NFTs...", tuples.len());
+
+        let clock = Clock::get()?;
+        let authority_bump = *ctx.bumps.get("program_authority").unwrap();
+        let mut redeem_amount: u64 = 0;
+        for nft in tuples {
+            let reward = batch::unstake_nft(
+                ctx.accounts,
+                ctx.program_id,
+                nft,
+                clock.unix_timestamp,
+                authority_bump,
+            )?;
+            redeem_amount = redeem_amount
+                .checked_add(reward)
+                .ok_or(StakeError::RewardOverflow)?;
+        }
+        msg!("Eligible redeem amount: {}", redeem_amount);
+
+        msg!("Minting staking rewards...");
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.stake_mint.to_account_info(),
+                    to: ctx.accounts.user_stake_ata.to_account_info(),
+                    authority: ctx.accounts.stake_authority.to_account_info(),
+                },
+                &[&[b"mint", &[*ctx.bumps.get("stake_authority").unwrap()]]],
+            ),
+            redeem_amount,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn redeem_all<'info>(ctx: Context<'_, '_, '_, 'info, RedeemAll<'info>>) -> Result<()> {
+        let tuples = batch::nft_tuples(ctx.remaining_accounts, batch::REDEEM_TUPLE_LEN)?;
+        msg!("Redeeming rewards for {} NFTs...", tuples.len());
+
+        let clock = Clock::get()?;
+        let mut redeem_amount: u64 = 0;
+        for nft in tuples {
+            let reward =
+                batch::redeem_nft(ctx.accounts, ctx.program_id, nft, clock.unix_timestamp)?;
+            redeem_amount = redeem_amount
+                .checked_add(reward)
+                .ok_or(StakeError::RewardOverflow)?;
+        }
+        msg!("Eligible redeem amount: {}", redeem_amount);
+
+        msg!("Minting staking rewards...");
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.stake_mint.to_account_info(),
+                    to: ctx.accounts.user_stake_ata.to_account_info(),
+                    authority: ctx.accounts.stake_authority.to_account_info(),
+                },
+                &[&[b"mint", &[*ctx.bumps.get("stake_authority").unwrap()]]],
+            ),
+            redeem_amount,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn initialize_stake_pool(
+        ctx: Context<InitializeStakePool>,
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+        rate_per_second: u64,
+        decimals: u8,
+        early_unstake: EarlyUnstake,
+    ) -> Result<()> {
+        StakePool::check_settings(&creators, early_unstake)?;
+
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.authority = ctx.accounts.authority.key();
+        stake_pool.collection = collection;
+        stake_pool.creators = creators;
+        stake_pool.rate_per_second = rate_per_second;
+        stake_pool.decimals = decimals;
+        stake_pool.early_unstake = early_unstake;
+        msg!("Staking restricted to collection {}", collection);
+
+        Ok(())
+    }
+
+    /// Rate changes apply to rewards not yet redeemed, so they also reprice
+    /// the time NFTs have already been staked.
+    pub fn update_stake_pool(
+        ctx: Context<UpdateStakePool>,
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+        rate_per_second: u64,
+        decimals: u8,
+        early_unstake: EarlyUnstake,
+    ) -> Result<()> {
+        StakePool::check_settings(&creators, early_unstake)?;
+
+        let stake_pool = &mut ctx.accounts.stake_pool;
+        stake_pool.collection = collection;
+        stake_pool.creators = creators;
+        stake_pool.rate_per_second = rate_per_second;
+        stake_pool.decimals = decimals;
+        stake_pool.early_unstake = early_unstake;
+        msg!("Staking restricted to collection {}", collection);
+
+        Ok(())
+    }
+
+    pub fn set_nft_multiplier(ctx: Context<SetNftMultiplier>, multiplier_bps: u16) -> Result<()> {
+        require!(multiplier_bps > 0, StakeError::InvalidMultiplier);
+
+        let nft_multiplier = &mut ctx.accounts.nft_multiplier;
+        nft_multiplier.mint = ctx.accounts.nft_mint.key();
+        nft_multiplier.multiplier_bps = multiplier_bps;
+        msg!(
+            "Multiplier for {} set to {} bps",
+            nft_multiplier.mint,
+            multiplier_bps
+        );
 
         Ok(())
     }
@@ -82,9 +258,10 @@ pub mod anchor_nft_staking {
         );
 
         msg!("Current time: {:?}", clock.unix_timestamp);
-        let unix_time = clock.unix_timestamp - ctx.accounts.stake_state.last_stake_redeem;
-        msg!("Seconds since last redeem: {}", unix_time);
-        let redeem_amount = (10 * i64::pow(10, 2) * unix_time) / (24 * 60 * 60);
+        let redeem_amount = ctx
+            .accounts
+            .stake_pool
+            .claimable_reward(&ctx.accounts.stake_state, clock.unix_timestamp)?;
         msg!("Eligible redeem amount: {}", redeem_amount);
 
         msg!("Minting staking rewards...");
@@ -98,7 +275,7 @@ pub mod anchor_nft_staking {
                 },
                 &[&[b"mint", &[*ctx.bumps.get("stake_authority").unwrap()]]],
             ),
-            redeem_amount.try_into().unwrap(),
+            redeem_amount,
         )?;
 
         ctx.accounts.stake_state.last_stake_redeem = clock.unix_timestamp;
@@ -158,9 +335,10 @@ pub mod anchor_nft_staking {
         );
 
         msg!("Current time: {:?}", clock.unix_timestamp);
-        let unix_time = clock.unix_timestamp - ctx.accounts.stake_state.last_stake_redeem;
-        msg!("Seconds since last redeem: {}", unix_time);
-        let redeem_amount = (10 * i64::pow(10, 2) * unix_time) / (24 * 60 * 60);
+        let redeem_amount = ctx
+            .accounts
+            .stake_pool
+            .claimable_reward(&ctx.accounts.stake_state, clock.unix_timestamp)?;
         msg!("Eligible redeem amount: {}", redeem_amount);
 
         msg!("Minting staking rewards...");
@@ -174,7 +352,7 @@ pub mod anchor_nft_staking {
                 },
                 &[&[b"mint", &[*ctx.bumps.get("stake_authority").unwrap()]]],
             ),
-            redeem_amount.try_into().unwrap(),
+            redeem_amount,
         )?;
 
         ctx.accounts.stake_state.last_stake_redeem = clock.unix_timestamp;
@@ -218,6 +396,142 @@ pub struct Stake<'info> {
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub metadata_program: Program<'info, Metadata>,
+    /// CHECK: Derived from the NFT mint, deserialized in the handler
+    #[account(
+        seeds = [b"metadata", MetadataTokenId.as_ref(), nft_mint.key().as_ref()],
+        seeds::program = MetadataTokenId,
+        bump
+    )]
+    pub nft_metadata: UncheckedAccount<'info>,
+    #[account(seeds = [b"stake_pool"], bump)]
+    pub stake_pool: Account<'info, StakePool>,
+    /// CHECK: Derived from the NFT mint and may not exist yet; required so a
+    /// multiplier below the base rate cannot be skipped
+    #[account(seeds = [b"multiplier", nft_mint.key().as_ref()], bump)]
+    pub nft_multiplier: UncheckedAccount<'info>,
+}
+
+/// Each NFT follows as a `batch::STAKE_TUPLE_LEN` tuple of remaining accounts.
+#[derive(Accounts)]
+pub struct StakeMany<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Manual validation
+    #[account(seeds = [b"authority"], bump)]
+    pub program_authority: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub metadata_program: Program<'info, Metadata>,
+    #[account(seeds = [b"stake_pool"], bump)]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+/// Each NFT follows as a `batch::UNSTAKE_TUPLE_LEN` tuple of remaining accounts.
+#[derive(Accounts)]
+pub struct UnstakeMany<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Manual validation
+    #[account(seeds = [b"authority"], bump)]
+    pub program_authority: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub stake_mint: Account<'info, Mint>,
+    /// CHECK: Manual validation
+    #[account(seeds = [b"mint"], bump)]
+    pub stake_authority: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = stake_mint,
+        associated_token::authority = user
+    )]
+    pub user_stake_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub metadata_program: Program<'info, Metadata>,
+    #[account(
+        seeds = [b"stake_pool"],
+        bump,
+        constraint = stake_mint.decimals == stake_pool.decimals @ StakeError::MintDecimalsMismatch
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+/// Each NFT follows as a `batch::REDEEM_TUPLE_LEN` tuple of remaining accounts.
+#[derive(Accounts)]
+pub struct RedeemAll<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub stake_mint: Account<'info, Mint>,
+    /// CHECK: Manual validation
+    #[account(seeds = [b"mint"], bump)]
+    pub stake_authority: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = stake_mint,
+        associated_token::authority = user
+    )]
+    pub user_stake_ata: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"stake_pool"],
+        bump,
+        constraint = stake_mint.decimals == stake_pool.decimals @ StakeError::MintDecimalsMismatch
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeStakePool<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 32 + 4 + 32 * StakePool::MAX_CREATORS + 8 + 1 + 3,
+        seeds = [b"stake_pool"],
+        bump
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+    /// Only the program's upgrade authority may create the pool.
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::AnchorNftStaking>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ StakeError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStakePool<'info> {
+    #[account(mut, seeds = [b"stake_pool"], bump, has_one = authority @ StakeError::Unauthorized)]
+    pub stake_pool: Account<'info, StakePool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetNftMultiplier<'info> {
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 32 + 2,
+        seeds = [b"multiplier", nft_mint.key().as_ref()],
+        bump
+    )]
+    pub nft_multiplier: Account<'info, NftMultiplier>,
+    pub nft_mint: Account<'info, Mint>,
+    #[account(seeds = [b"stake_pool"], bump, has_one = authority @ StakeError::Unauthorized)]
+    pub stake_pool: Account<'info, StakePool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -240,7 +554,7 @@ pub struct Redeem<'info> {
     #[account(mut)]
     pub stake_mint: Account<'info, Mint>,
     /// CHECK: Manual validation
-    #[account(mut, seeds=["authority".as_bytes().as_ref()], bump)]
+    #[account(seeds = ["mint".as_bytes().as_ref()], bump)]
     pub stake_authority: UncheckedAccount<'info>,
     #[account(
 			init_if_needed,
@@ -253,6 +567,12 @@ pub struct Redeem<'info> {
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
+    #[account(
+        seeds = [b"stake_pool"],
+        bump,
+        constraint = stake_mint.decimals == stake_pool.decimals @ StakeError::MintDecimalsMismatch
+    )]
+    pub stake_pool: Account<'info, StakePool>,
 }
 
 #[derive(Accounts)]
@@ -296,6 +616,12 @@ pub struct Unstake<'info> {
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
     pub metadata_program: Program<'info, Metadata>,
+    #[account(
+        seeds = [b"stake_pool"],
+        bump,
+        constraint = stake_mint.decimals == stake_pool.decimals @ StakeError::MintDecimalsMismatch
+    )]
+    pub stake_pool: Account<'info, StakePool>,
 }
 
 #[account]
@@ -307,6 +633,188 @@ pub struct UserStakeInfo {
     pub user_pubkey: Pubkey,
     pub stake_state: StakeState,
     pub is_initialized: bool,
+    /// Per-NFT reward multiplier in basis points, copied from the NFT's
+    /// `NftMultiplier` when it is staked. Zero means no multiplier.
+    pub multiplier_bps: u16,
+    /// Lock chosen when the NFT was staked, counted from `stake_start_time`.
+    pub lock_tier: LockTier,
+}
+
+impl UserStakeInfo {
+    pub fn lock_expires_at(&self) -> i64 {
+        self.stake_start_time
+            .saturating_add(self.lock_tier.duration_seconds())
+    }
+
+    pub fn multiplier_bps(&self) -> u16 {
+        if self.multiplier_bps == 0 {
+            NftMultiplier::BASE_BPS
+        } else {
+            self.multiplier_bps
+        }
+    }
+}
+
+/// Pins which NFTs may be staked.
+#[account]
+pub struct StakePool {
+    pub authority: Pubkey,
+    /// Verified Metaplex collection every staked NFT must belong to.
+    pub collection: Pubkey,
+    /// When not empty, staked NFTs must also have one of these as a verified
+    /// creator.
+    pub creators: Vec<Pubkey>,
+    /// Whole reward tokens earned per staked NFT per second, scaled by
+    /// `rewards::RATE_SCALE`.
+    pub rate_per_second: u64,
+    /// Decimals of the reward mint.
+    pub decimals: u8,
+    /// What happens when an NFT is redeemed or unstaked before its lock
+    /// expires.
+    pub early_unstake: EarlyUnstake,
+}
+
+impl StakePool {
+    /// Metaplex allows at most 5 creators per NFT.
+    pub const MAX_CREATORS: usize = 5;
+
+    /// Validates settings before they are written by `initialize_stake_pool`
+    /// or `update_stake_pool`.
+    pub fn check_settings(creators: &[Pubkey], early_unstake: EarlyUnstake) -> Result<()> {
+        require!(
+            creators.len() <= StakePool::MAX_CREATORS,
+            StakeError::TooManyCreators
+        );
+        if let EarlyUnstake::Forfeit { penalty_bps } = early_unstake {
+            require!(
+                u128::from(penalty_bps) <= rewards::BPS_DENOMINATOR,
+                StakeError::InvalidPenalty
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn check_nft(&self, metadata: &MetadataAccount) -> Result<()> {
+        require!(
+            matches!(
+                &metadata.collection,
+                Some(collection) if collection.verified && collection.key == self.collection
+            ),
+            StakeError::NftNotInCollection
+        );
+
+        if !self.creators.is_empty() {
+            require!(
+                metadata
+                    .data
+                    .creators
+                    .iter()
+                    .flatten()
+                    .any(|creator| creator.verified && self.creators.contains(&creator.address)),
+                StakeError::CreatorNotAllowed
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Tokens accrued by `stake_info` since its last redeem, before any
+    /// early-unstake penalty.
+    pub fn pending_reward(&self, stake_info: &UserStakeInfo, now: i64) -> Result<u64> {
+        rewards::calculate_reward(
+            self.rate_per_second,
+            self.decimals,
+            rewards::combined_multiplier_bps(
+                stake_info.multiplier_bps(),
+                stake_info.lock_tier.boost_bps(),
+            ),
+            stake_info.last_stake_redeem,
+            now,
+        )
+    }
+
+    /// Tokens paid by redeeming or unstaking. Both follow the pool's
+    /// early-unstake policy until the lock expires, so redeeming along the way
+    /// cannot collect the rewards an early unstake would forfeit.
+    pub fn claimable_reward(&self, stake_info: &UserStakeInfo, now: i64) -> Result<u64> {
+        let redeem_amount = self.pending_reward(stake_info, now)?;
+        let lock_expires_at = stake_info.lock_expires_at();
+        if now >= lock_expires_at {
+            return Ok(redeem_amount);
+        }
+
+        match self.early_unstake {
+            EarlyUnstake::Reject => {
+                msg!("NFT is locked until {}", lock_expires_at);
+                err!(StakeError::StakeLocked)
+            }
+            EarlyUnstake::Forfeit { penalty_bps } => {
+                msg!(
+                    "Claiming before {} forfeits {} bps of rewards",
+                    lock_expires_at,
+                    penalty_bps
+                );
+                rewards::apply_penalty(redeem_amount, penalty_bps)
+            }
+        }
+    }
+}
+
+/// Reward multiplier for a single NFT. Attributes live in the off-chain
+/// metadata JSON, so the pool authority publishes the multiplier each NFT's
+/// attributes earn.
+#[account]
+pub struct NftMultiplier {
+    pub mint: Pubkey,
+    pub multiplier_bps: u16,
+}
+
+impl NftMultiplier {
+    /// Multiplier for NFTs without an `NftMultiplier` account.
+    pub const BASE_BPS: u16 = 10_000;
+}
+
+/// Minimum staking period chosen at `stake`; longer locks earn a boosted rate.
+#[derive(Debug, Default, PartialEq, AnchorDeserialize, AnchorSerialize, Clone, Copy)]
+pub enum LockTier {
+    #[default]
+    None,
+    ThirtyDays,
+    NinetyDays,
+    OneEightyDays,
+}
+
+impl LockTier {
+    pub fn duration_seconds(self) -> i64 {
+        const DAY: i64 = 24 * 60 * 60;
+        match self {
+            LockTier::None => 0,
+            LockTier::ThirtyDays => 30 * DAY,
+            LockTier::NinetyDays => 90 * DAY,
+            LockTier::OneEightyDays => 180 * DAY,
+        }
+    }
+
+    /// Reward boost in basis points, applied on top of the NFT's multiplier.
+    pub fn boost_bps(self) -> u16 {
+        match self {
+            LockTier::None => 10_000,
+            LockTier::ThirtyDays => 12_500,
+            LockTier::NinetyDays => 15_000,
+            LockTier::OneEightyDays => 20_000,
+        }
+    }
+}
+
+/// Pool policy for redeeming or unstaking before a lock expires.
+#[derive(Debug, PartialEq, AnchorDeserialize, AnchorSerialize, Clone, Copy)]
+pub enum EarlyUnstake {
+    Reject,
+    /// Both are allowed but forfeit this share of the pending reward.
+    Forfeit {
+        penalty_bps: u16,
+    },
 }
 
 #[derive(Debug, PartialEq, AnchorDeserialize, AnchorSerialize, Clone)]
@@ -338,4 +846,100 @@ pub enum StakeError {
     UninitializedAccount,
     #[msg("Stake state is invalid")]
     InvalidStakeState,
+    #[msg("NFT is not a verified member of the pool's collection")]
+    NftNotInCollection,
+    #[msg("NFT has no verified creator allowed by the pool")]
+    CreatorNotAllowed,
+    #[msg("Stake pool allows too many creators")]
+    TooManyCreators,
+    #[msg("Reward calculation overflowed")]
+    RewardOverflow,
+    #[msg("Multiplier must be greater than zero")]
+    InvalidMultiplier,
+    #[msg("Signer is not the stake pool authority")]
+    Unauthorized,
+    #[msg("Stake mint decimals do not match the stake pool")]
+    MintDecimalsMismatch,
+    #[msg("Penalty cannot exceed 100%")]
+    InvalidPenalty,
+    #[msg("NFT is still locked")]
+    StakeLocked,
+    #[msg("Batch accounts must be whole per-NFT tuples")]
+    InvalidBatchAccounts,
+    #[msg("Too many NFTs in one batch")]
+    BatchTooLarge,
+    #[msg("NFT accounts in the batch do not match")]
+    InvalidNftAccounts,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_with(early_unstake: EarlyUnstake) -> StakePool {
+        StakePool {
+            authority: Pubkey::new_unique(),
+            collection: Pubkey::new_unique(),
+            creators: vec![],
+            // One whole token per second.
+            rate_per_second: rewards::RATE_SCALE as u64,
+            decimals: 0,
+            early_unstake,
+        }
+    }
+
+    fn locked_stake() -> UserStakeInfo {
+        UserStakeInfo {
+            stake_state: StakeState::Staked,
+            is_initialized: true,
+            lock_tier: LockTier::ThirtyDays,
+            ..UserStakeInfo::default()
+        }
+    }
+
+    // Mirrors `redeem` and `unstake` without the accounts.
+    fn claim(pool: &StakePool, stake: &mut UserStakeInfo, now: i64) -> Result<u64> {
+        let amount = pool.claimable_reward(stake, now)?;
+        stake.last_stake_redeem = now;
+        Ok(amount)
+    }
+
+    #[test]
+    fn redeeming_before_unstaking_early_still_forfeits() {
+        let pool = pool_with(EarlyUnstake::Forfeit { penalty_bps: 5_000 });
+        let mut stake = locked_stake();
+
+        // The 30 day lock boosts 100 seconds to 125 tokens, half forfeited.
+        let redeemed = claim(&pool, &mut stake, 100).unwrap();
+        assert_eq!(redeemed, 63);
+        let unstaked = claim(&pool, &mut stake, 300).unwrap();
+        assert_eq!(unstaked, 125);
+
+        // Same as unstaking early in one go.
+        assert_eq!(
+            redeemed + unstaked,
+            claim(&pool, &mut locked_stake(), 300).unwrap()
+        );
+
+        let expires_at = stake.lock_expires_at();
+        stake.last_stake_redeem = expires_at - 100;
+        assert_eq!(claim(&pool, &mut stake, expires_at).unwrap(), 125);
+    }
+
+    #[test]
+    fn redeeming_a_locked_nft_is_rejected() {
+        let pool = pool_with(EarlyUnstake::Reject);
+        let mut stake = locked_stake();
+        let expires_at = stake.lock_expires_at();
+
+        assert_eq!(
+            claim(&pool, &mut stake, expires_at - 1).unwrap_err(),
+            StakeError::StakeLocked.into()
+        );
+        assert_eq!(stake.last_stake_redeem, 0);
+        assert_eq!(
+            claim(&pool, &mut stake, expires_at).unwrap(),
+            expires_at as u64 * 5 / 4
+        );
+    }
 }
@@ -0,0 +1,155 @@
+use crate::StakeError;
+use anchor_lang::prelude::*;
+
+/// `StakePool::rate_per_second` is in whole tokens scaled by this factor, so
+/// rates below one base unit per second can still be expressed.
+pub const RATE_SCALE: u128 = 1_000_000_000;
+
+/// Multipliers are in basis points; 10_000 earns the pool's base rate.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Combines an NFT's multiplier with its lock boost, both in basis points.
+pub fn combined_multiplier_bps(nft_multiplier_bps: u16, lock_boost_bps: u16) -> u32 {
+    // At most u16::MAX * u16::MAX / 10_000, which fits comfortably in a u32.
+    (u32::from(nft_multiplier_bps) * u32::from(lock_boost_bps)) / BPS_DENOMINATOR as u32
+}
+
+/// What remains of `amount` after forfeiting `penalty_bps` of it.
+pub fn apply_penalty(amount: u64, penalty_bps: u16) -> Result<u64> {
+    require!(
+        u128::from(penalty_bps) <= BPS_DENOMINATOR,
+        StakeError::InvalidPenalty
+    );
+    // Cannot exceed `amount` now that the penalty is at most 100%.
+    let forfeited = u128::from(amount) * u128::from(penalty_bps) / BPS_DENOMINATOR;
+    Ok(amount - forfeited as u64)
+}
+
+/// Seconds earned between `last_redeem` and `now`. A clock that reads earlier
+/// than the last redeem earns nothing instead of a negative amount.
+pub fn elapsed_seconds(last_redeem: i64, now: i64) -> u64 {
+    let elapsed = i128::from(now) - i128::from(last_redeem);
+    u64::try_from(elapsed).unwrap_or(0)
+}
+
+/// Reward, in base units of a mint with `decimals`, for one NFT staked from
+/// `last_redeem` until `now`, before any early-unstake penalty.
+pub fn calculate_reward(
+    rate_per_second: u64,
+    decimals: u8,
+    multiplier_bps: u32,
+    last_redeem: i64,
+    now: i64,
+) -> Result<u64> {
+    let elapsed = elapsed_seconds(last_redeem, now) as u128;
+    let one_token = 10u128
+        .checked_pow(decimals.into())
+        .ok_or(StakeError::RewardOverflow)?;
+
+    let amount = elapsed
+        .checked_mul(rate_per_second.into())
+        .and_then(|amount| amount.checked_mul(one_token))
+        .and_then(|amount| amount.checked_mul(multiplier_bps.into()))
+        .ok_or(StakeError::RewardOverflow)?
+        / (RATE_SCALE * BPS_DENOMINATOR);
+
+    Ok(u64::try_from(amount).map_err(|_| StakeError::RewardOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    // Same vectors as the nft-staking program's rewards tests; keep both in
+    // step so the two programs keep paying the same rewards.
+    use super::*;
+
+    const DAY: i64 = 86_400;
+    // 10 tokens per day, the rate this program paid before it was configurable.
+    const RATE: u64 = (10 * RATE_SCALE / DAY as u128) as u64;
+
+    #[test]
+    fn negative_clock_delta_earns_nothing() {
+        assert_eq!(elapsed_seconds(1_000, 999), 0);
+        assert_eq!(calculate_reward(RATE, 2, 10_000, 1_000, 0).unwrap(), 0);
+        assert_eq!(
+            calculate_reward(RATE, 2, 10_000, i64::MAX, i64::MIN).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn same_second_earns_nothing() {
+        assert_eq!(calculate_reward(RATE, 2, 10_000, 500, 500).unwrap(), 0);
+    }
+
+    #[test]
+    fn one_day_at_base_rate() {
+        // The per-second rate rounds down, so a day pays just under 10 tokens.
+        assert_eq!(calculate_reward(RATE, 2, 10_000, 0, DAY).unwrap(), 999);
+        assert_eq!(calculate_reward(RATE, 2, 20_000, 0, DAY).unwrap(), 1_999);
+        assert_eq!(
+            calculate_reward(RATE_SCALE as u64, 0, 10_000, 0, DAY).unwrap(),
+            86_400
+        );
+    }
+
+    #[test]
+    fn multiplier_scales_reward() {
+        let base = calculate_reward(RATE_SCALE as u64, 2, 10_000, 0, 100).unwrap();
+        let boosted = calculate_reward(RATE_SCALE as u64, 2, 15_000, 0, 100).unwrap();
+        let reduced = calculate_reward(RATE_SCALE as u64, 2, 5_000, 0, 100).unwrap();
+        assert_eq!(base, 10_000);
+        assert_eq!(boosted, 15_000);
+        assert_eq!(reduced, 5_000);
+        assert_eq!(calculate_reward(RATE, 2, 0, 0, DAY).unwrap(), 0);
+    }
+
+    #[test]
+    fn lock_boost_stacks_with_nft_multiplier() {
+        assert_eq!(combined_multiplier_bps(10_000, 10_000), 10_000);
+        assert_eq!(combined_multiplier_bps(15_000, 20_000), 30_000);
+        assert_eq!(combined_multiplier_bps(5_000, 12_500), 6_250);
+        assert_eq!(
+            combined_multiplier_bps(u16::MAX, u16::MAX),
+            u32::from(u16::MAX) * u32::from(u16::MAX) / 10_000
+        );
+    }
+
+    #[test]
+    fn penalty_forfeits_a_share_of_the_reward() {
+        assert_eq!(apply_penalty(1_000, 0).unwrap(), 1_000);
+        assert_eq!(apply_penalty(1_000, 2_500).unwrap(), 750);
+        assert_eq!(apply_penalty(999, 5_000).unwrap(), 500);
+        assert_eq!(apply_penalty(1_000, 10_000).unwrap(), 0);
+        assert_eq!(apply_penalty(u64::MAX, 10_000).unwrap(), 0);
+        assert_eq!(
+            apply_penalty(1_000, 10_001).unwrap_err(),
+            StakeError::InvalidPenalty.into()
+        );
+    }
+
+    #[test]
+    fn locked_rewards_are_boosted_and_penalized() {
+        let boosted = combined_multiplier_bps(10_000, 15_000);
+        let reward = calculate_reward(RATE, 2, boosted, 0, DAY).unwrap();
+        assert_eq!(reward, 1_499);
+        assert_eq!(apply_penalty(reward, 2_500).unwrap(), 1_125);
+        assert_eq!(apply_penalty(reward, 10_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn elapsed_across_the_whole_clock_range() {
+        assert_eq!(elapsed_seconds(i64::MIN, i64::MAX), u64::MAX);
+        assert_eq!(elapsed_seconds(-10, 10), 20);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        for result in [
+            calculate_reward(u64::MAX, 0, u32::MAX, 0, i64::MAX),
+            calculate_reward(RATE, 40, 10_000, 0, DAY),
+            calculate_reward(u64::MAX, 19, 10_000, 0, DAY),
+        ] {
+            assert_eq!(result.unwrap_err(), StakeError::RewardOverflow.into());
+        }
+    }
+}
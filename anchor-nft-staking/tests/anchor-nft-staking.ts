@@ -18,24 +18,166 @@ describe("anchor-nft-staking", () => {
 	let delegatedAuthPda: anchor.web3.PublicKey;
 	let stakeStatePda: anchor.web3.PublicKey;
 	let nft: any;
+	let batchNfts: any[];
+	let unverifiedNft: any;
+	let collectionMint: anchor.web3.PublicKey;
 	let mintAuth: anchor.web3.PublicKey;
 	let mint: anchor.web3.PublicKey;
 	let tokenAddress: anchor.web3.PublicKey;
 
 	before(async () => {
-		({ nft, delegatedAuthPda, stakeStatePda, mint, mintAuth, tokenAddress } =
-			await setupNft(program, wallet.payer));
+		({
+			nft,
+			batchNfts,
+			unverifiedNft,
+			collectionMint,
+			delegatedAuthPda,
+			stakeStatePda,
+			mint,
+			mintAuth,
+			tokenAddress,
+		} = await setupNft(program, wallet.payer));
+	});
+
+	// One token per second, scaled by the program's RATE_SCALE of 1e9, so
+	// every test earns a reward.
+	const rateScale = new anchor.BN(1_000_000_000);
+	const ratePerSecond = rateScale;
+	const decimals = 2;
+	const earlyUnstake = { forfeit: { penaltyBps: 5_000 } };
+
+	// Base units earned between two redeems by an unlocked NFT without a
+	// multiplier, as `rewards::calculate_reward` computes them.
+	const reward = (from: anchor.BN, to: anchor.BN) =>
+		to
+			.sub(from)
+			.mul(ratePerSecond)
+			.mul(new anchor.BN(10).pow(new anchor.BN(decimals)))
+			.div(rateScale);
+
+	const balance = async () => {
+		try {
+			const account = await getAccount(provider.connection, tokenAddress);
+			return new anchor.BN(account.amount.toString());
+		} catch {
+			// The first redeem creates the token account.
+			return new anchor.BN(0);
+		}
+	};
+
+	const stakeStateOf = (stakedNft: any) =>
+		anchor.web3.PublicKey.findProgramAddressSync(
+			[wallet.publicKey.toBuffer(), stakedNft.tokenAddress.toBuffer()],
+			program.programId
+		)[0];
+
+	// Each freeze or thaw CPI costs tens of thousands of compute units.
+	const computeBudget = anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+		units: 400_000,
+	});
+
+	const stakeTuple = (stakedNft: any) => [
+		{ pubkey: stakedNft.tokenAddress, isSigner: false, isWritable: true },
+		{ pubkey: stakedNft.mintAddress, isSigner: false, isWritable: false },
+		{ pubkey: stakedNft.masterEditionAddress, isSigner: false, isWritable: false },
+		{ pubkey: stakeStateOf(stakedNft), isSigner: false, isWritable: true },
+		{ pubkey: stakedNft.metadataAddress, isSigner: false, isWritable: false },
+		{
+			pubkey: anchor.web3.PublicKey.findProgramAddressSync(
+				[Buffer.from("multiplier"), stakedNft.mintAddress.toBuffer()],
+				program.programId
+			)[0],
+			isSigner: false,
+			isWritable: false,
+		},
+	];
+
+	const unstakeTuple = (stakedNft: any) => stakeTuple(stakedNft).slice(0, 4);
+
+	const remaining = (tuple: (stakedNft: any) => any[]) =>
+		([] as any[]).concat(...batchNfts.map(tuple));
+
+	const stakeAccounts = (stakedNft: any) => ({
+		nftTokenAccount: stakedNft.tokenAddress,
+		nftMint: stakedNft.mintAddress,
+		nftEdition: stakedNft.masterEditionAddress,
+		metadataProgram: METADATA_PROGRAM_ID,
+		nftMetadata: stakedNft.metadataAddress,
+	});
+
+	it("initializes the stake pool", async () => {
+		// The test validator deploys the program with the wallet as its
+		// upgrade authority.
+		const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+			[program.programId.toBuffer()],
+			new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+		);
+		await program.methods
+			.initializeStakePool(collectionMint, [], ratePerSecond, decimals, earlyUnstake)
+			.accounts({ program: program.programId, programData })
+			.rpc();
+	});
+
+	it("rejects pool updates from anyone but the pool authority", async () => {
+		const intruder = anchor.web3.Keypair.generate();
+		try {
+			await program.methods
+				.updateStakePool(collectionMint, [], ratePerSecond, decimals, earlyUnstake)
+				.accounts({ authority: intruder.publicKey })
+				.signers([intruder])
+				.rpc();
+			expect.fail("intruder should not update the pool");
+		} catch (err) {
+			expect(err.error.errorCode.code).to.equal("Unauthorized");
+		}
+	});
+
+	it("rejects an NFT whose collection is not verified", async () => {
+		try {
+			await program.methods
+				.stake({ none: {} })
+				.accounts(stakeAccounts(unverifiedNft))
+				.rpc();
+			expect.fail("unverified collection should be rejected");
+		} catch (err) {
+			expect(err.error.errorCode.code).to.equal("NftNotInCollection");
+		}
+	});
+
+	it("rejects an NFT without an allowed creator", async () => {
+		await program.methods
+			.updateStakePool(
+				collectionMint,
+				[anchor.web3.Keypair.generate().publicKey],
+				ratePerSecond,
+				decimals,
+				earlyUnstake
+			)
+			.rpc();
+
+		try {
+			await program.methods.stake({ none: {} }).accounts(stakeAccounts(nft)).rpc();
+			expect.fail("disallowed creator should be rejected");
+		} catch (err) {
+			expect(err.error.errorCode.code).to.equal("CreatorNotAllowed");
+		}
+
+		// Metaplex verifies the wallet as the NFT's creator, so allow it again.
+		await program.methods
+			.updateStakePool(
+				collectionMint,
+				[wallet.publicKey],
+				ratePerSecond,
+				decimals,
+				earlyUnstake
+			)
+			.rpc();
 	});
 
 	it("stake", async () => {
 		await program.methods
-			.stake()
-			.accounts({
-				nftTokenAccount: nft.tokenAddress,
-				nftMint: nft.mintAddress,
-				nftEdition: nft.masterEditionAddress,
-				metadataProgram: METADATA_PROGRAM_ID,
-			})
+			.stake({ none: {} })
+			.accounts(stakeAccounts(nft))
 			.rpc();
 
 		const account = await program.account.userStakeInfo.fetch(stakeStatePda);
@@ -43,6 +185,7 @@ describe("anchor-nft-staking", () => {
 	});
 
 	it("redeem", async () => {
+		const staked = await program.account.userStakeInfo.fetch(stakeStatePda);
 		await program.methods
 			.redeem()
 			.accounts({
@@ -54,7 +197,49 @@ describe("anchor-nft-staking", () => {
 
 		const account = await program.account.userStakeInfo.fetch(stakeStatePda);
 		expect(account.stakeState === "Unstaked");
-		const tokenAccount = await getAccount(provider.connection, tokenAddress);
+		const minted = reward(staked.lastStakeRedeem, account.lastStakeRedeem);
+		expect(minted.gtn(0)).to.be.true;
+		expect((await balance()).eq(minted)).to.be.true;
+	});
+
+	it("redeems all staked NFTs in one mint", async () => {
+		const before = await balance();
+		const staked = await program.account.userStakeInfo.fetch(stakeStatePda);
+		await program.methods
+			.redeemAll()
+			.accounts({
+				stakeMint: mint,
+				userStakeAta: tokenAddress,
+			})
+			.remainingAccounts([
+				{ pubkey: nft.tokenAddress, isSigner: false, isWritable: false },
+				{ pubkey: stakeStatePda, isSigner: false, isWritable: true },
+			])
+			.rpc();
+
+		const account = await program.account.userStakeInfo.fetch(stakeStatePda);
+		const minted = (await balance()).sub(before);
+		expect(
+			minted.eq(reward(staked.lastStakeRedeem, account.lastStakeRedeem))
+		).to.be.true;
+	});
+
+	it("rejects a batch with a partial NFT tuple", async () => {
+		try {
+			await program.methods
+				.redeemAll()
+				.accounts({
+					stakeMint: mint,
+					userStakeAta: tokenAddress,
+				})
+				.remainingAccounts([
+					{ pubkey: nft.tokenAddress, isSigner: false, isWritable: false },
+				])
+				.rpc();
+			expect.fail("partial tuple should be rejected");
+		} catch (err) {
+			expect(err.error.errorCode.code).to.equal("InvalidBatchAccounts");
+		}
 	});
 
 	it("unstake", async () => {
@@ -73,5 +258,89 @@ describe("anchor-nft-staking", () => {
 		const account = await program.account.userStakeInfo.fetch(stakeStatePda);
 		expect(account.stakeState === "Unstaked");
 	});
-});
 
+	it("stakes several NFTs in one call, creating their stake states", async () => {
+		for (const batchNft of batchNfts) {
+			const info = await provider.connection.getAccountInfo(
+				stakeStateOf(batchNft)
+			);
+			expect(info).to.be.null;
+		}
+
+		await program.methods
+			.stakeMany({ none: {} })
+			.accounts({ metadataProgram: METADATA_PROGRAM_ID })
+			.remainingAccounts(remaining(stakeTuple))
+			.preInstructions([computeBudget])
+			.rpc();
+
+		for (const batchNft of batchNfts) {
+			const account = await program.account.userStakeInfo.fetch(
+				stakeStateOf(batchNft)
+			);
+			expect(account.stakeState).to.deep.equal({ staked: {} });
+			expect(account.tokenAccount.equals(batchNft.tokenAddress)).to.be.true;
+			expect(account.userPubkey.equals(wallet.publicKey)).to.be.true;
+		}
+	});
+
+	it("rejects a batch whose NFT accounts do not match", async () => {
+		// The first NFT's token account with the second NFT's stake state.
+		try {
+			await program.methods
+				.redeemAll()
+				.accounts({
+					stakeMint: mint,
+					userStakeAta: tokenAddress,
+				})
+				.remainingAccounts([
+					{
+						pubkey: batchNfts[0].tokenAddress,
+						isSigner: false,
+						isWritable: false,
+					},
+					{
+						pubkey: stakeStateOf(batchNfts[1]),
+						isSigner: false,
+						isWritable: true,
+					},
+				])
+				.rpc();
+			expect.fail("mismatched tuple should be rejected");
+		} catch (err) {
+			expect(err.error.errorCode.code).to.equal("InvalidNftAccounts");
+		}
+	});
+
+	it("unstakes several NFTs in one call and mints their rewards at once", async () => {
+		const before = await balance();
+		const staked = await Promise.all(
+			batchNfts.map((batchNft) =>
+				program.account.userStakeInfo.fetch(stakeStateOf(batchNft))
+			)
+		);
+
+		await program.methods
+			.unstakeMany()
+			.accounts({
+				stakeMint: mint,
+				userStakeAta: tokenAddress,
+				metadataProgram: METADATA_PROGRAM_ID,
+			})
+			.remainingAccounts(remaining(unstakeTuple))
+			.preInstructions([computeBudget])
+			.rpc();
+
+		let expected = new anchor.BN(0);
+		for (const [i, batchNft] of batchNfts.entries()) {
+			const account = await program.account.userStakeInfo.fetch(
+				stakeStateOf(batchNft)
+			);
+			expect(account.stakeState).to.deep.equal({ unstaked: {} });
+			expected = expected.add(
+				reward(staked[i].lastStakeRedeem, account.lastStakeRedeem)
+			);
+		}
+		expect((await balance()).sub(before).eq(expected)).to.be.true;
+	});
+});
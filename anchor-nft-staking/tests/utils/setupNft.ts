@@ -15,12 +15,45 @@ export const setupNft = async (
 		.use(keypairIdentity(payer))
 		.use(bundlrStorage());
 
+	const { nft: collectionNft } = await metaplex.nfts().create({
+		uri: "",
+		name: "Test Collection",
+		sellerFeeBasisPoints: 0,
+		isCollection: true,
+	});
+
 	const nft = await metaplex.nfts().create({
 		uri: "",
 		name: "Test NFT",
 		sellerFeeBasisPoints: 0,
+		collection: collectionNft.address,
+		collectionAuthority: payer,
+	});
+
+	// More verified members of the collection, staked together in batches.
+	const batchNfts = [];
+	for (const name of ["Batch NFT 1", "Batch NFT 2"]) {
+		batchNfts.push(
+			await metaplex.nfts().create({
+				uri: "",
+				name,
+				sellerFeeBasisPoints: 0,
+				collection: collectionNft.address,
+				collectionAuthority: payer,
+			})
+		);
+	}
+
+	// Claims the collection without the collection authority verifying it.
+	const { nft: unverifiedNft } = await metaplex.nfts().create({
+		uri: "",
+		name: "Unverified NFT",
+		sellerFeeBasisPoints: 0,
+		collection: collectionNft.address,
 	});
 
+	console.log("Collection Mint Pubkey: ", collectionNft.address.toBase58());
+
 	console.log("NFT Metadata Pubkey: ", nft.metadataAddress.toBase58());
 	console.log("NFT Token Address: ", nft.tokenAddress.toBase58());
 	const [delegatedAuthPda] = await anchor.web3.PublicKey.findProgramAddress(
@@ -52,6 +85,9 @@ export const setupNft = async (
 
 	return {
 		nft: nft,
+		batchNfts: batchNfts,
+		unverifiedNft: unverifiedNft,
+		collectionMint: collectionNft.address,
 		delegatedAuthPda: delegatedAuthPda,
 		stakeStatePda: stakeStatePda,
 		mint: mint,
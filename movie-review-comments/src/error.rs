@@ -17,6 +17,33 @@ pub enum ReviewError {
 
     #[error("Accounts do not match")]
     IncorrectAccountError,
+
+    #[error("Signer is not the owner of this account")]
+    Unauthorized,
+
+    #[error("Comment has been deleted")]
+    CommentDeleted,
+
+    #[error("Instruction payload could not be deserialized")]
+    InvalidInstructionPayload,
+
+    #[error("Account data could not be deserialized")]
+    CorruptAccountData,
+
+    #[error("Voter has already upvoted this account")]
+    AlreadyVoted,
+
+    #[error("Authors cannot upvote their own reviews or comments")]
+    SelfVote,
+
+    #[error("Reward amount does not fit in a u64")]
+    RewardOverflow,
+
+    #[error("Max supply does not match the reward config")]
+    MaxSupplyMismatch,
+
+    #[error("Comment counter overflowed")]
+    CounterOverflow,
 }
 
 impl From<ReviewError> for ProgramError {
@@ -0,0 +1,27 @@
+use crate::state::RewardSchedule;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, msg, pubkey::Pubkey};
+
+/// Logged whenever the reward config is created or changed. The borsh-encoded
+/// event follows its name in the `Program data:` log line.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct RewardConfigUpdated {
+    pub admin: Pubkey,
+    pub daily_cap: u64,
+    pub schedule: RewardSchedule,
+}
+
+impl RewardConfigUpdated {
+    pub const NAME: &'static str = "RewardConfigUpdated";
+
+    pub fn emit(&self) {
+        msg!(
+            "{}: admin {}, daily cap {}, {:?}",
+            Self::NAME,
+            self.admin,
+            self.daily_cap,
+            self.schedule
+        );
+        sol_log_data(&[Self::NAME.as_bytes(), &self.try_to_vec().unwrap()]);
+    }
+}
@@ -1,6 +1,19 @@
-use borsh::BorshDeserialize;
-use solana_program::program_error::ProgramError;
+use crate::error::ReviewError;
+use crate::signer_seeds;
+use crate::state::RewardSchedule;
+use crate::title::title_hash;
+use borsh::{BorshDeserialize, BorshSerialize};
+use mpl_token_metadata::pda::find_metadata_account;
+use solana_program::{
+    bpf_loader_upgradeable,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_associated_token_account::get_associated_token_address;
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum MovieInstruction {
     AddMovieReview {
         title: String,
@@ -15,7 +28,32 @@ pub enum MovieInstruction {
     AddComment {
         comment: String,
     },
-    InitializeMint,
+    InitializeMint {
+        decimals: u8,
+        max_supply: u64,
+        name: String,
+        symbol: String,
+        uri: String,
+    },
+    ReplyToComment {
+        comment: String,
+    },
+    UpdateComment {
+        comment: String,
+    },
+    DeleteComment,
+    CloseMovieReview,
+    UpvoteReview,
+    UpvoteComment,
+    UpdateRewardConfig {
+        admin: Pubkey,
+        daily_cap: u64,
+        schedule: RewardSchedule,
+    },
+    InitializeRewardConfig {
+        max_supply: u64,
+        daily_cap: u64,
+    },
 }
 
 #[derive(BorshDeserialize)]
@@ -30,36 +68,758 @@ struct CommentPayload {
     comment: String,
 }
 
+#[derive(BorshDeserialize)]
+struct InitializeMintPayload {
+    decimals: u8,
+    max_supply: u64,
+    name: String,
+    symbol: String,
+    uri: String,
+}
+
+#[derive(BorshDeserialize)]
+struct RewardConfigPayload {
+    admin: Pubkey,
+    daily_cap: u64,
+    schedule: RewardSchedule,
+}
+
+#[derive(BorshDeserialize)]
+struct InitializeRewardConfigPayload {
+    max_supply: u64,
+    daily_cap: u64,
+}
+
+/// Instruction tags, the first byte of every instruction. Tags are part of the
+/// wire format: an existing tag is never renumbered or reused, new
+/// instructions take the next free value.
+pub mod tag {
+    pub const ADD_MOVIE_REVIEW: u8 = 0;
+    pub const UPDATE_MOVIE_REVIEW: u8 = 1;
+    pub const ADD_COMMENT: u8 = 2;
+    pub const INITIALIZE_MINT: u8 = 3;
+    pub const REPLY_TO_COMMENT: u8 = 4;
+    pub const UPDATE_COMMENT: u8 = 5;
+    pub const DELETE_COMMENT: u8 = 6;
+    pub const CLOSE_MOVIE_REVIEW: u8 = 7;
+    pub const UPVOTE_REVIEW: u8 = 8;
+    pub const UPVOTE_COMMENT: u8 = 9;
+    pub const UPDATE_REWARD_CONFIG: u8 = 10;
+    pub const INITIALIZE_REWARD_CONFIG: u8 = 11;
+}
+
 impl MovieInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (&variant, rest) = input
             .split_first()
             .ok_or(ProgramError::InvalidInstructionData)?;
         Ok(match variant {
-            0 => {
-                let payload = MovieReviewPayload::try_from_slice(rest).unwrap();
+            tag::ADD_MOVIE_REVIEW => {
+                let payload = MovieReviewPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
                 Self::AddMovieReview {
                     title: payload.title,
                     rating: payload.rating,
                     description: payload.description,
                 }
             }
-            1 => {
-                let payload = MovieReviewPayload::try_from_slice(rest).unwrap();
+            tag::UPDATE_MOVIE_REVIEW => {
+                let payload = MovieReviewPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
                 Self::UpdateMovieReview {
                     title: payload.title,
                     rating: payload.rating,
                     description: payload.description,
                 }
             }
-            2 => {
-                let payload = CommentPayload::try_from_slice(rest).unwrap();
+            tag::ADD_COMMENT => {
+                let payload = CommentPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
                 Self::AddComment {
                     comment: payload.comment,
                 }
             }
-            3 => Self::InitializeMint,
+            tag::INITIALIZE_MINT => {
+                let payload = InitializeMintPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::InitializeMint {
+                    decimals: payload.decimals,
+                    max_supply: payload.max_supply,
+                    name: payload.name,
+                    symbol: payload.symbol,
+                    uri: payload.uri,
+                }
+            }
+            tag::REPLY_TO_COMMENT => {
+                let payload = CommentPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::ReplyToComment {
+                    comment: payload.comment,
+                }
+            }
+            tag::UPDATE_COMMENT => {
+                let payload = CommentPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::UpdateComment {
+                    comment: payload.comment,
+                }
+            }
+            tag::DELETE_COMMENT => Self::DeleteComment,
+            tag::CLOSE_MOVIE_REVIEW => Self::CloseMovieReview,
+            tag::UPVOTE_REVIEW => Self::UpvoteReview,
+            tag::UPVOTE_COMMENT => Self::UpvoteComment,
+            tag::UPDATE_REWARD_CONFIG => {
+                let payload = RewardConfigPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::UpdateRewardConfig {
+                    admin: payload.admin,
+                    daily_cap: payload.daily_cap,
+                    schedule: payload.schedule,
+                }
+            }
+            tag::INITIALIZE_REWARD_CONFIG => {
+                let payload = InitializeRewardConfigPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::InitializeRewardConfig {
+                    max_supply: payload.max_supply,
+                    daily_cap: payload.daily_cap,
+                }
+            }
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::AddMovieReview {
+                title,
+                rating,
+                description,
+            } => {
+                buf.push(tag::ADD_MOVIE_REVIEW);
+                (title, rating, description).serialize(&mut buf).unwrap();
+            }
+            Self::UpdateMovieReview {
+                title,
+                rating,
+                description,
+            } => {
+                buf.push(tag::UPDATE_MOVIE_REVIEW);
+                (title, rating, description).serialize(&mut buf).unwrap();
+            }
+            Self::AddComment { comment } => {
+                buf.push(tag::ADD_COMMENT);
+                comment.serialize(&mut buf).unwrap();
+            }
+            Self::InitializeMint {
+                decimals,
+                max_supply,
+                name,
+                symbol,
+                uri,
+            } => {
+                buf.push(tag::INITIALIZE_MINT);
+                (decimals, max_supply, name, symbol, uri)
+                    .serialize(&mut buf)
+                    .unwrap();
+            }
+            Self::ReplyToComment { comment } => {
+                buf.push(tag::REPLY_TO_COMMENT);
+                comment.serialize(&mut buf).unwrap();
+            }
+            Self::UpdateComment { comment } => {
+                buf.push(tag::UPDATE_COMMENT);
+                comment.serialize(&mut buf).unwrap();
+            }
+            Self::DeleteComment => buf.push(tag::DELETE_COMMENT),
+            Self::CloseMovieReview => buf.push(tag::CLOSE_MOVIE_REVIEW),
+            Self::UpvoteReview => buf.push(tag::UPVOTE_REVIEW),
+            Self::UpvoteComment => buf.push(tag::UPVOTE_COMMENT),
+            Self::UpdateRewardConfig {
+                admin,
+                daily_cap,
+                schedule,
+            } => {
+                buf.push(tag::UPDATE_REWARD_CONFIG);
+                (admin, daily_cap, schedule).serialize(&mut buf).unwrap();
+            }
+            Self::InitializeRewardConfig {
+                max_supply,
+                daily_cap,
+            } => {
+                buf.push(tag::INITIALIZE_REWARD_CONFIG);
+                (max_supply, daily_cap).serialize(&mut buf).unwrap();
+            }
+        }
+        buf
+    }
+}
+
+pub fn find_review_address(program_id: &Pubkey, reviewer: &Pubkey, title: &str) -> Pubkey {
+    Pubkey::find_program_address(&[reviewer.as_ref(), title_hash(title).as_ref()], program_id).0
+}
+
+/// Addresses the review `reviewer` posted under `title` may live at, canonical
+/// one first. Reviews created before seeds used the title hash are seeded with
+/// the raw title, which only fits titles of up to 32 bytes; clients should take
+/// the first candidate that exists.
+pub fn find_review_address_candidates(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    title: &str,
+) -> Vec<Pubkey> {
+    let mut candidates = vec![find_review_address(program_id, reviewer, title)];
+    if let Some(seed) = signer_seeds::legacy_review_title(title) {
+        candidates.push(Pubkey::find_program_address(&[reviewer.as_ref(), seed], program_id).0);
+    }
+    candidates
+}
+
+pub fn find_comment_counter_address(program_id: &Pubkey, review: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[review.as_ref(), signer_seeds::COMMENT], program_id).0
+}
+
+pub fn find_comment_address(program_id: &Pubkey, review: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[review.as_ref(), &signer_seeds::comment_index(index)],
+        program_id,
+    )
+    .0
+}
+
+/// Addresses a comment at `index` under `review` may live at, canonical one
+/// first. Comments created by older `anchor-movie-review` deployments use the
+/// little-endian index; clients should take the first candidate that exists.
+/// Both encodings agree for some indexes, so there may be a single candidate.
+pub fn find_comment_address_candidates(
+    program_id: &Pubkey,
+    review: &Pubkey,
+    index: u64,
+) -> Vec<Pubkey> {
+    let mut candidates = vec![find_comment_address(program_id, review, index)];
+    let legacy = Pubkey::find_program_address(
+        &[review.as_ref(), &signer_seeds::legacy_comment_index(index)],
+        program_id,
+    )
+    .0;
+    if !candidates.contains(&legacy) {
+        candidates.push(legacy);
+    }
+    candidates
+}
+
+pub fn find_reply_counter_address(program_id: &Pubkey, parent: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[parent.as_ref(), signer_seeds::REPLY], program_id).0
+}
+
+pub fn find_stats_address(program_id: &Pubkey, title: &str) -> Pubkey {
+    Pubkey::find_program_address(
+        &[signer_seeds::STATS, title_hash(title).as_ref()],
+        program_id,
+    )
+    .0
+}
+
+pub fn find_vote_address(program_id: &Pubkey, target: &Pubkey, voter: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[signer_seeds::VOTE, target.as_ref(), voter.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+pub fn find_mint_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id).0
+}
+
+pub fn find_mint_authority_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id).0
+}
+
+pub fn find_reward_config_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[signer_seeds::REWARD_CONFIG], program_id).0
+}
+
+/// The ProgramData account of an upgradeable program, which records its
+/// upgrade authority.
+pub fn find_program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+pub fn find_reward_ledger_address(program_id: &Pubkey, user: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[signer_seeds::REWARD_LEDGER, user.as_ref()], program_id).0
+}
+
+/// Creates an `AddMovieReview` instruction. The reviewer's associated token
+/// account for the reward mint must already exist.
+pub fn add_movie_review_ix(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    title: &str,
+    rating: u8,
+    description: &str,
+) -> Instruction {
+    let review = find_review_address(program_id, reviewer, title);
+    let mint = find_mint_address(program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*reviewer, true),
+            AccountMeta::new(review, false),
+            AccountMeta::new(find_comment_counter_address(program_id, &review), false),
+            AccountMeta::new(find_stats_address(program_id, title), false),
+            AccountMeta::new(mint, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id), false),
+            AccountMeta::new(get_associated_token_address(reviewer, &mint), false),
+            AccountMeta::new_readonly(find_reward_config_address(program_id), false),
+            AccountMeta::new(find_reward_ledger_address(program_id, reviewer), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: MovieInstruction::AddMovieReview {
+            title: title.to_owned(),
+            rating,
+            description: description.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `UpdateMovieReview` instruction for the review `reviewer`
+/// previously posted under `title`.
+pub fn update_movie_review_ix(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    title: &str,
+    rating: u8,
+    description: &str,
+) -> Instruction {
+    update_movie_review_at_ix(
+        program_id,
+        reviewer,
+        &find_review_address(program_id, reviewer, title),
+        title,
+        rating,
+        description,
+    )
+}
+
+/// Like `update_movie_review_ix`, for a review at `review`, one of
+/// `find_review_address_candidates`.
+pub fn update_movie_review_at_ix(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    review: &Pubkey,
+    title: &str,
+    rating: u8,
+    description: &str,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*reviewer, true),
+            AccountMeta::new(*review, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(find_stats_address(program_id, title), false),
+        ],
+        data: MovieInstruction::UpdateMovieReview {
+            title: title.to_owned(),
+            rating,
+            description: description.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `AddComment` instruction. `comment_index` must be the current
+/// value of the review's comment counter.
+pub fn add_comment_ix(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    review: &Pubkey,
+    comment_index: u64,
+    comment: &str,
+) -> Instruction {
+    let mint = find_mint_address(program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new_readonly(*review, false),
+            AccountMeta::new(find_comment_counter_address(program_id, review), false),
+            AccountMeta::new(
+                find_comment_address(program_id, review, comment_index),
+                false,
+            ),
+            AccountMeta::new(mint, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id), false),
+            AccountMeta::new(get_associated_token_address(commenter, &mint), false),
+            AccountMeta::new_readonly(find_reward_config_address(program_id), false),
+            AccountMeta::new(find_reward_ledger_address(program_id, commenter), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: MovieInstruction::AddComment {
+            comment: comment.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `InitializeMint` instruction that initializes the program's
+/// reward mint and attaches its token metadata. `payer` must be the program's
+/// upgrade authority. If the reward config does not exist yet it is created
+/// with `max_supply`, in base units, no daily cap and `payer` as its admin,
+/// otherwise `max_supply` must match the config.
+pub fn initialize_mint_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    decimals: u8,
+    max_supply: u64,
+    name: &str,
+    symbol: &str,
+    uri: &str,
+) -> Instruction {
+    let mint = find_mint_address(program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(mint, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id), false),
+            AccountMeta::new(find_reward_config_address(program_id), false),
+            AccountMeta::new(find_metadata_account(&mint).0, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+            AccountMeta::new_readonly(mpl_token_metadata::ID, false),
+            AccountMeta::new_readonly(find_program_data_address(program_id), false),
+        ],
+        data: MovieInstruction::InitializeMint {
+            decimals,
+            max_supply,
+            name: name.to_owned(),
+            symbol: symbol.to_owned(),
+            uri: uri.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `ReplyToComment` instruction. `reply_index` must be the current
+/// value of the parent's reply counter, or 0 if it does not exist yet.
+pub fn reply_to_comment_ix(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    review: &Pubkey,
+    parent: &Pubkey,
+    reply_index: u64,
+    comment: &str,
+) -> Instruction {
+    let mint = find_mint_address(program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new_readonly(*review, false),
+            AccountMeta::new_readonly(*parent, false),
+            AccountMeta::new(find_reply_counter_address(program_id, parent), false),
+            AccountMeta::new(find_comment_address(program_id, parent, reply_index), false),
+            AccountMeta::new(mint, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id), false),
+            AccountMeta::new(get_associated_token_address(commenter, &mint), false),
+            AccountMeta::new_readonly(find_reward_config_address(program_id), false),
+            AccountMeta::new(find_reward_ledger_address(program_id, commenter), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: MovieInstruction::ReplyToComment {
+            comment: comment.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `UpdateComment` instruction for an existing comment or reply.
+pub fn update_comment_ix(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    comment_account: &Pubkey,
+    comment: &str,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new(*comment_account, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: MovieInstruction::UpdateComment {
+            comment: comment.to_owned(),
+        }
+        .pack(),
+    }
+}
+
+/// Creates a `DeleteComment` instruction for an existing comment or reply.
+pub fn delete_comment_ix(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    comment_account: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new(*comment_account, false),
+        ],
+        data: MovieInstruction::DeleteComment.pack(),
+    }
+}
+
+/// Creates a `CloseMovieReview` instruction for the review `reviewer`
+/// previously posted under `title`.
+pub fn close_movie_review_ix(program_id: &Pubkey, reviewer: &Pubkey, title: &str) -> Instruction {
+    close_movie_review_at_ix(
+        program_id,
+        reviewer,
+        &find_review_address(program_id, reviewer, title),
+        title,
+    )
+}
+
+/// Like `close_movie_review_ix`, for a review at `review`, one of
+/// `find_review_address_candidates`.
+pub fn close_movie_review_at_ix(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    review: &Pubkey,
+    title: &str,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*reviewer, true),
+            AccountMeta::new(*review, false),
+            AccountMeta::new(find_comment_counter_address(program_id, review), false),
+            AccountMeta::new(find_stats_address(program_id, title), false),
+        ],
+        data: MovieInstruction::CloseMovieReview.pack(),
+    }
+}
+
+fn upvote_ix(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    target: &Pubkey,
+    author: &Pubkey,
+    instruction: MovieInstruction,
+) -> Instruction {
+    let mint = find_mint_address(program_id);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(*target, false),
+            AccountMeta::new(find_vote_address(program_id, target, voter), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(mint, false),
+            AccountMeta::new_readonly(find_mint_authority_address(program_id), false),
+            AccountMeta::new(get_associated_token_address(author, &mint), false),
+            AccountMeta::new_readonly(find_reward_config_address(program_id), false),
+            AccountMeta::new(find_reward_ledger_address(program_id, author), false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: instruction.pack(),
+    }
+}
+
+/// Creates an `UpvoteReview` instruction. `reviewer` is the review's author,
+/// who is rewarded when the upvote crosses a threshold.
+pub fn upvote_review_ix(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    review: &Pubkey,
+    reviewer: &Pubkey,
+) -> Instruction {
+    upvote_ix(
+        program_id,
+        voter,
+        review,
+        reviewer,
+        MovieInstruction::UpvoteReview,
+    )
+}
+
+/// Creates an `UpvoteComment` instruction for a comment or reply.
+/// `commenter` is its author, who is rewarded when the upvote crosses a
+/// threshold.
+pub fn upvote_comment_ix(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    comment_account: &Pubkey,
+    commenter: &Pubkey,
+) -> Instruction {
+    upvote_ix(
+        program_id,
+        voter,
+        comment_account,
+        commenter,
+        MovieInstruction::UpvoteComment,
+    )
+}
+
+/// Creates an `UpdateRewardConfig` instruction. Must be signed by the current
+/// admin; `new_admin` takes over from it and may be the same key. `daily_cap`
+/// is in base units of the reward mint, while `schedule` is in whole tokens.
+pub fn update_reward_config_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: &Pubkey,
+    daily_cap: u64,
+    schedule: RewardSchedule,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(find_reward_config_address(program_id), false),
+        ],
+        data: MovieInstruction::UpdateRewardConfig {
+            admin: *new_admin,
+            daily_cap,
+            schedule,
+        }
+        .pack(),
+    }
+}
+
+/// Creates an `InitializeRewardConfig` instruction that creates the reward
+/// config with `admin`, who must be the program's upgrade authority, as its
+/// admin. `max_supply` and `daily_cap` are in base units of the reward mint.
+/// Deployments whose mint predates the config use this instead of
+/// `InitializeMint`.
+pub fn initialize_reward_config_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    max_supply: u64,
+    daily_cap: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(find_reward_config_address(program_id), false),
+            AccountMeta::new_readonly(find_program_data_address(program_id), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: MovieInstruction::InitializeRewardConfig {
+            max_supply,
+            daily_cap,
+        }
+        .pack(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    fn movie_instruction() -> impl Strategy<Value = MovieInstruction> {
+        prop_oneof![
+            (any::<String>(), any::<u8>(), any::<String>()).prop_map(
+                |(title, rating, description)| MovieInstruction::AddMovieReview {
+                    title,
+                    rating,
+                    description,
+                }
+            ),
+            (any::<String>(), any::<u8>(), any::<String>()).prop_map(
+                |(title, rating, description)| MovieInstruction::UpdateMovieReview {
+                    title,
+                    rating,
+                    description,
+                }
+            ),
+            any::<String>().prop_map(|comment| MovieInstruction::AddComment { comment }),
+            (
+                any::<u8>(),
+                any::<u64>(),
+                any::<String>(),
+                any::<String>(),
+                any::<String>()
+            )
+                .prop_map(|(decimals, max_supply, name, symbol, uri)| {
+                    MovieInstruction::InitializeMint {
+                        decimals,
+                        max_supply,
+                        name,
+                        symbol,
+                        uri,
+                    }
+                }),
+            any::<String>().prop_map(|comment| MovieInstruction::ReplyToComment { comment }),
+            any::<String>().prop_map(|comment| MovieInstruction::UpdateComment { comment }),
+            Just(MovieInstruction::DeleteComment),
+            Just(MovieInstruction::CloseMovieReview),
+            Just(MovieInstruction::UpvoteReview),
+            Just(MovieInstruction::UpvoteComment),
+            (any::<[u8; 32]>(), any::<u64>(), any::<[u64; 4]>()).prop_map(
+                |(admin, daily_cap, [review, comment, reply, upvote])| {
+                    MovieInstruction::UpdateRewardConfig {
+                        admin: Pubkey::new_from_array(admin),
+                        daily_cap,
+                        schedule: RewardSchedule {
+                            review,
+                            comment,
+                            reply,
+                            upvote,
+                        },
+                    }
+                }
+            ),
+            (any::<u64>(), any::<u64>()).prop_map(|(max_supply, daily_cap)| {
+                MovieInstruction::InitializeRewardConfig {
+                    max_supply,
+                    daily_cap,
+                }
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_pack_unpack_round_trip(instruction in movie_instruction()) {
+            let packed = instruction.pack();
+            prop_assert_eq!(MovieInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn test_canonical_comment_address_comes_first(index in any::<u64>()) {
+            let program_id = Pubkey::new_unique();
+            let review = Pubkey::new_unique();
+
+            let candidates = find_comment_address_candidates(&program_id, &review, index);
+
+            prop_assert_eq!(candidates[0], find_comment_address(&program_id, &review, index));
+            prop_assert_eq!(
+                candidates.len() == 1,
+                index.to_le_bytes() == index.to_be_bytes()
+            );
+        }
+
+        #[test]
+        fn test_unknown_tags_are_rejected(variant in (tag::INITIALIZE_REWARD_CONFIG + 1)..=u8::MAX) {
+            prop_assert_eq!(
+                MovieInstruction::unpack(&[variant]).unwrap_err(),
+                ProgramError::InvalidInstructionData
+            );
+        }
+    }
 }
@@ -1,5 +1,8 @@
 pub mod entrypoint;
 pub mod error;
+pub mod event;
 pub mod instruction;
 pub mod processor;
+pub mod signer_seeds;
 pub mod state;
+pub mod title;
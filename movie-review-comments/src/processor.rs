@@ -1,27 +1,35 @@
 use crate::error::ReviewError;
+use crate::event::RewardConfigUpdated;
 use crate::instruction::MovieInstruction;
-use crate::state::{MovieAccountState, MovieComment, MovieCommentCounter};
+use crate::signer_seeds;
+use crate::state::{
+    MovieAccountState, MovieComment, MovieCommentCounter, MovieStats, RewardAction, RewardConfig,
+    RewardLedger, RewardSchedule, Vote,
+};
+use crate::title::title_hash;
 use borsh::BorshSerialize;
+use mpl_token_metadata::{
+    instruction::create_metadata_accounts_v3, pda::find_metadata_account,
+    ID as TOKEN_METADATA_PROGRAM_ID,
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     borsh::try_from_slice_unchecked,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     entrypoint::ProgramResult,
     msg,
-    native_token::LAMPORTS_PER_SOL,
-    program::invoke_signed,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
-    program_pack::IsInitialized,
+    program_pack::{IsInitialized, Pack},
+    program_utils::limited_deserialize,
     pubkey::Pubkey,
     system_instruction,
     system_program::ID as SYSTEM_PROGRAM_ID,
-    sysvar::{rent::Rent, rent::ID as RENT_PROGRAM_ID, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, rent::ID as RENT_PROGRAM_ID, Sysvar},
 };
 use spl_associated_token_account::get_associated_token_address;
-use spl_token::{
-    instruction::{initialize_mint, mint_to},
-    ID as TOKEN_PROGRAM_ID,
-};
-use std::convert::TryInto;
+use spl_token::{instruction::initialize_mint, state::Mint, ID as TOKEN_PROGRAM_ID};
+use std::{convert::TryInto, slice::Iter};
 
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -41,7 +49,34 @@ pub fn process_instruction(
             description,
         } => update_movie_review(program_id, accounts, title, rating, description),
         MovieInstruction::AddComment { comment } => add_comment(program_id, accounts, comment),
-        MovieInstruction::InitializeMint => initialize_token_mint(program_id, accounts),
+        MovieInstruction::InitializeMint {
+            decimals,
+            max_supply,
+            name,
+            symbol,
+            uri,
+        } => initialize_token_mint(
+            program_id, accounts, decimals, max_supply, name, symbol, uri,
+        ),
+        MovieInstruction::ReplyToComment { comment } => {
+            reply_to_comment(program_id, accounts, comment)
+        }
+        MovieInstruction::UpdateComment { comment } => {
+            update_comment(program_id, accounts, comment)
+        }
+        MovieInstruction::DeleteComment => delete_comment(program_id, accounts),
+        MovieInstruction::CloseMovieReview => close_movie_review(program_id, accounts),
+        MovieInstruction::UpvoteReview => upvote_review(program_id, accounts),
+        MovieInstruction::UpvoteComment => upvote_comment(program_id, accounts),
+        MovieInstruction::UpdateRewardConfig {
+            admin,
+            daily_cap,
+            schedule,
+        } => update_reward_config(program_id, accounts, admin, daily_cap, schedule),
+        MovieInstruction::InitializeRewardConfig {
+            max_supply,
+            daily_cap,
+        } => initialize_reward_config(program_id, accounts, max_supply, daily_cap),
     }
 }
 
@@ -62,9 +97,12 @@ pub fn add_movie_review(
     let initializer = next_account_info(account_info_iter)?;
     let pda_account = next_account_info(account_info_iter)?;
     let pda_counter = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
     let token_mint = next_account_info(account_info_iter)?;
     let mint_auth = next_account_info(account_info_iter)?;
     let user_ata = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let reward_ledger = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
@@ -74,7 +112,7 @@ pub fn add_movie_review(
     }
 
     let (pda, bump_seed) = Pubkey::find_program_address(
-        &[initializer.key.as_ref(), title.as_bytes().as_ref()],
+        &[initializer.key.as_ref(), title_hash(&title).as_ref()],
         program_id,
     );
     if pda != *pda_account.key {
@@ -88,9 +126,10 @@ pub fn add_movie_review(
     }
 
     msg!("Deriving mint authority");
-    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], program_id);
+    let (mint_pda, _mint_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id);
     let (mint_auth_pda, mint_auth_bump) =
-        Pubkey::find_program_address(&[b"token_auth"], program_id);
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id);
 
     if *token_mint.key != mint_pda {
         msg!("Incorrect token mint");
@@ -112,10 +151,13 @@ pub fn add_movie_review(
         return Err(ReviewError::IncorrectAccountError.into());
     }
 
-    let account_len: usize = 1000;
+    let account_len = MovieAccountState::get_account_size(title.clone(), description.clone());
 
-    if MovieAccountState::get_account_size(title.clone(), description.clone()) > account_len {
-        msg!("Data length is larger than 1000 bytes");
+    if account_len > MovieAccountState::MAX_ACCOUNT_SIZE {
+        msg!(
+            "Data length is larger than {} bytes",
+            MovieAccountState::MAX_ACCOUNT_SIZE
+        );
         return Err(ReviewError::InvalidDataLength.into());
     }
 
@@ -135,18 +177,19 @@ pub fn add_movie_review(
             pda_account.clone(),
             system_program.clone(),
         ],
-        &[&[
-            initializer.key.as_ref(),
-            title.as_bytes().as_ref(),
+        &[&signer_seeds::review(
+            initializer.key,
+            &title_hash(&title),
             &[bump_seed],
-        ]],
+        )],
     )?;
 
     msg!("PDA created: {}", pda);
 
     msg!("unpacking state account");
     let mut account_data =
-        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow()).unwrap();
+        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
     msg!("borrowed account data");
 
     msg!("checking if movie account is already initialized");
@@ -157,7 +200,9 @@ pub fn add_movie_review(
 
     account_data.discriminator = MovieAccountState::DISCRIMINATOR.to_string();
     account_data.reviewer = *initializer.key;
-    account_data.title = title;
+    account_data.upvotes = 0;
+    account_data.counted_in_stats = true;
+    account_data.title = title.clone();
     account_data.rating = rating;
     account_data.description = description;
     account_data.is_initialized = true;
@@ -172,70 +217,156 @@ pub fn add_movie_review(
     let counter_rent_lamports = rent.minimum_balance(MovieCommentCounter::SIZE);
 
     let (counter_pda, counter_bump_seed) =
-        Pubkey::find_program_address(&[pda.as_ref(), "comment".as_ref()], program_id);
+        Pubkey::find_program_address(&[pda.as_ref(), signer_seeds::COMMENT], program_id);
 
     if counter_pda != *pda_counter.key {
         msg!("Invalid seeds for counter PDA");
         return Err(ProgramError::InvalidArgument);
     }
 
-    invoke_signed(
-        &system_instruction::create_account(
-            initializer.key,
-            pda_counter.key,
-            counter_rent_lamports,
-            MovieCommentCounter::SIZE.try_into().unwrap(),
-            program_id,
-        ),
-        &[
-            initializer.clone(),
-            pda_counter.clone(),
-            system_program.clone(),
-        ],
-        &[&[pda.as_ref(), "comment".as_ref(), &[counter_bump_seed]]],
-    )?;
-    msg!("Comment counter created");
+    if pda_counter.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                initializer.key,
+                pda_counter.key,
+                counter_rent_lamports,
+                MovieCommentCounter::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                initializer.clone(),
+                pda_counter.clone(),
+                system_program.clone(),
+            ],
+            &[&signer_seeds::comment_counter(&pda, &[counter_bump_seed])],
+        )?;
+        msg!("Comment counter created");
+
+        let mut counter_data =
+            try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow())
+                .map_err(|_| ReviewError::CorruptAccountData)?;
+
+        msg!("Checking if counter account is already initialized...");
+        if counter_data.is_initialized() {
+            msg!("Account already initialized");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        counter_data.discriminator = MovieCommentCounter::DISCRIMINATOR.to_string();
+        counter_data.counter = 0;
+        counter_data.is_initialized = true;
+
+        msg!("Comment count: {}", counter_data.counter);
+        counter_data.serialize(&mut &mut pda_counter.data.borrow_mut()[..])?;
+
+        msg!("Comment counter initialized.");
+    } else {
+        // A closed review with comments leaves its counter behind. Keep
+        // counting from it so new comments don't collide with the old ones.
+        if pda_counter.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        let counter_data =
+            try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow())
+                .map_err(|_| ReviewError::CorruptAccountData)?;
+
+        if counter_data.discriminator != MovieCommentCounter::DISCRIMINATOR
+            || !counter_data.is_initialized()
+        {
+            msg!("Comment counter is not initialized");
+            return Err(ReviewError::UninitializedAccount.into());
+        }
+
+        msg!("Reusing comment counter at {}", counter_data.counter);
+    }
 
-    let mut counter_data =
-        try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow()).unwrap();
+    let (stats_pda, stats_bump_seed) = Pubkey::find_program_address(
+        &[signer_seeds::STATS, title_hash(&title).as_ref()],
+        program_id,
+    );
 
-    msg!("Checking if counter account is already initialized...");
-    if counter_data.is_initialized() {
-        msg!("Account already initialized");
-        return Err(ProgramError::AccountAlreadyInitialized);
+    if stats_pda != *pda_stats.key {
+        msg!("Invalid seeds for stats PDA");
+        return Err(ReviewError::InvalidPDA.into());
     }
 
-    counter_data.discriminator = MovieCommentCounter::DISCRIMINATOR.to_string();
-    counter_data.counter = 0;
-    counter_data.is_initialized = true;
-
-    msg!("Comment count: {}", counter_data.counter);
-    counter_data.serialize(&mut &mut pda_counter.data.borrow_mut()[..])?;
+    if pda_stats.data_is_empty() {
+        msg!("Creating movie stats");
+        let stats_rent_lamports = rent.minimum_balance(MovieStats::SIZE);
+
+        invoke_signed(
+            &system_instruction::create_account(
+                initializer.key,
+                pda_stats.key,
+                stats_rent_lamports,
+                MovieStats::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                initializer.clone(),
+                pda_stats.clone(),
+                system_program.clone(),
+            ],
+            &[&signer_seeds::stats(
+                &title_hash(&title),
+                &[stats_bump_seed],
+            )],
+        )?;
+    } else if pda_stats.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
 
-    msg!("Comment counter initialized.");
+    let mut stats_data = try_from_slice_unchecked::<MovieStats>(&pda_stats.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+    if !stats_data.is_initialized() {
+        stats_data.discriminator = MovieStats::DISCRIMINATOR.to_string();
+        stats_data.is_initialized = true;
+    }
+    stats_data.add_rating(rating);
+    stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
 
-    msg!("Minting 10 tokens to user's ATA");
+    msg!(
+        "Movie stats: {} reviews, rating sum {}",
+        stats_data.review_count,
+        stats_data.rating_sum
+    );
 
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            token_mint.key,
-            user_ata.key,
-            mint_auth.key,
-            &[],
-            10 * LAMPORTS_PER_SOL,
-        )?,
-        &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
-        &[&[b"token_auth", &[mint_auth_bump]]],
+    let reward = claim_reward(
+        program_id,
+        initializer,
+        initializer.key,
+        reward_ledger,
+        reward_config,
+        token_mint,
+        system_program,
+        RewardAction::Review,
     )?;
 
+    if reward > 0 {
+        msg!("Minting {} tokens to user's ATA", reward);
+
+        invoke_signed(
+            &spl_token::instruction::mint_to(
+                token_program.key,
+                token_mint.key,
+                user_ata.key,
+                mint_auth.key,
+                &[],
+                reward,
+            )?,
+            &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
+            &[&signer_seeds::token_auth(&[mint_auth_bump])],
+        )?;
+    }
+
     Ok(())
 }
 
 pub fn update_movie_review(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    title: String,
+    _title: String,
     rating: u8,
     description: String,
 ) -> ProgramResult {
@@ -245,6 +376,8 @@ pub fn update_movie_review(
 
     let initializer = next_account_info(account_info_iter)?;
     let pda_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
 
     if pda_account.owner != program_id {
         return Err(ProgramError::IllegalOwner);
@@ -255,19 +388,23 @@ pub fn update_movie_review(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    if *system_program.key != SYSTEM_PROGRAM_ID {
+        msg!("Incorrect system program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
     msg!("unpacking state account");
     let mut account_data =
-        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow()).unwrap();
+        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
     msg!("review title: {}", account_data.title);
 
-    let (pda, _bump_seed) = Pubkey::find_program_address(
-        &[
-            initializer.key.as_ref(),
-            account_data.title.as_bytes().as_ref(),
-        ],
+    if !is_review_address(
         program_id,
-    );
-    if pda != *pda_account.key {
+        initializer.key,
+        &account_data.title,
+        pda_account.key,
+    ) {
         msg!("Invalid seeds for PDA");
         return Err(ReviewError::InvalidPDA.into());
     }
@@ -283,12 +420,49 @@ pub fn update_movie_review(
         return Err(ReviewError::InvalidRating.into());
     }
 
-    let update_len = MovieAccountState::get_account_size(title, description.clone());
-    if update_len > 1000 {
-        msg!("Data length is larger than 1000 bytes");
+    let update_len =
+        MovieAccountState::get_account_size(account_data.title.clone(), description.clone());
+    if update_len > MovieAccountState::MAX_ACCOUNT_SIZE {
+        msg!(
+            "Data length is larger than {} bytes",
+            MovieAccountState::MAX_ACCOUNT_SIZE
+        );
         return Err(ReviewError::InvalidDataLength.into());
     }
 
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(update_len);
+
+    if rent_lamports > pda_account.lamports() {
+        msg!("Topping up rent for larger review");
+        invoke(
+            &system_instruction::transfer(
+                initializer.key,
+                pda_account.key,
+                rent_lamports - pda_account.lamports(),
+            ),
+            &[
+                initializer.clone(),
+                pda_account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+    } else if rent_lamports < pda_account.lamports() {
+        msg!("Refunding rent for smaller review");
+        let refund = pda_account.lamports() - rent_lamports;
+        **pda_account.try_borrow_mut_lamports()? -= refund;
+        **initializer.try_borrow_mut_lamports()? += refund;
+    }
+
+    msg!("Reallocating review account to {} bytes", update_len);
+    pda_account.realloc(update_len, false)?;
+
+    if let Some(mut stats_data) = load_stats(program_id, pda_stats, &account_data)? {
+        stats_data.remove_rating(account_data.rating);
+        stats_data.add_rating(rating);
+        stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
+    }
+
     msg!("Review before update:");
     msg!("Title: {}", account_data.title);
     msg!("Rating: {}", account_data.rating);
@@ -326,13 +500,16 @@ pub fn add_comment(
     let token_mint = next_account_info(account_info_iter)?;
     let mint_auth = next_account_info(account_info_iter)?;
     let user_ata = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let reward_ledger = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
 
     msg!("Deriving mint authority");
-    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], program_id);
+    let (mint_pda, _mint_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id);
     let (mint_auth_pda, mint_auth_bump) =
-        Pubkey::find_program_address(&[b"token_auth"], program_id);
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id);
 
     if *token_mint.key != mint_pda {
         msg!("Incorrect token mint");
@@ -354,8 +531,44 @@ pub fn add_comment(
         return Err(ReviewError::IncorrectAccountError.into());
     }
 
+    if pda_review.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let review_data = try_from_slice_unchecked::<MovieAccountState>(&pda_review.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !review_data.is_initialized()
+        || review_data.discriminator != MovieAccountState::DISCRIMINATOR
+    {
+        msg!("Account is not a review");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    let (counter_pda, _counter_bump_seed) = Pubkey::find_program_address(
+        &[pda_review.key.as_ref(), signer_seeds::COMMENT],
+        program_id,
+    );
+
+    if counter_pda != *pda_counter.key {
+        msg!("Invalid seeds for counter PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if pda_counter.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
     let mut counter_data =
-        try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow()).unwrap();
+        try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !counter_data.is_initialized()
+        || counter_data.discriminator != MovieCommentCounter::DISCRIMINATOR
+    {
+        msg!("Account is not a comment counter");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
 
     let account_len = MovieComment::get_account_size(comment.clone());
 
@@ -365,7 +578,7 @@ pub fn add_comment(
     let (pda, bump_seed) = Pubkey::find_program_address(
         &[
             pda_review.key.as_ref(),
-            counter_data.counter.to_be_bytes().as_ref(),
+            signer_seeds::comment_index(counter_data.counter).as_ref(),
         ],
         program_id,
     );
@@ -388,17 +601,17 @@ pub fn add_comment(
             pda_comment.clone(),
             system_program.clone(),
         ],
-        &[&[
-            pda_review.key.as_ref(),
-            counter_data.counter.to_be_bytes().as_ref(),
+        &[&signer_seeds::comment(
+            pda_review.key,
+            &signer_seeds::comment_index(counter_data.counter),
             &[bump_seed],
-        ]],
+        )],
     )?;
 
     msg!("Comment account created");
 
-    let mut comment_data =
-        try_from_slice_unchecked::<MovieComment>(&pda_comment.data.borrow()).unwrap();
+    let mut comment_data = try_from_slice_unchecked::<MovieComment>(&pda_comment.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
 
     msg!("Checking if comment account is already initialized...");
     if comment_data.is_initialized() {
@@ -410,52 +623,108 @@ pub fn add_comment(
     comment_data.commenter = *commenter.key;
     comment_data.review = *pda_review.key;
     comment_data.comment = comment;
+    comment_data.count = counter_data.counter;
+    comment_data.parent = Pubkey::default();
+    comment_data.is_deleted = false;
+    comment_data.upvotes = 0;
     comment_data.is_initialized = true;
 
     comment_data.serialize(&mut &mut pda_comment.data.borrow_mut()[..])?;
 
     msg!("Comment Count: {}", counter_data.counter);
 
-    counter_data.counter += 1;
+    counter_data.counter = counter_data
+        .counter
+        .checked_add(1)
+        .ok_or(ReviewError::CounterOverflow)?;
     counter_data.serialize(&mut &mut pda_counter.data.borrow_mut()[..])?;
 
-    msg!("Minting 5 tokens to user's ATA");
+    if review_data.reviewer == *commenter.key {
+        msg!("Comments on your own review earn no reward");
+        return Ok(());
+    }
 
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            token_mint.key,
-            user_ata.key,
-            mint_auth.key,
-            &[],
-            5 * LAMPORTS_PER_SOL,
-        )?,
-        &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
-        &[&[b"token_mint", &[mint_auth_bump]]],
+    let reward = claim_reward(
+        program_id,
+        commenter,
+        commenter.key,
+        reward_ledger,
+        reward_config,
+        token_mint,
+        system_program,
+        RewardAction::Comment,
     )?;
 
+    if reward > 0 {
+        msg!("Minting {} tokens to user's ATA", reward);
+
+        invoke_signed(
+            &spl_token::instruction::mint_to(
+                token_program.key,
+                token_mint.key,
+                user_ata.key,
+                mint_auth.key,
+                &[],
+                reward,
+            )?,
+            &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
+            &[&signer_seeds::token_auth(&[mint_auth_bump])],
+        )?;
+    }
+
     Ok(())
 }
 
-pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+pub fn reply_to_comment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    comment: String,
+) -> ProgramResult {
+    msg!("Replying to comment...");
+    msg!("Reply: {}", comment);
+
     let account_info_iter = &mut accounts.iter();
 
-    let initializer = next_account_info(account_info_iter)?;
+    let commenter = next_account_info(account_info_iter)?;
+    let pda_review = next_account_info(account_info_iter)?;
+    let pda_parent = next_account_info(account_info_iter)?;
+    let pda_reply_counter = next_account_info(account_info_iter)?;
+    let pda_reply = next_account_info(account_info_iter)?;
     let token_mint = next_account_info(account_info_iter)?;
     let mint_auth = next_account_info(account_info_iter)?;
+    let user_ata = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let reward_ledger = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
-    let sysvar_rent = next_account_info(account_info_iter)?;
 
-    let (mint_pda, mint_bump_seed) = Pubkey::find_program_address(&[b"token_mint"], program_id);
+    if !commenter.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_review.owner != program_id || pda_parent.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    msg!("Deriving mint authority");
+    let (mint_pda, _mint_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id);
     let (mint_auth_pda, mint_auth_bump) =
-        Pubkey::find_program_address(&[b"token_auth"], program_id);
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id);
 
-    msg!("Token mint: {}", mint_pda);
-    msg!("Mint authority: {}", mint_auth_pda);
+    if *token_mint.key != mint_pda {
+        msg!("Incorrect token mint");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
 
-    if mint_pda != *token_mint.key {
-        msg!("Incorrect token mint account");
+    if *mint_auth.key != mint_auth_pda {
+        msg!("Mint passed in and mint derived do not match");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if *user_ata.key != get_associated_token_address(commenter.key, token_mint.key) {
+        msg!("Incorrect token mint");
         return Err(ReviewError::IncorrectAccountError.into());
     }
 
@@ -464,176 +733,3100 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
         return Err(ReviewError::IncorrectAccountError.into());
     }
 
-    if *mint_auth.key != mint_auth_pda {
-        msg!("Incorrect mint auth account");
-        return Err(ReviewError::IncorrectAccountError.into());
+    let parent_data = try_from_slice_unchecked::<MovieComment>(&pda_parent.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if parent_data.discriminator != MovieComment::DISCRIMINATOR || !parent_data.is_initialized() {
+        msg!("Parent comment is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
     }
 
-    if *system_program.key != SYSTEM_PROGRAM_ID {
-        msg!("Incorrect system program");
+    if parent_data.review != *pda_review.key {
+        msg!("Parent comment does not belong to this review");
         return Err(ReviewError::IncorrectAccountError.into());
     }
 
-    if *sysvar_rent.key != RENT_PROGRAM_ID {
-        msg!("Incorrect rent program");
-        return Err(ReviewError::IncorrectAccountError.into());
+    if parent_data.is_deleted {
+        msg!("Cannot reply to a deleted comment");
+        return Err(ReviewError::CommentDeleted.into());
+    }
+
+    let (reply_counter_pda, reply_counter_bump) =
+        Pubkey::find_program_address(&[pda_parent.key.as_ref(), signer_seeds::REPLY], program_id);
+
+    if reply_counter_pda != *pda_reply_counter.key {
+        msg!("Invalid seeds for reply counter PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if pda_reply_counter.data_is_empty() {
+        msg!("Creating reply counter");
+        let rent = Rent::get()?;
+        let counter_rent_lamports = rent.minimum_balance(MovieCommentCounter::SIZE);
+
+        invoke_signed(
+            &system_instruction::create_account(
+                commenter.key,
+                pda_reply_counter.key,
+                counter_rent_lamports,
+                MovieCommentCounter::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                commenter.clone(),
+                pda_reply_counter.clone(),
+                system_program.clone(),
+            ],
+            &[&signer_seeds::reply_counter(
+                pda_parent.key,
+                &[reply_counter_bump],
+            )],
+        )?;
+
+        let mut counter_data =
+            try_from_slice_unchecked::<MovieCommentCounter>(&pda_reply_counter.data.borrow())
+                .map_err(|_| ReviewError::CorruptAccountData)?;
+
+        counter_data.discriminator = MovieCommentCounter::DISCRIMINATOR.to_string();
+        counter_data.counter = 0;
+        counter_data.is_initialized = true;
+
+        counter_data.serialize(&mut &mut pda_reply_counter.data.borrow_mut()[..])?;
+        msg!("Reply counter initialized.");
+    } else if pda_reply_counter.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
     }
 
+    let mut counter_data =
+        try_from_slice_unchecked::<MovieCommentCounter>(&pda_reply_counter.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    let account_len = MovieComment::get_account_size(comment.clone());
+
     let rent = Rent::get()?;
-    let rent_lamports = rent.minimum_balance(82);
+    let rent_lamports = rent.minimum_balance(account_len);
+
+    let (pda, bump_seed) = Pubkey::find_program_address(
+        &[
+            pda_parent.key.as_ref(),
+            signer_seeds::comment_index(counter_data.counter).as_ref(),
+        ],
+        program_id,
+    );
+
+    if pda != *pda_reply.key {
+        msg!("Invalid seeds for PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
 
     invoke_signed(
         &system_instruction::create_account(
-            initializer.key,
-            token_mint.key,
+            commenter.key,
+            pda_reply.key,
             rent_lamports,
-            82,
-            token_program.key,
+            account_len.try_into().unwrap(),
+            program_id,
         ),
-        &[
-            initializer.clone(),
-            token_mint.clone(),
-            system_program.clone(),
-        ],
-        &[&[b"token_mint", &[mint_bump_seed]]],
+        &[commenter.clone(), pda_reply.clone(), system_program.clone()],
+        &[&signer_seeds::comment(
+            pda_parent.key,
+            &signer_seeds::comment_index(counter_data.counter),
+            &[bump_seed],
+        )],
     )?;
 
-    msg!("Initialized token mint");
+    msg!("Reply account created");
 
-    Ok(())
-}
+    let mut reply_data = try_from_slice_unchecked::<MovieComment>(&pda_reply.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
 
-#[cfg(test)]
-mod tests {
-    use {
-        super::*,
-        assert_matches::*,
-        solana_program::{
-            instruction::{AccountMeta, Instruction},
-            system_program::ID as SYSTEM_PROGRAM_ID,
-        },
-        solana_program_test::*,
-        solana_sdk::{
-            signature::Signer, sysvar::rent::ID as SYSVAR_RENT_ID, transaction::Transaction,
-        },
-        spl_associated_token_account::{
-            get_associated_token_address, instruction::create_associated_token_account,
-        },
-        spl_token::ID as TOKEN_PROGRAM_ID,
-    };
+    msg!("Checking if reply account is already initialized...");
+    if reply_data.is_initialized() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
 
-    fn create_init_mint_ix(payer: Pubkey, program_id: Pubkey) -> (Pubkey, Pubkey, Instruction) {
-        let (mint, mint_bump) = Pubkey::find_program_address(&[b"token_mint"], &program_id);
-        let (mint_auth, _bump) = Pubkey::find_program_address(&[b"token_auth"], &program_id);
-
-        let init_mint_ix = Instruction {
-            program_id: program_id,
-            accounts: vec![
-                AccountMeta::new_readonly(payer, true),
-                AccountMeta::new(mint, false),
-                AccountMeta::new(mint_auth, false),
-                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
-                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
-                AccountMeta::new_readonly(SYSVAR_RENT_ID, false),
-            ],
-            data: vec![3],
-        };
+    reply_data.discriminator = MovieComment::DISCRIMINATOR.to_string();
+    reply_data.commenter = *commenter.key;
+    reply_data.review = *pda_review.key;
+    reply_data.comment = comment;
+    reply_data.count = counter_data.counter;
+    reply_data.parent = *pda_parent.key;
+    reply_data.is_deleted = false;
+    reply_data.upvotes = 0;
+    reply_data.is_initialized = true;
+
+    reply_data.serialize(&mut &mut pda_reply.data.borrow_mut()[..])?;
+
+    msg!("Reply Count: {}", counter_data.counter);
+
+    counter_data.counter = counter_data
+        .counter
+        .checked_add(1)
+        .ok_or(ReviewError::CounterOverflow)?;
+    counter_data.serialize(&mut &mut pda_reply_counter.data.borrow_mut()[..])?;
+
+    if parent_data.commenter == *commenter.key {
+        msg!("Replies to your own comment earn no reward");
+        return Ok(());
+    }
+
+    let reward = claim_reward(
+        program_id,
+        commenter,
+        commenter.key,
+        reward_ledger,
+        reward_config,
+        token_mint,
+        system_program,
+        RewardAction::Reply,
+    )?;
 
-        (mint, mint_auth, init_mint_ix)
+    if reward > 0 {
+        msg!("Minting {} tokens to user's ATA", reward);
+
+        invoke_signed(
+            &spl_token::instruction::mint_to(
+                token_program.key,
+                token_mint.key,
+                user_ata.key,
+                mint_auth.key,
+                &[],
+                reward,
+            )?,
+            &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
+            &[&signer_seeds::token_auth(&[mint_auth_bump])],
+        )?;
     }
 
-    #[tokio::test]
-    async fn test_initialize_mint_instruction() {
-        let program_id = Pubkey::new_unique();
-        let (mut bank_clients, payer, recent_blockhash) = ProgramTest::new(
-            "movie_review_comments",
-            program_id,
-            processor!(process_instruction),
-        )
-        .start()
-        .await;
+    Ok(())
+}
 
-        let (_mint, _mint_auth, init_mint_ix) = create_init_mint_ix(payer.pubkey(), program_id);
+pub fn update_comment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    comment: String,
+) -> ProgramResult {
+    msg!("Updating comment...");
+    msg!("Comment: {}", comment);
 
-        let mut txn = Transaction::new_with_payer(&[init_mint_ix], Some(&payer.pubkey()));
+    let account_info_iter = &mut accounts.iter();
 
-        txn.sign(&[&payer], recent_blockhash);
+    let commenter = next_account_info(account_info_iter)?;
+    let pda_comment = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-        assert_matches!(bank_clients.process_transaction(txn).await, Ok(_));
+    if !commenter.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
     }
 
-    #[tokio::test]
-    async fn test_add_movie_review_instruction() {
-        let program_id = Pubkey::new_unique();
-        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
-            "movie_review_comments",
-            program_id,
-            processor!(process_instruction),
-        )
-        .start()
-        .await;
+    if pda_comment.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
 
-        let (mint, mint_auth, init_mint_ix) = create_init_mint_ix(payer.pubkey(), program_id);
+    if *system_program.key != SYSTEM_PROGRAM_ID {
+        msg!("Incorrect system program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
 
-        let title: String = "Captain America".to_owned();
-        const RATING: u8 = 3;
-        let review: String = "Liked the movie".to_owned();
-        let (review_pda, _bump_seed) =
-            Pubkey::find_program_address(&[payer.pubkey().as_ref(), title.as_bytes()], &program_id);
+    let mut comment_data = try_from_slice_unchecked::<MovieComment>(&pda_comment.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
 
-        let (comment_pda, _bump_seed) =
-            Pubkey::find_program_address(&[review_pda.as_ref(), b"comment"], &program_id);
+    if comment_data.discriminator != MovieComment::DISCRIMINATOR || !comment_data.is_initialized() {
+        msg!("Comment account is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
 
-        let init_ata_ix: Instruction = create_associated_token_account(
-            &payer.pubkey(),
-            &payer.pubkey(),
-            &mint,
-            &TOKEN_PROGRAM_ID,
-        );
+    if comment_data.commenter != *commenter.key {
+        msg!("Only the commenter can update this comment");
+        return Err(ReviewError::Unauthorized.into());
+    }
 
-        let user_ata: Pubkey = get_associated_token_address(&payer.pubkey(), &mint);
+    if comment_data.is_deleted {
+        msg!("Comment has been deleted");
+        return Err(ReviewError::CommentDeleted.into());
+    }
 
-        let mut data_vec = vec![0];
-        data_vec.append(
-            &mut (TryInto::<u32>::try_into(title.len()).unwrap().to_le_bytes())
-                .try_into()
-                .unwrap(),
-        );
-        data_vec.append(&mut title.into_bytes());
-        data_vec.push(RATING);
-        data_vec.append(
-            &mut (TryInto::<u32>::try_into(review.len())
-                .unwrap()
-                .to_le_bytes())
-            .try_into()
-            .unwrap(),
-        );
-        data_vec.append(&mut review.into_bytes());
+    let account_len = MovieComment::get_account_size(comment.clone());
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(account_len);
 
-        let mut txn = Transaction::new_with_payer(
+    if rent_lamports > pda_comment.lamports() {
+        msg!("Topping up rent for larger comment");
+        invoke(
+            &system_instruction::transfer(
+                commenter.key,
+                pda_comment.key,
+                rent_lamports - pda_comment.lamports(),
+            ),
             &[
-                init_mint_ix,
-                init_ata_ix,
-                Instruction {
-                    program_id: program_id,
-                    accounts: vec![
-                        AccountMeta::new_readonly(payer.pubkey(), true),
-                        AccountMeta::new(review_pda, false),
-                        AccountMeta::new(comment_pda, false),
-                        AccountMeta::new(mint, false),
-                        AccountMeta::new_readonly(mint_auth, false),
-                        AccountMeta::new(user_ata, false),
-                        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
-                        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
-                    ],
-                    data: data_vec,
-                },
+                commenter.clone(),
+                pda_comment.clone(),
+                system_program.clone(),
             ],
-            Some(&payer.pubkey()),
-        );
+        )?;
+    } else if rent_lamports < pda_comment.lamports() {
+        msg!("Refunding rent for smaller comment");
+        let refund = pda_comment.lamports() - rent_lamports;
+        **pda_comment.try_borrow_mut_lamports()? -= refund;
+        **commenter.try_borrow_mut_lamports()? += refund;
+    }
 
-        txn.sign(&[&payer], recent_blockhash);
+    pda_comment.realloc(account_len, false)?;
 
-        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+    comment_data.comment = comment;
+    comment_data.serialize(&mut &mut pda_comment.data.borrow_mut()[..])?;
+
+    msg!("Comment updated");
+
+    Ok(())
+}
+
+pub fn delete_comment(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Deleting comment...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let commenter = next_account_info(account_info_iter)?;
+    let pda_comment = next_account_info(account_info_iter)?;
+
+    if !commenter.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_comment.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut comment_data = try_from_slice_unchecked::<MovieComment>(&pda_comment.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if comment_data.discriminator != MovieComment::DISCRIMINATOR || !comment_data.is_initialized() {
+        msg!("Comment account is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if comment_data.commenter != *commenter.key {
+        msg!("Only the commenter can delete this comment");
+        return Err(ReviewError::Unauthorized.into());
+    }
+
+    if comment_data.is_deleted {
+        msg!("Comment has already been deleted");
+        return Err(ReviewError::CommentDeleted.into());
+    }
+
+    // The account is kept as a tombstone so comment indices stay dense;
+    // only the comment body is dropped and its rent returned.
+    let account_len = MovieComment::get_account_size(String::new());
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(account_len);
+
+    let refund = pda_comment.lamports().saturating_sub(rent_lamports);
+    **pda_comment.try_borrow_mut_lamports()? -= refund;
+    **commenter.try_borrow_mut_lamports()? += refund;
+
+    pda_comment.realloc(account_len, false)?;
+
+    comment_data.comment = String::new();
+    comment_data.is_deleted = true;
+    comment_data.serialize(&mut &mut pda_comment.data.borrow_mut()[..])?;
+
+    msg!("Comment deleted, refunded {} lamports", refund);
+
+    Ok(())
+}
+
+pub fn close_movie_review(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Closing movie review...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let reviewer = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let pda_counter = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
+
+    if !reviewer.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_account.owner != program_id || pda_counter.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let account_data = try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if account_data.discriminator != MovieAccountState::DISCRIMINATOR
+        || !account_data.is_initialized()
+    {
+        msg!("Account is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if account_data.reviewer != *reviewer.key {
+        msg!("Only the reviewer can close this review");
+        return Err(ReviewError::Unauthorized.into());
+    }
+
+    let (counter_pda, _counter_bump_seed) = Pubkey::find_program_address(
+        &[pda_account.key.as_ref(), signer_seeds::COMMENT],
+        program_id,
+    );
+
+    if counter_pda != *pda_counter.key {
+        msg!("Invalid seeds for counter PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    let counter_data = try_from_slice_unchecked::<MovieCommentCounter>(&pda_counter.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if let Some(mut stats_data) = load_stats(program_id, pda_stats, &account_data)? {
+        stats_data.remove_rating(account_data.rating);
+        stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
+    }
+
+    msg!("Draining review account");
+    let review_lamports = pda_account.lamports();
+    **pda_account.try_borrow_mut_lamports()? = 0;
+    **reviewer.try_borrow_mut_lamports()? += review_lamports;
+    pda_account.data.borrow_mut().fill(0);
+
+    if counter_data.counter == 0 {
+        msg!("No comments found, closing comment counter");
+        let counter_lamports = pda_counter.lamports();
+        **pda_counter.try_borrow_mut_lamports()? = 0;
+        **reviewer.try_borrow_mut_lamports()? += counter_lamports;
+        pda_counter.data.borrow_mut().fill(0);
+    } else {
+        msg!(
+            "Review has {} comments, keeping comment counter",
+            counter_data.counter
+        );
+    }
+
+    msg!("Movie review closed");
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_token_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    decimals: u8,
+    max_supply: u64,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let initializer = next_account_info(account_info_iter)?;
+    let token_mint = next_account_info(account_info_iter)?;
+    let mint_auth = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let metadata = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let sysvar_rent = next_account_info(account_info_iter)?;
+    let token_metadata_program = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+
+    // The caller picks the decimals and becomes the metadata update authority.
+    check_upgrade_authority(program_id, initializer, program_data)?;
+
+    let (mint_pda, mint_bump_seed) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id);
+    let (mint_auth_pda, mint_auth_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id);
+
+    msg!("Token mint: {}", mint_pda);
+    msg!("Mint authority: {}", mint_auth_pda);
+
+    if mint_pda != *token_mint.key {
+        msg!("Incorrect token mint account");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *token_program.key != TOKEN_PROGRAM_ID {
+        msg!("Incorrect token program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *mint_auth.key != mint_auth_pda {
+        msg!("Incorrect mint auth account");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *metadata.key != find_metadata_account(token_mint.key).0 {
+        msg!("Incorrect metadata account");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *system_program.key != SYSTEM_PROGRAM_ID {
+        msg!("Incorrect system program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *sysvar_rent.key != RENT_PROGRAM_ID {
+        msg!("Incorrect rent program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *token_metadata_program.key != TOKEN_METADATA_PROGRAM_ID {
+        msg!("Incorrect token metadata program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if reward_config.data_is_empty() {
+        // No daily cap until the admin sets one with UpdateRewardConfig.
+        create_reward_config(
+            program_id,
+            initializer,
+            reward_config,
+            system_program,
+            max_supply,
+            u64::MAX,
+        )?;
+    } else if load_reward_config(program_id, reward_config)?.max_supply != max_supply {
+        msg!("Reward config already exists with a different max supply");
+        return Err(ReviewError::MaxSupplyMismatch.into());
+    }
+
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(Mint::LEN);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            initializer.key,
+            token_mint.key,
+            rent_lamports,
+            Mint::LEN.try_into().unwrap(),
+            token_program.key,
+        ),
+        &[
+            initializer.clone(),
+            token_mint.clone(),
+            system_program.clone(),
+        ],
+        &[&signer_seeds::token_mint(&[mint_bump_seed])],
+    )?;
+
+    invoke(
+        &initialize_mint(
+            token_program.key,
+            token_mint.key,
+            mint_auth.key,
+            None,
+            decimals,
+        )?,
+        &[
+            token_mint.clone(),
+            sysvar_rent.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    msg!("Initialized token mint with {} decimals", decimals);
+
+    invoke_signed(
+        &create_metadata_accounts_v3(
+            TOKEN_METADATA_PROGRAM_ID,
+            *metadata.key,
+            *token_mint.key,
+            *mint_auth.key,
+            *initializer.key,
+            *initializer.key,
+            name,
+            symbol,
+            uri,
+            None,
+            0,
+            true,
+            true,
+            None,
+            None,
+            None,
+        ),
+        &[
+            metadata.clone(),
+            token_mint.clone(),
+            mint_auth.clone(),
+            initializer.clone(),
+            system_program.clone(),
+            sysvar_rent.clone(),
+        ],
+        &[&signer_seeds::token_auth(&[mint_auth_bump])],
+    )?;
+
+    msg!("Created reward token metadata");
+
+    Ok(())
+}
+
+/// Whether `review` is the review `reviewer` posted under `title`, seeded with
+/// the title hash or, for reviews created before that, the raw title.
+fn is_review_address(program_id: &Pubkey, reviewer: &Pubkey, title: &str, review: &Pubkey) -> bool {
+    let (pda, _bump_seed) =
+        Pubkey::find_program_address(&[reviewer.as_ref(), title_hash(title).as_ref()], program_id);
+    if pda == *review {
+        return true;
+    }
+
+    match signer_seeds::legacy_review_title(title) {
+        Some(seed) => {
+            Pubkey::find_program_address(&[reviewer.as_ref(), seed], program_id).0 == *review
+        }
+        None => false,
+    }
+}
+
+/// Loads the stats that count `review`, or `None` if it was posted before
+/// stats were tracked. Its rating was never tallied, so there is nothing to
+/// adjust even if the title has stats from newer reviews.
+fn load_stats(
+    program_id: &Pubkey,
+    pda_stats: &AccountInfo,
+    review: &MovieAccountState,
+) -> Result<Option<MovieStats>, ProgramError> {
+    let (stats_pda, _stats_bump_seed) = Pubkey::find_program_address(
+        &[signer_seeds::STATS, title_hash(&review.title).as_ref()],
+        program_id,
+    );
+
+    if stats_pda != *pda_stats.key {
+        msg!("Invalid seeds for stats PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if !review.counted_in_stats {
+        msg!("Review is not counted in the title's stats");
+        return Ok(None);
+    }
+
+    if pda_stats.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let stats_data = try_from_slice_unchecked::<MovieStats>(&pda_stats.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !stats_data.is_initialized() {
+        msg!("Stats are not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    Ok(Some(stats_data))
+}
+
+fn load_reward_config(
+    program_id: &Pubkey,
+    reward_config: &AccountInfo,
+) -> Result<RewardConfig, ProgramError> {
+    let (config_pda, _config_bump_seed) =
+        Pubkey::find_program_address(&[signer_seeds::REWARD_CONFIG], program_id);
+
+    if *reward_config.key != config_pda {
+        msg!("Invalid seeds for reward config PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if reward_config.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let config_data = try_from_slice_unchecked::<RewardConfig>(&reward_config.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !config_data.is_initialized() {
+        msg!("Reward config is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    Ok(config_data)
+}
+
+fn reward_within_supply_cap(config_data: &RewardConfig, mint_data: &Mint, amount: u64) -> bool {
+    matches!(
+        mint_data.supply.checked_add(amount),
+        Some(supply) if supply <= config_data.max_supply
+    )
+}
+
+fn reward_amount(
+    config_data: &RewardConfig,
+    mint_data: &Mint,
+    action: RewardAction,
+) -> Result<u64, ProgramError> {
+    config_data
+        .schedule
+        .amount_for(action, mint_data.decimals)
+        .ok_or_else(|| ReviewError::RewardOverflow.into())
+}
+
+// Records the reward for `action` in the user's reward ledger, creating it at
+// `payer`'s expense on first use, and returns how much may be minted under the
+// daily and supply caps.
+#[allow(clippy::too_many_arguments)]
+fn claim_reward<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    user: &Pubkey,
+    reward_ledger: &AccountInfo<'a>,
+    reward_config: &AccountInfo<'a>,
+    token_mint: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    action: RewardAction,
+) -> Result<u64, ProgramError> {
+    let config_data = load_reward_config(program_id, reward_config)?;
+    let mint_data = Mint::unpack(&token_mint.data.borrow())?;
+    let amount = reward_amount(&config_data, &mint_data, action)?;
+
+    let (ledger_pda, ledger_bump_seed) =
+        Pubkey::find_program_address(&[signer_seeds::REWARD_LEDGER, user.as_ref()], program_id);
+
+    if ledger_pda != *reward_ledger.key {
+        msg!("Invalid seeds for reward ledger PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if reward_ledger.data_is_empty() {
+        msg!("Creating reward ledger");
+        let rent = Rent::get()?;
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                reward_ledger.key,
+                rent.minimum_balance(RewardLedger::SIZE),
+                RewardLedger::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[payer.clone(), reward_ledger.clone(), system_program.clone()],
+            &[&signer_seeds::reward_ledger(user, &[ledger_bump_seed])],
+        )?;
+    } else if reward_ledger.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut ledger_data = try_from_slice_unchecked::<RewardLedger>(&reward_ledger.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !ledger_data.is_initialized() {
+        ledger_data.discriminator = RewardLedger::DISCRIMINATOR.to_string();
+        ledger_data.user = *user;
+        ledger_data.is_initialized = true;
+    }
+
+    ledger_data.roll_window(Clock::get()?.unix_timestamp);
+
+    let mut reward = amount.min(ledger_data.remaining(config_data.daily_cap));
+
+    if amount == 0 {
+        msg!("No reward configured for this action");
+    } else if reward == 0 {
+        msg!("Daily reward cap reached, no tokens minted");
+    } else if !reward_within_supply_cap(&config_data, &mint_data, reward) {
+        msg!("Reward supply cap reached, no tokens minted");
+        reward = 0;
+    }
+
+    ledger_data.rewarded += reward;
+    ledger_data.serialize(&mut &mut reward_ledger.data.borrow_mut()[..])?;
+
+    msg!(
+        "Rewarded {} of {} in the current window",
+        ledger_data.rewarded,
+        config_data.daily_cap
+    );
+
+    Ok(reward)
+}
+
+pub fn upvote_review(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Upvoting review...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let voter = next_account_info(account_info_iter)?;
+    let pda_review = next_account_info(account_info_iter)?;
+    let pda_vote = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !voter.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_review.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut review_data = try_from_slice_unchecked::<MovieAccountState>(&pda_review.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !review_data.is_initialized()
+        || review_data.discriminator != MovieAccountState::DISCRIMINATOR
+    {
+        msg!("Account is not a review");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if review_data.reviewer == *voter.key {
+        msg!("Reviewers cannot upvote their own review");
+        return Err(ReviewError::SelfVote.into());
+    }
+
+    create_vote(program_id, voter, pda_review, pda_vote, system_program)?;
+
+    review_data.upvotes += 1;
+    let account_len = MovieAccountState::get_account_size(
+        review_data.title.clone(),
+        review_data.description.clone(),
+    );
+    realloc_with_rent(voter, pda_review, system_program, account_len)?;
+    review_data.serialize(&mut &mut pda_review.data.borrow_mut()[..])?;
+
+    msg!("Review upvotes: {}", review_data.upvotes);
+
+    if review_data.upvotes % Vote::REWARD_THRESHOLD == 0 {
+        mint_upvote_reward(
+            program_id,
+            voter,
+            &review_data.reviewer,
+            system_program,
+            account_info_iter,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn upvote_comment(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Upvoting comment...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let voter = next_account_info(account_info_iter)?;
+    let pda_comment = next_account_info(account_info_iter)?;
+    let pda_vote = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !voter.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_comment.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut comment_data = try_from_slice_unchecked::<MovieComment>(&pda_comment.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !comment_data.is_initialized() || comment_data.discriminator != MovieComment::DISCRIMINATOR {
+        msg!("Account is not a comment");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if comment_data.is_deleted {
+        msg!("Cannot upvote a deleted comment");
+        return Err(ReviewError::CommentDeleted.into());
+    }
+
+    if comment_data.commenter == *voter.key {
+        msg!("Commenters cannot upvote their own comment");
+        return Err(ReviewError::SelfVote.into());
+    }
+
+    create_vote(program_id, voter, pda_comment, pda_vote, system_program)?;
+
+    comment_data.upvotes += 1;
+    let account_len = MovieComment::get_account_size(comment_data.comment.clone());
+    realloc_with_rent(voter, pda_comment, system_program, account_len)?;
+    comment_data.serialize(&mut &mut pda_comment.data.borrow_mut()[..])?;
+
+    msg!("Comment upvotes: {}", comment_data.upvotes);
+
+    if comment_data.upvotes % Vote::REWARD_THRESHOLD == 0 {
+        mint_upvote_reward(
+            program_id,
+            voter,
+            &comment_data.commenter,
+            system_program,
+            account_info_iter,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_vote<'a>(
+    program_id: &Pubkey,
+    voter: &AccountInfo<'a>,
+    target: &AccountInfo<'a>,
+    pda_vote: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let (vote_pda, vote_bump_seed) = Pubkey::find_program_address(
+        &[signer_seeds::VOTE, target.key.as_ref(), voter.key.as_ref()],
+        program_id,
+    );
+
+    if vote_pda != *pda_vote.key {
+        msg!("Invalid seeds for vote PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if *system_program.key != SYSTEM_PROGRAM_ID {
+        msg!("Incorrect system program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if !pda_vote.data_is_empty() {
+        msg!("Voter has already upvoted this account");
+        return Err(ReviewError::AlreadyVoted.into());
+    }
+
+    let rent = Rent::get()?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            voter.key,
+            pda_vote.key,
+            rent.minimum_balance(Vote::SIZE),
+            Vote::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[voter.clone(), pda_vote.clone(), system_program.clone()],
+        &[&signer_seeds::vote(
+            target.key,
+            voter.key,
+            &[vote_bump_seed],
+        )],
+    )?;
+
+    let vote_data = Vote {
+        discriminator: Vote::DISCRIMINATOR.to_string(),
+        is_initialized: true,
+        voter: *voter.key,
+        target: *target.key,
+    };
+    vote_data.serialize(&mut &mut pda_vote.data.borrow_mut()[..])?;
+
+    msg!("Vote recorded: {}", vote_pda);
+
+    Ok(())
+}
+
+// Grows accounts created before a field was appended, topping up their rent.
+fn realloc_with_rent<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    account_len: usize,
+) -> ProgramResult {
+    if account.data_len() >= account_len {
+        return Ok(());
+    }
+
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(account_len);
+
+    if rent_lamports > account.lamports() {
+        invoke(
+            &system_instruction::transfer(
+                payer.key,
+                account.key,
+                rent_lamports - account.lamports(),
+            ),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    msg!("Reallocating account to {} bytes", account_len);
+    account.realloc(account_len, false)
+}
+
+// The reward accounts are optional; without them the upvote is still counted.
+// Rewards count towards the author's daily cap, the voter pays for the author's
+// reward ledger if it does not exist yet.
+fn mint_upvote_reward<'a, 'b>(
+    program_id: &Pubkey,
+    voter: &AccountInfo<'b>,
+    author: &Pubkey,
+    system_program: &AccountInfo<'b>,
+    account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+) -> ProgramResult {
+    if account_info_iter.len() == 0 {
+        msg!("No reward accounts passed, skipping upvote reward");
+        return Ok(());
+    }
+
+    let token_mint = next_account_info(account_info_iter)?;
+    let mint_auth = next_account_info(account_info_iter)?;
+    let author_ata = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let reward_ledger = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    let (mint_pda, _mint_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_MINT], program_id);
+    let (mint_auth_pda, mint_auth_bump) =
+        Pubkey::find_program_address(&[signer_seeds::TOKEN_AUTH], program_id);
+
+    if *token_mint.key != mint_pda {
+        msg!("Incorrect token mint");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *mint_auth.key != mint_auth_pda {
+        msg!("Mint passed in and mint derived do not match");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if *author_ata.key != get_associated_token_address(author, token_mint.key) {
+        msg!("Incorrect author token account");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *token_program.key != TOKEN_PROGRAM_ID {
+        msg!("Incorrect token program");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if author_ata.data_is_empty() {
+        msg!("Author has no token account, skipping upvote reward");
+        return Ok(());
+    }
+
+    let reward = claim_reward(
+        program_id,
+        voter,
+        author,
+        reward_ledger,
+        reward_config,
+        token_mint,
+        system_program,
+        RewardAction::Upvote,
+    )?;
+
+    if reward > 0 {
+        msg!("Minting {} tokens to author's ATA", reward);
+
+        invoke_signed(
+            &spl_token::instruction::mint_to(
+                token_program.key,
+                token_mint.key,
+                author_ata.key,
+                mint_auth.key,
+                &[],
+                reward,
+            )?,
+            &[token_mint.clone(), author_ata.clone(), mint_auth.clone()],
+            &[&signer_seeds::token_auth(&[mint_auth_bump])],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn initialize_reward_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_supply: u64,
+    daily_cap: u64,
+) -> ProgramResult {
+    msg!("Initializing reward config...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_upgrade_authority(program_id, admin, program_data)?;
+
+    if !reward_config.data_is_empty() {
+        msg!("Reward config already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    create_reward_config(
+        program_id,
+        admin,
+        reward_config,
+        system_program,
+        max_supply,
+        daily_cap,
+    )
+}
+
+// Checks that `authority` signed and is the upgrade authority recorded in this
+// program's ProgramData account. Only it may create the reward mint and appoint
+// the reward config admin.
+fn check_upgrade_authority(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    program_data: &AccountInfo,
+) -> ProgramResult {
+    let (program_data_address, _program_data_bump) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+
+    if *program_data.key != program_data_address {
+        msg!("Incorrect program data account");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+    let data = program_data.data.borrow();
+    let upgrade_authority =
+        match limited_deserialize(&data[..metadata_len.min(data.len())], metadata_len as u64) {
+            Ok(UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address,
+                ..
+            }) => upgrade_authority_address,
+            _ => return Err(ReviewError::CorruptAccountData.into()),
+        };
+
+    if !authority.is_signer || upgrade_authority != Some(*authority.key) {
+        msg!("Signer is not the program's upgrade authority");
+        return Err(ReviewError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+// Creates the reward config with `admin` paying for and administering it,
+// starting from the default reward schedule.
+fn create_reward_config<'a>(
+    program_id: &Pubkey,
+    admin: &AccountInfo<'a>,
+    reward_config: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    max_supply: u64,
+    daily_cap: u64,
+) -> ProgramResult {
+    let (config_pda, config_bump_seed) =
+        Pubkey::find_program_address(&[signer_seeds::REWARD_CONFIG], program_id);
+
+    if *reward_config.key != config_pda {
+        msg!("Invalid seeds for reward config PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    msg!("Creating reward config at {}", config_pda);
+    let rent = Rent::get()?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            reward_config.key,
+            rent.minimum_balance(RewardConfig::SIZE),
+            RewardConfig::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[admin.clone(), reward_config.clone(), system_program.clone()],
+        &[&signer_seeds::reward_config(&[config_bump_seed])],
+    )?;
+
+    let config_data = RewardConfig {
+        discriminator: RewardConfig::DISCRIMINATOR.to_string(),
+        is_initialized: true,
+        max_supply,
+        daily_cap,
+        admin: *admin.key,
+        schedule: RewardSchedule::default(),
+    };
+    config_data.serialize(&mut &mut reward_config.data.borrow_mut()[..])?;
+
+    msg!("Reward supply capped at {}", max_supply);
+    RewardConfigUpdated {
+        admin: config_data.admin,
+        daily_cap: config_data.daily_cap,
+        schedule: config_data.schedule,
+    }
+    .emit();
+
+    Ok(())
+}
+
+pub fn update_reward_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_admin: Pubkey,
+    daily_cap: u64,
+    schedule: RewardSchedule,
+) -> ProgramResult {
+    msg!("Updating reward config...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = next_account_info(account_info_iter)?;
+    let reward_config = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut config_data = load_reward_config(program_id, reward_config)?;
+
+    if config_data.admin != *admin.key {
+        msg!("Only the reward config admin can update it");
+        return Err(ReviewError::Unauthorized.into());
+    }
+
+    config_data.admin = new_admin;
+    config_data.daily_cap = daily_cap;
+    config_data.schedule = schedule;
+    config_data.serialize(&mut &mut reward_config.data.borrow_mut()[..])?;
+
+    RewardConfigUpdated {
+        admin: config_data.admin,
+        daily_cap: config_data.daily_cap,
+        schedule: config_data.schedule,
+    }
+    .emit();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::instruction::{
+            add_comment_ix, add_movie_review_ix, close_movie_review_at_ix, close_movie_review_ix,
+            delete_comment_ix, find_comment_address, find_comment_counter_address,
+            find_mint_address, find_mint_authority_address, find_program_data_address,
+            find_reply_counter_address, find_review_address, find_review_address_candidates,
+            find_reward_config_address, find_reward_ledger_address, find_stats_address,
+            initialize_mint_ix, initialize_reward_config_ix, reply_to_comment_ix,
+            update_comment_ix, update_movie_review_at_ix, update_movie_review_ix,
+            update_reward_config_ix, upvote_review_ix,
+        },
+        assert_matches::*,
+        solana_program::{
+            instruction::{AccountMeta, Instruction, InstructionError},
+            native_token::LAMPORTS_PER_SOL,
+            program_option::COption,
+        },
+        solana_program_test::*,
+        solana_sdk::{
+            account::Account,
+            signature::{Keypair, Signer},
+            signer::keypair::keypair_from_seed,
+            transaction::{Transaction, TransactionError},
+        },
+        spl_associated_token_account::instruction::create_associated_token_account,
+        spl_token::{state::Account as TokenAccount, ID as TOKEN_PROGRAM_ID},
+    };
+
+    // Stands in for the program's upgrade authority, the only key that may
+    // appoint the reward config admin.
+    fn upgrade_authority() -> Keypair {
+        keypair_from_seed(&[7; 32]).unwrap()
+    }
+
+    // Starts with the reward mint in place and the reward config administered
+    // by the upgrade authority, as on a live deployment.
+    fn program_test(program_id: Pubkey) -> ProgramTest {
+        let mut program_test = program_test_without_reward_config(program_id);
+
+        let mut config_data = RewardConfig {
+            discriminator: RewardConfig::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            max_supply: 1_000 * LAMPORTS_PER_SOL,
+            daily_cap: 100 * LAMPORTS_PER_SOL,
+            admin: upgrade_authority().pubkey(),
+            schedule: RewardSchedule::default(),
+        }
+        .try_to_vec()
+        .unwrap();
+        config_data.resize(RewardConfig::SIZE, 0);
+        program_test.add_account(
+            find_reward_config_address(&program_id),
+            Account {
+                lamports: Rent::default().minimum_balance(RewardConfig::SIZE),
+                data: config_data,
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        program_test
+    }
+
+    // Starts with the reward mint in place, as on a deployment that predates
+    // the reward config, so only the tests that create the mint need the token
+    // metadata program.
+    fn program_test_without_reward_config(program_id: Pubkey) -> ProgramTest {
+        let mut program_test = ProgramTest::new(
+            "movie_review_comments",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        let mut mint_data = vec![0; Mint::LEN];
+        Mint {
+            mint_authority: COption::Some(find_mint_authority_address(&program_id)),
+            decimals: 9,
+            is_initialized: true,
+            ..Mint::default()
+        }
+        .pack_into_slice(&mut mint_data);
+        program_test.add_account(
+            find_mint_address(&program_id),
+            Account {
+                lamports: Rent::default().minimum_balance(Mint::LEN),
+                data: mint_data,
+                owner: TOKEN_PROGRAM_ID,
+                ..Account::default()
+            },
+        );
+
+        add_upgrade_authority(&mut program_test, &program_id);
+        program_test
+    }
+
+    // Records `upgrade_authority()` in a ProgramData account for `program_id`
+    // and funds it. Tests load the program natively, so nothing else creates it.
+    fn add_upgrade_authority(program_test: &mut ProgramTest, program_id: &Pubkey) {
+        program_test.add_account(
+            find_program_data_address(program_id),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: program_data(Some(upgrade_authority().pubkey())),
+                owner: bpf_loader_upgradeable::id(),
+                ..Account::default()
+            },
+        );
+        program_test.add_account(
+            upgrade_authority().pubkey(),
+            Account {
+                lamports: 10 * LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+    }
+
+    // ProgramData metadata as the upgradeable loader serializes it with bincode:
+    // the variant index, the last deploy slot and the optional authority.
+    fn program_data(upgrade_authority: Option<Pubkey>) -> Vec<u8> {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        match upgrade_authority {
+            Some(authority) => {
+                data.push(1);
+                data.extend_from_slice(authority.as_ref());
+            }
+            None => data.push(0),
+        }
+        data
+    }
+
+    async fn get_reward_config(
+        banks_client: &mut BanksClient,
+        program_id: &Pubkey,
+    ) -> RewardConfig {
+        let config_account = banks_client
+            .get_account(find_reward_config_address(program_id))
+            .await
+            .unwrap()
+            .unwrap();
+        try_from_slice_unchecked::<RewardConfig>(&config_account.data).unwrap()
+    }
+
+    #[test]
+    fn test_only_the_upgrade_authority_passes_the_check() {
+        let program_id = Pubkey::new_unique();
+        let program_data_key = find_program_data_address(&program_id);
+        let loader = bpf_loader_upgradeable::id();
+        let authority = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let check = |signer: &Pubkey, is_signer: bool, key: &Pubkey, data: Vec<u8>| {
+            let (mut signer_lamports, mut signer_data) = (0, vec![]);
+            let (mut lamports, mut data) = (0, data);
+            let signer = AccountInfo::new(
+                signer,
+                is_signer,
+                false,
+                &mut signer_lamports,
+                &mut signer_data,
+                &SYSTEM_PROGRAM_ID,
+                false,
+                0,
+            );
+            let program_data = AccountInfo::new(
+                key,
+                false,
+                false,
+                &mut lamports,
+                &mut data,
+                &loader,
+                false,
+                0,
+            );
+            check_upgrade_authority(&program_id, &signer, &program_data)
+        };
+
+        assert_eq!(
+            check(
+                &authority,
+                true,
+                &program_data_key,
+                program_data(Some(authority))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                &authority,
+                false,
+                &program_data_key,
+                program_data(Some(authority))
+            ),
+            Err(ReviewError::Unauthorized.into())
+        );
+        assert_eq!(
+            check(
+                &other,
+                true,
+                &program_data_key,
+                program_data(Some(authority))
+            ),
+            Err(ReviewError::Unauthorized.into())
+        );
+        assert_eq!(
+            check(&authority, true, &program_data_key, program_data(None)),
+            Err(ReviewError::Unauthorized.into())
+        );
+        assert_eq!(
+            check(&authority, true, &other, program_data(Some(authority))),
+            Err(ReviewError::IncorrectAccountError.into())
+        );
+        assert_eq!(
+            check(&authority, true, &program_data_key, vec![3, 0, 0]),
+            Err(ReviewError::CorruptAccountData.into())
+        );
+    }
+
+    // The token metadata program is loaded from `tests/fixtures/mpl_token_metadata.so`,
+    // see the README for how to dump it.
+    #[tokio::test]
+    async fn test_initialize_mint_instruction() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let mut program_test = ProgramTest::new(
+            "movie_review_comments",
+            program_id,
+            processor!(process_instruction),
+        );
+        program_test.add_program("mpl_token_metadata", TOKEN_METADATA_PROGRAM_ID, None);
+        add_upgrade_authority(&mut program_test, &program_id);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let init_mint_ix = initialize_mint_ix(
+            &program_id,
+            &authority.pubkey(),
+            9,
+            1_000 * LAMPORTS_PER_SOL,
+            "Movie Review Token",
+            "MRT",
+            "https://arweave.net/movie-review-token.json",
+        );
+
+        let mut txn = Transaction::new_with_payer(&[init_mint_ix], Some(&payer.pubkey()));
+
+        txn.sign(&[&payer, &authority], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let config_data = get_reward_config(&mut banks_client, &program_id).await;
+        assert_eq!(config_data.admin, authority.pubkey());
+        assert_eq!(config_data.max_supply, 1_000 * LAMPORTS_PER_SOL);
+        assert_eq!(config_data.daily_cap, u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_mint_after_the_reward_config_is_gated() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let mut program_test = ProgramTest::new(
+            "movie_review_comments",
+            program_id,
+            processor!(process_instruction),
+        );
+        program_test.add_program("mpl_token_metadata", TOKEN_METADATA_PROGRAM_ID, None);
+        add_upgrade_authority(&mut program_test, &program_id);
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let init_mint_ix = |initializer: &Pubkey, max_supply| {
+            initialize_mint_ix(
+                &program_id,
+                initializer,
+                9,
+                max_supply,
+                "Movie Review Token",
+                "MRT",
+                "https://arweave.net/movie-review-token.json",
+            )
+        };
+
+        let mut txn = Transaction::new_with_payer(
+            &[initialize_reward_config_ix(
+                &program_id,
+                &authority.pubkey(),
+                1_000 * LAMPORTS_PER_SOL,
+                15 * LAMPORTS_PER_SOL,
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let intruder = Keypair::new();
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            init_mint_ix(&intruder.pubkey(), 1_000 * LAMPORTS_PER_SOL),
+            ReviewError::Unauthorized,
+        )
+        .await;
+
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &authority,
+            init_mint_ix(&authority.pubkey(), u64::MAX),
+            ReviewError::MaxSupplyMismatch,
+        )
+        .await;
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[init_mint_ix(&authority.pubkey(), 1_000 * LAMPORTS_PER_SOL)],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let config_data = get_reward_config(&mut banks_client, &program_id).await;
+        assert_eq!(config_data.daily_cap, 15 * LAMPORTS_PER_SOL);
+    }
+
+    #[tokio::test]
+    async fn test_reward_config_is_initialized_by_the_upgrade_authority() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let (mut banks_client, payer, _) =
+            program_test_without_reward_config(program_id).start().await;
+
+        let intruder = Keypair::new();
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            initialize_reward_config_ix(&program_id, &intruder.pubkey(), u64::MAX, u64::MAX),
+            ReviewError::Unauthorized,
+        )
+        .await;
+
+        let init_config_ix = initialize_reward_config_ix(
+            &program_id,
+            &authority.pubkey(),
+            1_000 * LAMPORTS_PER_SOL,
+            15 * LAMPORTS_PER_SOL,
+        );
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(&[init_config_ix], Some(&payer.pubkey()));
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let config_data = get_reward_config(&mut banks_client, &program_id).await;
+        assert_eq!(config_data.admin, authority.pubkey());
+        assert_eq!(config_data.max_supply, 1_000 * LAMPORTS_PER_SOL);
+        assert_eq!(config_data.daily_cap, 15 * LAMPORTS_PER_SOL);
+        assert_eq!(config_data.schedule, RewardSchedule::default());
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[initialize_reward_config_ix(
+                &program_id,
+                &authority.pubkey(),
+                u64::MAX,
+                u64::MAX,
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_movie_review_instruction() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+
+        let init_ata_ix: Instruction = create_associated_token_account(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint,
+            &TOKEN_PROGRAM_ID,
+        );
+
+        let add_review_ix = add_movie_review_ix(
+            &program_id,
+            &payer.pubkey(),
+            "Captain America",
+            3,
+            "Liked the movie",
+        );
+
+        let mut txn =
+            Transaction::new_with_payer(&[init_ata_ix, add_review_ix], Some(&payer.pubkey()));
+
+        txn.sign(&[&payer], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+    }
+
+    #[tokio::test]
+    async fn test_rewards_stop_at_max_supply() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let (mut banks_client, payer, recent_blockhash) =
+            program_test_without_reward_config(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                initialize_reward_config_ix(
+                    &program_id,
+                    &authority.pubkey(),
+                    15 * LAMPORTS_PER_SOL,
+                    100 * LAMPORTS_PER_SOL,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Iron Man", 5, "Great"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 10 * LAMPORTS_PER_SOL);
+    }
+
+    #[tokio::test]
+    async fn test_rewards_stop_at_daily_cap() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let (mut banks_client, payer, recent_blockhash) =
+            program_test_without_reward_config(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                initialize_reward_config_ix(
+                    &program_id,
+                    &authority.pubkey(),
+                    1_000 * LAMPORTS_PER_SOL,
+                    15 * LAMPORTS_PER_SOL,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Iron Man", 5, "Great"),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Thor", 4, "Good"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 15 * LAMPORTS_PER_SOL);
+    }
+
+    #[tokio::test]
+    async fn test_reward_schedule_is_admin_only() {
+        let program_id = Pubkey::new_unique();
+        let admin = upgrade_authority();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+        let schedule = RewardSchedule {
+            review: 2,
+            ..RewardSchedule::default()
+        };
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                update_reward_config_ix(
+                    &program_id,
+                    &admin.pubkey(),
+                    &admin.pubkey(),
+                    100 * LAMPORTS_PER_SOL,
+                    schedule,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &admin], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 2 * LAMPORTS_PER_SOL);
+
+        let intruder = Keypair::new();
+        let mut txn = Transaction::new_with_payer(
+            &[update_reward_config_ix(
+                &program_id,
+                &intruder.pubkey(),
+                &intruder.pubkey(),
+                u64::MAX,
+                schedule,
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &intruder], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ReviewError::Unauthorized as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_comment_rewards_are_minted() {
+        let program_id = Pubkey::new_unique();
+        let commenter = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            commenter.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let commenter_ata = get_associated_token_address(&commenter.pubkey(), &mint);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &commenter.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &commenter.pubkey(), &review, 0, "Agreed"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &commenter], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let ata_account = banks_client
+            .get_account(commenter_ata)
+            .await
+            .unwrap()
+            .unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 5 * LAMPORTS_PER_SOL);
+    }
+
+    #[tokio::test]
+    async fn test_comments_record_their_index() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 0, "First"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 1, "Second"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        for index in 0..2 {
+            let comment_account = banks_client
+                .get_account(find_comment_address(&program_id, &review, index))
+                .await
+                .unwrap()
+                .unwrap();
+            let comment_data =
+                try_from_slice_unchecked::<MovieComment>(&comment_account.data).unwrap();
+            assert_eq!(comment_data.count, index);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_self_comments_earn_no_reward() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 0, "Agreed"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer], recent_blockhash);
+
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 10 * LAMPORTS_PER_SOL);
+    }
+
+    #[tokio::test]
+    async fn test_upvotes_are_counted_once_per_voter() {
+        let program_id = Pubkey::new_unique();
+        let voter = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            voter.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let upvote_ix = upvote_review_ix(&program_id, &voter.pubkey(), &review, &payer.pubkey());
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                upvote_ix.clone(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &voter], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let review_account = banks_client.get_account(review).await.unwrap().unwrap();
+        let review_data =
+            try_from_slice_unchecked::<MovieAccountState>(&review_account.data).unwrap();
+        assert_eq!(review_data.upvotes, 1);
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(&[upvote_ix], Some(&payer.pubkey()));
+        txn.sign(&[&payer, &voter], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ReviewError::AlreadyVoted as u32)
+            )
+        );
+
+        let mut txn = Transaction::new_with_payer(
+            &[upvote_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                &review,
+                &payer.pubkey(),
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ReviewError::SelfVote as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upvote_rewards_count_towards_the_daily_cap() {
+        let program_id = Pubkey::new_unique();
+        let voters: Vec<Keypair> = (0..Vote::REWARD_THRESHOLD)
+            .map(|_| Keypair::new())
+            .collect();
+        let mut program_test = program_test_without_reward_config(program_id);
+        for voter in &voters {
+            program_test.add_account(
+                voter.pubkey(),
+                Account {
+                    lamports: LAMPORTS_PER_SOL,
+                    ..Account::default()
+                },
+            );
+        }
+        let (mut banks_client, payer, _) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+
+        // The review reward takes 10 of the 10.5 tokens the author may earn today.
+        let authority = upgrade_authority();
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[initialize_reward_config_ix(
+                &program_id,
+                &authority.pubkey(),
+                1_000 * LAMPORTS_PER_SOL,
+                10 * LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2,
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+            ],
+        )
+        .await;
+
+        for voter in &voters {
+            let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+            let mut txn = Transaction::new_with_payer(
+                &[upvote_review_ix(
+                    &program_id,
+                    &voter.pubkey(),
+                    &review,
+                    &payer.pubkey(),
+                )],
+                Some(&payer.pubkey()),
+            );
+            txn.sign(&[&payer, voter], recent_blockhash);
+            assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+        }
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(
+            token_account.amount,
+            10 * LAMPORTS_PER_SOL + LAMPORTS_PER_SOL / 2
+        );
+
+        let ledger_account = banks_client
+            .get_account(find_reward_ledger_address(&program_id, &payer.pubkey()))
+            .await
+            .unwrap()
+            .unwrap();
+        let ledger_data = try_from_slice_unchecked::<RewardLedger>(&ledger_account.data).unwrap();
+        assert_eq!(ledger_data.rewarded, token_account.amount);
+    }
+
+    // An AddComment on `review` that passes `counter` in place of the review's
+    // comment counter.
+    fn add_comment_with_counter(
+        program_id: &Pubkey,
+        commenter: &Pubkey,
+        review: &Pubkey,
+        counter: Pubkey,
+    ) -> Instruction {
+        let mut ix = add_comment_ix(program_id, commenter, review, 1, "Hijacked");
+        ix.accounts[2].pubkey = counter;
+        ix
+    }
+
+    #[tokio::test]
+    async fn test_replies_are_threaded_under_their_parent() {
+        let program_id = Pubkey::new_unique();
+        let replier = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            replier.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let payer_ata = get_associated_token_address(&payer.pubkey(), &mint);
+        let replier_ata = get_associated_token_address(&replier.pubkey(), &mint);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let other_review = find_review_address(&program_id, &payer.pubkey(), "Iron Man");
+        let parent = find_comment_address(&program_id, &review, 0);
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &replier.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Iron Man", 5, "Great"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 0, "Any thoughts?"),
+                reply_to_comment_ix(
+                    &program_id,
+                    &replier.pubkey(),
+                    &review,
+                    &parent,
+                    0,
+                    "Agreed",
+                ),
+                reply_to_comment_ix(
+                    &program_id,
+                    &replier.pubkey(),
+                    &review,
+                    &parent,
+                    1,
+                    "Still agreed",
+                ),
+                reply_to_comment_ix(&program_id, &payer.pubkey(), &review, &parent, 2, "Thanks"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &replier], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let counter_account = banks_client
+            .get_account(find_reply_counter_address(&program_id, &parent))
+            .await
+            .unwrap()
+            .unwrap();
+        let counter_data =
+            try_from_slice_unchecked::<MovieCommentCounter>(&counter_account.data).unwrap();
+        assert_eq!(counter_data.counter, 3);
+
+        for index in 0..3 {
+            let reply_account = banks_client
+                .get_account(find_comment_address(&program_id, &parent, index))
+                .await
+                .unwrap()
+                .unwrap();
+            let reply_data = try_from_slice_unchecked::<MovieComment>(&reply_account.data).unwrap();
+            assert_eq!(reply_data.count, index);
+            assert_eq!(reply_data.parent, parent);
+            assert_eq!(reply_data.review, review);
+            assert!(!reply_data.is_deleted);
+        }
+
+        // Two replies to someone else's comment earn the reply reward twice,
+        // replying in your own thread earns nothing.
+        let ata_account = banks_client
+            .get_account(replier_ata)
+            .await
+            .unwrap()
+            .unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 10 * LAMPORTS_PER_SOL);
+        let ata_account = banks_client.get_account(payer_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 20 * LAMPORTS_PER_SOL);
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let rejected = [
+            (
+                reply_to_comment_ix(&program_id, &replier.pubkey(), &review, &parent, 1, "Again"),
+                ReviewError::InvalidPDA,
+            ),
+            (
+                reply_to_comment_ix(
+                    &program_id,
+                    &replier.pubkey(),
+                    &other_review,
+                    &parent,
+                    3,
+                    "Wrong thread",
+                ),
+                ReviewError::IncorrectAccountError,
+            ),
+            (
+                add_comment_with_counter(
+                    &program_id,
+                    &replier.pubkey(),
+                    &review,
+                    find_reply_counter_address(&program_id, &parent),
+                ),
+                ReviewError::InvalidPDA,
+            ),
+            (
+                add_comment_with_counter(
+                    &program_id,
+                    &replier.pubkey(),
+                    &review,
+                    find_comment_counter_address(&program_id, &other_review),
+                ),
+                ReviewError::InvalidPDA,
+            ),
+        ];
+        for (ix, expected) in rejected {
+            let mut txn = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+            txn.sign(&[&payer, &replier], recent_blockhash);
+            let err = banks_client
+                .process_transaction(txn)
+                .await
+                .unwrap_err()
+                .unwrap();
+            assert_eq!(
+                err,
+                TransactionError::InstructionError(0, InstructionError::Custom(expected as u32))
+            );
+        }
+    }
+
+    async fn assert_custom_error(
+        banks_client: &mut BanksClient,
+        payer: &Keypair,
+        signer: &Keypair,
+        ix: Instruction,
+        expected: ReviewError,
+    ) {
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+        txn.sign(&[payer, signer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(0, InstructionError::Custom(expected as u32))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_the_author_can_edit_or_delete_a_comment() {
+        let program_id = Pubkey::new_unique();
+        let commenter = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            commenter.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let comment = find_comment_address(&program_id, &review, 0);
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &commenter.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &commenter.pubkey(), &review, 0, "Agreed"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &commenter], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        // The review's author signs in place of the comment's author.
+        let intruder = Keypair::new();
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            update_comment_ix(&program_id, &intruder.pubkey(), &comment, "Hijacked"),
+            ReviewError::Unauthorized,
+        )
+        .await;
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            delete_comment_ix(&program_id, &intruder.pubkey(), &comment),
+            ReviewError::Unauthorized,
+        )
+        .await;
+
+        let comment_account = banks_client.get_account(comment).await.unwrap().unwrap();
+        let comment_data = try_from_slice_unchecked::<MovieComment>(&comment_account.data).unwrap();
+        assert_eq!(comment_data.comment, "Agreed");
+    }
+
+    #[tokio::test]
+    async fn test_comment_edits_resize_and_deletes_leave_a_tombstone() {
+        let program_id = Pubkey::new_unique();
+        let commenter = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            commenter.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let comment = find_comment_address(&program_id, &review, 0);
+        let rent = banks_client.get_rent().await.unwrap();
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &commenter.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &commenter.pubkey(), &review, 0, "Agreed"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &commenter], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        // Each step is paid for by `payer`, so the commenter's balance moves
+        // only by the rent the comment account gains or gives back.
+        let steps = [
+            (
+                update_comment_ix(
+                    &program_id,
+                    &commenter.pubkey(),
+                    &comment,
+                    "Agreed, and the soundtrack was even better than the plot",
+                ),
+                "Agreed, and the soundtrack was even better than the plot",
+                false,
+            ),
+            (
+                update_comment_ix(&program_id, &commenter.pubkey(), &comment, "Meh"),
+                "Meh",
+                false,
+            ),
+            (
+                delete_comment_ix(&program_id, &commenter.pubkey(), &comment),
+                "",
+                true,
+            ),
+        ];
+        for (ix, expected_comment, expected_deleted) in steps {
+            let comment_before = banks_client.get_account(comment).await.unwrap().unwrap();
+            let commenter_before = banks_client.get_balance(commenter.pubkey()).await.unwrap();
+
+            let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+            let mut txn = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+            txn.sign(&[&payer, &commenter], recent_blockhash);
+            assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+            let comment_after = banks_client.get_account(comment).await.unwrap().unwrap();
+            let commenter_after = banks_client.get_balance(commenter.pubkey()).await.unwrap();
+            let account_len = MovieComment::get_account_size(expected_comment.to_string());
+            assert_eq!(comment_after.data.len(), account_len);
+            assert_eq!(comment_after.lamports, rent.minimum_balance(account_len));
+            assert_eq!(
+                commenter_before as i128 - commenter_after as i128,
+                comment_after.lamports as i128 - comment_before.lamports as i128
+            );
+
+            let comment_data =
+                try_from_slice_unchecked::<MovieComment>(&comment_after.data).unwrap();
+            assert_eq!(comment_data.comment, expected_comment);
+            assert_eq!(comment_data.is_deleted, expected_deleted);
+            assert_eq!(comment_data.commenter, commenter.pubkey());
+            assert_eq!(comment_data.count, 0);
+        }
+
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &commenter,
+            update_comment_ix(&program_id, &commenter.pubkey(), &comment, "Back again"),
+            ReviewError::CommentDeleted,
+        )
+        .await;
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &commenter,
+            delete_comment_ix(&program_id, &commenter.pubkey(), &comment),
+            ReviewError::CommentDeleted,
+        )
+        .await;
+    }
+
+    async fn process(banks_client: &mut BanksClient, payer: &Keypair, ixs: &[Instruction]) {
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(ixs, Some(&payer.pubkey()));
+        txn.sign(&[payer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+    }
+
+    #[tokio::test]
+    async fn test_closed_review_title_can_be_reused() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, _) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let counter = find_comment_counter_address(&program_id, &review);
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+            ],
+        )
+        .await;
+
+        let review_account = banks_client.get_account(review).await.unwrap().unwrap();
+        let counter_account = banks_client.get_account(counter).await.unwrap().unwrap();
+        let balance_before = banks_client.get_balance(payer.pubkey()).await.unwrap();
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[close_movie_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                "Captain America",
+            )],
+        )
+        .await;
+
+        // Without comments both the review and its counter are closed.
+        assert_eq!(banks_client.get_account(review).await.unwrap(), None);
+        assert_eq!(banks_client.get_account(counter).await.unwrap(), None);
+        let balance_after = banks_client.get_balance(payer.pubkey()).await.unwrap();
+        assert!(balance_after > balance_before);
+        assert!(
+            balance_after - balance_before <= review_account.lamports + counter_account.lamports
+        );
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[add_movie_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                "Captain America",
+                5,
+                "Better the second time",
+            )],
+        )
+        .await;
+
+        let review_account = banks_client.get_account(review).await.unwrap().unwrap();
+        let review_data =
+            try_from_slice_unchecked::<MovieAccountState>(&review_account.data).unwrap();
+        assert_eq!(review_data.rating, 5);
+    }
+
+    #[tokio::test]
+    async fn test_reopened_review_keeps_its_comment_counter() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, _) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let counter = find_comment_counter_address(&program_id, &review);
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 0, "First"),
+            ],
+        )
+        .await;
+        process(
+            &mut banks_client,
+            &payer,
+            &[close_movie_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                "Captain America",
+            )],
+        )
+        .await;
+
+        assert_eq!(banks_client.get_account(review).await.unwrap(), None);
+        let counter_account = banks_client.get_account(counter).await.unwrap().unwrap();
+        let counter_data =
+            try_from_slice_unchecked::<MovieCommentCounter>(&counter_account.data).unwrap();
+        assert_eq!(counter_data.counter, 1);
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 4, "Again"),
+                add_comment_ix(&program_id, &payer.pubkey(), &review, 1, "Second"),
+            ],
+        )
+        .await;
+
+        for (index, expected) in [(0, "First"), (1, "Second")] {
+            let comment_account = banks_client
+                .get_account(find_comment_address(&program_id, &review, index))
+                .await
+                .unwrap()
+                .unwrap();
+            let comment_data =
+                try_from_slice_unchecked::<MovieComment>(&comment_account.data).unwrap();
+            assert_eq!(comment_data.comment, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_is_reviewer_only_and_checks_the_discriminator() {
+        let program_id = Pubkey::new_unique();
+        let intruder = Keypair::new();
+        let not_a_review = Pubkey::new_unique();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            not_a_review,
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: MovieAccountState {
+                    discriminator: MovieComment::DISCRIMINATOR.to_string(),
+                    is_initialized: true,
+                    rating: 3,
+                    title: "Captain America".to_string(),
+                    description: "Fine".to_string(),
+                    reviewer: intruder.pubkey(),
+                    upvotes: 0,
+                    counted_in_stats: false,
+                }
+                .try_to_vec()
+                .unwrap(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, _) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+            ],
+        )
+        .await;
+
+        let mut ix = close_movie_review_ix(&program_id, &payer.pubkey(), "Captain America");
+        ix.accounts[0] = AccountMeta::new(intruder.pubkey(), true);
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            ix.clone(),
+            ReviewError::Unauthorized,
+        )
+        .await;
+
+        // A program-owned account that is not a review, even one naming the
+        // signer as its reviewer, cannot be closed through this instruction.
+        ix.accounts[1] = AccountMeta::new(not_a_review, false);
+        assert_custom_error(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            ix,
+            ReviewError::UninitializedAccount,
+        )
+        .await;
+
+        assert!(banks_client.get_account(review).await.unwrap().is_some());
+        assert!(banks_client
+            .get_account(not_a_review)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_review_accounts_are_sized_to_their_content() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, _) = program_test(program_id).start().await;
+
+        let mint = find_mint_address(&program_id);
+        let review = find_review_address(&program_id, &payer.pubkey(), "Captain America");
+        let rent = banks_client.get_rent().await.unwrap();
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+            ],
+        )
+        .await;
+
+        let long_description = "So much to say about this one. ".repeat(20);
+        for description in ["Fine", long_description.as_str(), "Meh"] {
+            if description != "Fine" {
+                process(
+                    &mut banks_client,
+                    &payer,
+                    &[update_movie_review_ix(
+                        &program_id,
+                        &payer.pubkey(),
+                        "Captain America",
+                        4,
+                        description,
+                    )],
+                )
+                .await;
+            }
+
+            let review_account = banks_client.get_account(review).await.unwrap().unwrap();
+            let account_len = MovieAccountState::get_account_size(
+                "Captain America".to_string(),
+                description.to_string(),
+            );
+            assert_eq!(review_account.data.len(), account_len);
+            assert_eq!(review_account.lamports, rent.minimum_balance(account_len));
+            let review_data =
+                try_from_slice_unchecked::<MovieAccountState>(&review_account.data).unwrap();
+            assert_eq!(review_data.description, description);
+        }
+
+        // One byte over the limit, which still fits in a transaction.
+        let too_long = "x".repeat(
+            MovieAccountState::MAX_ACCOUNT_SIZE + 1
+                - MovieAccountState::get_account_size("Captain America".to_string(), String::new()),
+        );
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[update_movie_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                "Captain America",
+                5,
+                &too_long,
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(ReviewError::InvalidDataLength as u32)
+            )
+        );
+    }
+
+    async fn get_stats(
+        banks_client: &mut BanksClient,
+        program_id: &Pubkey,
+        title: &str,
+    ) -> Option<MovieStats> {
+        banks_client
+            .get_account(find_stats_address(program_id, title))
+            .await
+            .unwrap()
+            .map(|account| try_from_slice_unchecked::<MovieStats>(&account.data).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_stats_follow_reviews_being_added_updated_and_closed() {
+        let program_id = Pubkey::new_unique();
+        let other_reviewer = Keypair::new();
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            other_reviewer.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &other_reviewer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 3, "Fine"),
+                // Titles are normalized, so both reviews share one stats account.
+                add_movie_review_ix(
+                    &program_id,
+                    &other_reviewer.pubkey(),
+                    "  captain AMERICA ",
+                    5,
+                    "Great",
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &other_reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let stats = get_stats(&mut banks_client, &program_id, "Captain America")
+            .await
+            .unwrap();
+        assert_eq!(stats.review_count, 2);
+        assert_eq!(stats.rating_sum, 8);
+        assert_eq!(stats.rating_histogram, [0, 0, 1, 0, 1]);
+
+        process(
+            &mut banks_client,
+            &payer,
+            &[update_movie_review_ix(
+                &program_id,
+                &payer.pubkey(),
+                "Captain America",
+                4,
+                "Grew on me",
+            )],
+        )
+        .await;
+
+        let stats = get_stats(&mut banks_client, &program_id, "Captain America")
+            .await
+            .unwrap();
+        assert_eq!(stats.review_count, 2);
+        assert_eq!(stats.rating_sum, 9);
+        assert_eq!(stats.rating_histogram, [0, 0, 0, 1, 1]);
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[close_movie_review_ix(
+                &program_id,
+                &other_reviewer.pubkey(),
+                "  captain AMERICA ",
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &other_reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let stats = get_stats(&mut banks_client, &program_id, "Captain America")
+            .await
+            .unwrap();
+        assert_eq!(stats.review_count, 1);
+        assert_eq!(stats.rating_sum, 4);
+        assert_eq!(stats.rating_histogram, [0, 0, 0, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_reviews_without_stats_can_be_updated_and_closed() {
+        let program_id = Pubkey::new_unique();
+        let reviewer = Keypair::new();
+        let review = find_review_address(&program_id, &reviewer.pubkey(), "Captain America");
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            reviewer.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        // A review posted before stats were tracked, with its comment counter.
+        program_test.add_account(
+            review,
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: MovieAccountState {
+                    discriminator: MovieAccountState::DISCRIMINATOR.to_string(),
+                    is_initialized: true,
+                    rating: 3,
+                    title: "Captain America".to_string(),
+                    description: "Fine".to_string(),
+                    reviewer: reviewer.pubkey(),
+                    upvotes: 0,
+                    counted_in_stats: false,
+                }
+                .try_to_vec()
+                .unwrap(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        program_test.add_account(
+            find_comment_counter_address(&program_id, &review),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: MovieCommentCounter {
+                    discriminator: MovieCommentCounter::DISCRIMINATOR.to_string(),
+                    is_initialized: true,
+                    counter: 0,
+                }
+                .try_to_vec()
+                .unwrap(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                update_movie_review_ix(
+                    &program_id,
+                    &reviewer.pubkey(),
+                    "Captain America",
+                    4,
+                    "Grew on me",
+                ),
+                close_movie_review_ix(&program_id, &reviewer.pubkey(), "Captain America"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        assert_eq!(banks_client.get_account(review).await.unwrap(), None);
+        assert!(get_stats(&mut banks_client, &program_id, "Captain America")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_legacy_raw_title_reviews_can_be_updated_and_closed() {
+        let program_id = Pubkey::new_unique();
+        let reviewer = Keypair::new();
+        let candidates =
+            find_review_address_candidates(&program_id, &reviewer.pubkey(), "Captain America");
+        let review = candidates[1];
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            reviewer.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        // Created with the raw title seed, the fixed 1000-byte allocation and
+        // no upvote tally.
+        let mut data = (
+            MovieAccountState::DISCRIMINATOR.to_string(),
+            true,
+            3u8,
+            "Captain America".to_string(),
+            "Fine".to_string(),
+            reviewer.pubkey(),
+        )
+            .try_to_vec()
+            .unwrap();
+        data.resize(1000, 0);
+        program_test.add_account(
+            review,
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data,
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        program_test.add_account(
+            find_comment_counter_address(&program_id, &review),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: MovieCommentCounter {
+                    discriminator: MovieCommentCounter::DISCRIMINATOR.to_string(),
+                    is_initialized: true,
+                    counter: 0,
+                }
+                .try_to_vec()
+                .unwrap(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mut txn = Transaction::new_with_payer(
+            &[update_movie_review_at_ix(
+                &program_id,
+                &reviewer.pubkey(),
+                &review,
+                "Captain America",
+                4,
+                "Grew on me",
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let review_account = banks_client.get_account(review).await.unwrap().unwrap();
+        assert_eq!(
+            review_account.data.len(),
+            MovieAccountState::get_account_size(
+                "Captain America".to_string(),
+                "Grew on me".to_string()
+            )
+        );
+        let review_data =
+            try_from_slice_unchecked::<MovieAccountState>(&review_account.data).unwrap();
+        assert_eq!(review_data.rating, 4);
+        assert_eq!(review_data.upvotes, 0);
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[close_movie_review_at_ix(
+                &program_id,
+                &reviewer.pubkey(),
+                &review,
+                "Captain America",
+            )],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        assert_eq!(banks_client.get_account(review).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_reviews_stay_out_of_stats_created_later() {
+        let program_id = Pubkey::new_unique();
+        let reviewer = Keypair::new();
+        let legacy_review =
+            find_review_address_candidates(&program_id, &reviewer.pubkey(), "Captain America")[1];
+        let mut program_test = program_test(program_id);
+        program_test.add_account(
+            reviewer.pubkey(),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        // Posted before stats were tracked, so its rating was never counted.
+        let mut data = (
+            MovieAccountState::DISCRIMINATOR.to_string(),
+            true,
+            3u8,
+            "Captain America".to_string(),
+            "Fine".to_string(),
+            reviewer.pubkey(),
+        )
+            .try_to_vec()
+            .unwrap();
+        data.resize(1000, 0);
+        program_test.add_account(
+            legacy_review,
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data,
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        program_test.add_account(
+            find_comment_counter_address(&program_id, &legacy_review),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: MovieCommentCounter {
+                    discriminator: MovieCommentCounter::DISCRIMINATOR.to_string(),
+                    is_initialized: true,
+                    counter: 0,
+                }
+                .try_to_vec()
+                .unwrap(),
+                owner: program_id,
+                ..Account::default()
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let mint = find_mint_address(&program_id);
+        let mut txn = Transaction::new_with_payer(
+            &[
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                add_movie_review_ix(&program_id, &payer.pubkey(), "Captain America", 5, "Great"),
+                update_movie_review_at_ix(
+                    &program_id,
+                    &reviewer.pubkey(),
+                    &legacy_review,
+                    "Captain America",
+                    1,
+                    "Worse on rewatch",
+                ),
+                close_movie_review_at_ix(
+                    &program_id,
+                    &reviewer.pubkey(),
+                    &legacy_review,
+                    "Captain America",
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &reviewer], recent_blockhash);
+        assert_matches!(banks_client.process_transaction(txn).await, Ok(_));
+
+        let stats = get_stats(&mut banks_client, &program_id, "Captain America")
+            .await
+            .unwrap();
+        assert_eq!(stats.review_count, 1);
+        assert_eq!(stats.rating_sum, 5);
+        assert_eq!(stats.rating_histogram, [0, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_payloads_are_rejected() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let review_payload = (
+            "Captain America".to_owned(),
+            3u8,
+            "Liked the movie".to_owned(),
+        )
+            .try_to_vec()
+            .unwrap();
+        let comment_payload = "Great review".to_owned().try_to_vec().unwrap();
+        let mint_payload = (
+            9u8,
+            1_000 * LAMPORTS_PER_SOL,
+            100 * LAMPORTS_PER_SOL,
+            "Movie Review Token".to_owned(),
+            "MRT".to_owned(),
+            "https://arweave.net/movie-review-token.json".to_owned(),
+        )
+            .try_to_vec()
+            .unwrap();
+
+        let cases: [(u8, &Vec<u8>); 6] = [
+            (0, &review_payload),
+            (1, &review_payload),
+            (2, &comment_payload),
+            (3, &mint_payload),
+            (4, &comment_payload),
+            (5, &comment_payload),
+        ];
+
+        for (variant, payload) in cases {
+            let mut malformed: Vec<Vec<u8>> = (0..payload.len())
+                .map(|len| payload[..len].to_vec())
+                .collect();
+            let mut oversized = payload.clone();
+            oversized.extend_from_slice(&[0xff; 16]);
+            malformed.push(oversized);
+
+            for data in malformed {
+                let mut instruction_data = vec![variant];
+                instruction_data.extend_from_slice(&data);
+
+                let mut txn = Transaction::new_with_payer(
+                    &[Instruction {
+                        program_id,
+                        accounts: vec![AccountMeta::new(payer.pubkey(), true)],
+                        data: instruction_data,
+                    }],
+                    Some(&payer.pubkey()),
+                );
+                txn.sign(&[&payer], recent_blockhash);
+
+                let err = banks_client
+                    .process_transaction(txn)
+                    .await
+                    .unwrap_err()
+                    .unwrap();
+                assert_eq!(
+                    err,
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(ReviewError::InvalidInstructionPayload as u32)
+                    )
+                );
+            }
+        }
     }
 }
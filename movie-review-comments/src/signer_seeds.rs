@@ -0,0 +1,228 @@
+//! Every PDA seed used by the program. Addresses are derived from the seed
+//! constants and CPIs sign with the matching helper, so the two cannot drift
+//! apart.
+
+use solana_program::pubkey::{Pubkey, MAX_SEED_LEN};
+
+pub const TOKEN_MINT: &[u8] = b"token_mint";
+pub const TOKEN_AUTH: &[u8] = b"token_auth";
+pub const COMMENT: &[u8] = b"comment";
+pub const REPLY: &[u8] = b"reply";
+pub const STATS: &[u8] = b"stats";
+pub const VOTE: &[u8] = b"vote";
+pub const REWARD_CONFIG: &[u8] = b"reward_config";
+pub const REWARD_LEDGER: &[u8] = b"reward_ledger";
+
+/// The reward mint.
+pub fn token_mint(bump: &[u8; 1]) -> [&[u8]; 2] {
+    [TOKEN_MINT, bump]
+}
+
+/// The reward mint's mint authority.
+pub fn token_auth(bump: &[u8; 1]) -> [&[u8]; 2] {
+    [TOKEN_AUTH, bump]
+}
+
+pub fn review<'a>(
+    reviewer: &'a Pubkey,
+    title_hash: &'a [u8; 32],
+    bump: &'a [u8; 1],
+) -> [&'a [u8]; 3] {
+    [reviewer.as_ref(), title_hash, bump]
+}
+
+/// The raw title seed reviews were created with before seeds used the title
+/// hash, or `None` if the title is too long to have ever been a seed. Only for
+/// looking those reviews up, never for creating new ones.
+pub fn legacy_review_title(title: &str) -> Option<&[u8]> {
+    Some(title.as_bytes()).filter(|seed| seed.len() <= MAX_SEED_LEN)
+}
+
+pub fn comment_counter<'a>(review: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [review.as_ref(), COMMENT, bump]
+}
+
+pub fn reply_counter<'a>(parent: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [parent.as_ref(), REPLY, bump]
+}
+
+/// Comment and reply PDAs are seeded with the index encoded big-endian. This is
+/// the canonical encoding for every movie review program; comments created by
+/// `anchor-movie-review` before it adopted the same encoding are seeded with
+/// `legacy_comment_index`.
+pub fn comment_index(index: u64) -> [u8; 8] {
+    index.to_be_bytes()
+}
+
+/// The little-endian index used by older `anchor-movie-review` comments. Only
+/// for looking those up, never for creating new comments.
+pub fn legacy_comment_index(index: u64) -> [u8; 8] {
+    index.to_le_bytes()
+}
+
+/// A comment under `parent`, either a review or the comment being replied to.
+/// `index` comes from `comment_index`.
+pub fn comment<'a>(parent: &'a Pubkey, index: &'a [u8; 8], bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [parent.as_ref(), index, bump]
+}
+
+pub fn stats<'a>(title_hash: &'a [u8; 32], bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [STATS, title_hash, bump]
+}
+
+pub fn vote<'a>(target: &'a Pubkey, voter: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 4] {
+    [VOTE, target.as_ref(), voter.as_ref(), bump]
+}
+
+pub fn reward_config(bump: &[u8; 1]) -> [&[u8]; 2] {
+    [REWARD_CONFIG, bump]
+}
+
+pub fn reward_ledger<'a>(user: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [REWARD_LEDGER, user.as_ref(), bump]
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{instruction::*, title::title_hash},
+    };
+
+    // Each signer helper, fed the canonical bump, must recreate the address the
+    // instruction builders pass in and the processor checks against.
+    fn signs_for(seeds: &[&[u8]], program_id: &Pubkey, expected: Pubkey) {
+        assert_eq!(
+            Pubkey::create_program_address(seeds, program_id).unwrap(),
+            expected
+        );
+    }
+
+    fn bump(seeds: &[&[u8]], program_id: &Pubkey) -> [u8; 1] {
+        [Pubkey::find_program_address(seeds, program_id).1]
+    }
+
+    #[test]
+    fn test_mint_seeds() {
+        let program_id = Pubkey::new_unique();
+
+        let mint_bump = bump(&[TOKEN_MINT], &program_id);
+        signs_for(
+            &token_mint(&mint_bump),
+            &program_id,
+            find_mint_address(&program_id),
+        );
+
+        let auth_bump = bump(&[TOKEN_AUTH], &program_id);
+        signs_for(
+            &token_auth(&auth_bump),
+            &program_id,
+            find_mint_authority_address(&program_id),
+        );
+
+        assert_ne!(
+            find_mint_address(&program_id),
+            find_mint_authority_address(&program_id)
+        );
+    }
+
+    #[test]
+    fn test_review_seeds() {
+        let program_id = Pubkey::new_unique();
+        let reviewer = Pubkey::new_unique();
+        let hash = title_hash("Captain America");
+
+        let review_bump = bump(&[reviewer.as_ref(), &hash], &program_id);
+        let review_address = find_review_address(&program_id, &reviewer, "Captain America");
+        signs_for(
+            &review(&reviewer, &hash, &review_bump),
+            &program_id,
+            review_address,
+        );
+
+        let stats_bump = bump(&[STATS, &hash], &program_id);
+        signs_for(
+            &stats(&hash, &stats_bump),
+            &program_id,
+            find_stats_address(&program_id, "Captain America"),
+        );
+
+        let counter_bump = bump(&[review_address.as_ref(), COMMENT], &program_id);
+        signs_for(
+            &comment_counter(&review_address, &counter_bump),
+            &program_id,
+            find_comment_counter_address(&program_id, &review_address),
+        );
+    }
+
+    #[test]
+    fn test_legacy_review_candidates() {
+        let program_id = Pubkey::new_unique();
+        let reviewer = Pubkey::new_unique();
+
+        let candidates = find_review_address_candidates(&program_id, &reviewer, "Inception");
+        assert_eq!(
+            candidates,
+            vec![
+                find_review_address(&program_id, &reviewer, "Inception"),
+                Pubkey::find_program_address(&[reviewer.as_ref(), b"Inception"], &program_id).0,
+            ]
+        );
+
+        let long_title = "Dr. Strangelove or: How I Learned to Stop Worrying and Love the Bomb";
+        assert_eq!(legacy_review_title(long_title), None);
+        assert_eq!(
+            find_review_address_candidates(&program_id, &reviewer, long_title),
+            vec![find_review_address(&program_id, &reviewer, long_title)]
+        );
+    }
+
+    #[test]
+    fn test_comment_seeds() {
+        let program_id = Pubkey::new_unique();
+        let parent = Pubkey::new_unique();
+        let index = comment_index(7);
+
+        let comment_bump = bump(&[parent.as_ref(), &index], &program_id);
+        signs_for(
+            &comment(&parent, &index, &comment_bump),
+            &program_id,
+            find_comment_address(&program_id, &parent, 7),
+        );
+
+        let reply_bump = bump(&[parent.as_ref(), REPLY], &program_id);
+        signs_for(
+            &reply_counter(&parent, &reply_bump),
+            &program_id,
+            find_reply_counter_address(&program_id, &parent),
+        );
+    }
+
+    #[test]
+    fn test_reward_seeds() {
+        let program_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+
+        let config_bump = bump(&[REWARD_CONFIG], &program_id);
+        signs_for(
+            &reward_config(&config_bump),
+            &program_id,
+            find_reward_config_address(&program_id),
+        );
+
+        let ledger_bump = bump(&[REWARD_LEDGER, user.as_ref()], &program_id);
+        signs_for(
+            &reward_ledger(&user, &ledger_bump),
+            &program_id,
+            find_reward_ledger_address(&program_id, &user),
+        );
+
+        let vote_bump = bump(&[VOTE, target.as_ref(), user.as_ref()], &program_id);
+        signs_for(
+            &vote(&target, &user, &vote_bump),
+            &program_id,
+            find_vote_address(&program_id, &target, &user),
+        );
+    }
+}
@@ -1,10 +1,12 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    entrypoint::MAX_PERMITTED_DATA_INCREASE,
     program_pack::{IsInitialized, Sealed},
     pubkey::Pubkey,
 };
+use std::io;
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize)]
 pub struct MovieAccountState {
     pub discriminator: String,
     pub is_initialized: bool,
@@ -12,6 +14,10 @@ pub struct MovieAccountState {
     pub title: String,
     pub description: String,
     pub reviewer: Pubkey,
+    pub upvotes: u64,
+    /// Whether `rating` is tallied in the title's `MovieStats`. Reviews posted
+    /// before stats were tracked are not, even once the title has stats.
+    pub counted_in_stats: bool,
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -21,31 +27,173 @@ pub struct MovieCommentCounter {
     pub counter: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize)]
 pub struct MovieComment {
     pub discriminator: String,
     pub is_initialized: bool,
     pub review: Pubkey,
     pub commenter: Pubkey,
     pub comment: String,
+    /// Position under the review or parent comment, the index in the PDA seed.
+    /// Comments created before it was recorded read as 0.
     pub count: u64,
+    pub parent: Pubkey,
+    pub is_deleted: bool,
+    pub upvotes: u64,
+}
+
+/// One per (voter, target), seeded by `[b"vote", target, voter]`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Vote {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub voter: Pubkey,
+    pub target: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieStats {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub review_count: u64,
+    pub rating_sum: u64,
+    pub rating_histogram: [u64; 5],
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RewardConfig {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    /// Supply the reward mint may reach, in base units.
+    pub max_supply: u64,
+    /// Rewards a user may earn per window, in base units.
+    pub daily_cap: u64,
+    pub admin: Pubkey,
+    /// Rewards per action, in whole tokens.
+    pub schedule: RewardSchedule,
+}
+
+/// Reward per action, in whole tokens. Converted to base units with the
+/// mint's decimals when minting.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RewardSchedule {
+    pub review: u64,
+    pub comment: u64,
+    pub reply: u64,
+    pub upvote: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RewardAction {
+    Review,
+    Comment,
+    Reply,
+    Upvote,
+}
+
+/// Rewards minted to `user` in the current window, seeded by
+/// `[b"reward_ledger", user]`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RewardLedger {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub user: Pubkey,
+    pub window_start: i64,
+    pub rewarded: u64,
+}
+
+// Reviews and comments sized before a trailing field was added end right
+// before it, so the missing field reads as its default: no upvotes, no parent
+// and not deleted.
+fn deserialize_trailing<T: BorshDeserialize + Default>(buf: &mut &[u8]) -> io::Result<T> {
+    if buf.is_empty() {
+        Ok(T::default())
+    } else {
+        T::deserialize(buf)
+    }
+}
+
+impl BorshDeserialize for MovieAccountState {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::deserialize(&mut data.as_slice())
+    }
+
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(MovieAccountState {
+            discriminator: String::deserialize(buf)?,
+            is_initialized: bool::deserialize(buf)?,
+            rating: u8::deserialize(buf)?,
+            title: String::deserialize(buf)?,
+            description: String::deserialize(buf)?,
+            reviewer: Pubkey::deserialize(buf)?,
+            upvotes: deserialize_trailing(buf)?,
+            // Reviews are sized to end right after the flag. Legacy reviews are
+            // padded to 1000 bytes, possibly with stale data, and were never
+            // counted.
+            counted_in_stats: buf.len() == 1 && u8::deserialize(buf)? == 1,
+        })
+    }
+}
+
+impl BorshDeserialize for MovieComment {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::deserialize(&mut data.as_slice())
+    }
+
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(MovieComment {
+            discriminator: String::deserialize(buf)?,
+            is_initialized: bool::deserialize(buf)?,
+            review: Pubkey::deserialize(buf)?,
+            commenter: Pubkey::deserialize(buf)?,
+            comment: String::deserialize(buf)?,
+            count: u64::deserialize(buf)?,
+            parent: deserialize_trailing(buf)?,
+            is_deleted: deserialize_trailing(buf)?,
+            upvotes: deserialize_trailing(buf)?,
+        })
+    }
 }
 
 impl MovieAccountState {
     pub const DISCRIMINATOR: &'static str = "review";
+    /// Largest review `AddMovieReview` and `UpdateMovieReview` will allocate,
+    /// which caps how long a title and description can be. It matches the
+    /// fixed size reviews used to be created with; raise it to allow longer
+    /// reviews.
+    pub const MAX_ACCOUNT_SIZE: usize = 1000;
     pub fn get_account_size(title: String, description: String) -> usize {
         return (4 + MovieAccountState::DISCRIMINATOR.len())
             + 1
             + 1
             + (4 + title.len())
-            + (4 + description.len());
+            + (4 + description.len())
+            + 32
+            + 8
+            + 1;
     }
 }
 
+// `UpdateMovieReview` grows a review in a single realloc, which may add at
+// most `MAX_PERMITTED_DATA_INCREASE` bytes.
+const _: () = assert!(MovieAccountState::MAX_ACCOUNT_SIZE <= MAX_PERMITTED_DATA_INCREASE);
+
 impl MovieComment {
     pub const DISCRIMINATOR: &'static str = "comment";
     pub fn get_account_size(comment: String) -> usize {
-        return (4 + MovieComment::DISCRIMINATOR.len()) + 1 + 32 + 32 + (4 + comment.len()) + 8;
+        return (4 + MovieComment::DISCRIMINATOR.len())
+            + 1
+            + 32
+            + 32
+            + (4 + comment.len())
+            + 8
+            + 32
+            + 1
+            + 8;
     }
 }
 
@@ -54,8 +202,95 @@ impl MovieCommentCounter {
     pub const SIZE: usize = (4 + MovieCommentCounter::DISCRIMINATOR.len()) + 1 + 8;
 }
 
+impl MovieStats {
+    pub const DISCRIMINATOR: &'static str = "stats";
+    pub const SIZE: usize = (4 + MovieStats::DISCRIMINATOR.len()) + 1 + 8 + 8 + (8 * 5);
+
+    pub fn add_rating(&mut self, rating: u8) {
+        self.review_count += 1;
+        self.rating_sum += rating as u64;
+        self.rating_histogram[(rating - 1) as usize] += 1;
+    }
+
+    pub fn remove_rating(&mut self, rating: u8) {
+        self.review_count = self.review_count.saturating_sub(1);
+        self.rating_sum = self.rating_sum.saturating_sub(rating as u64);
+        let bucket = &mut self.rating_histogram[(rating - 1) as usize];
+        *bucket = bucket.saturating_sub(1);
+    }
+}
+
+impl Vote {
+    pub const DISCRIMINATOR: &'static str = "vote";
+    pub const SIZE: usize = (4 + Vote::DISCRIMINATOR.len()) + 1 + 32 + 32;
+    /// The author is rewarded each time a target's upvotes reach a multiple of this.
+    pub const REWARD_THRESHOLD: u64 = 10;
+}
+
+impl RewardConfig {
+    pub const DISCRIMINATOR: &'static str = "reward_config";
+    pub const SIZE: usize =
+        (4 + RewardConfig::DISCRIMINATOR.len()) + 1 + 8 + 8 + 32 + RewardSchedule::SIZE;
+}
+
+impl RewardSchedule {
+    pub const SIZE: usize = 8 * 4;
+
+    pub fn tokens_for(&self, action: RewardAction) -> u64 {
+        match action {
+            RewardAction::Review => self.review,
+            RewardAction::Comment => self.comment,
+            RewardAction::Reply => self.reply,
+            RewardAction::Upvote => self.upvote,
+        }
+    }
+
+    /// The reward for `action` in base units of a mint with `decimals`, or
+    /// `None` if it does not fit in a `u64`.
+    pub fn amount_for(&self, action: RewardAction, decimals: u8) -> Option<u64> {
+        10u64
+            .checked_pow(decimals.into())?
+            .checked_mul(self.tokens_for(action))
+    }
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        RewardSchedule {
+            review: 10,
+            comment: 5,
+            reply: 5,
+            upvote: 1,
+        }
+    }
+}
+
+impl RewardLedger {
+    pub const DISCRIMINATOR: &'static str = "reward_ledger";
+    pub const SIZE: usize = (4 + RewardLedger::DISCRIMINATOR.len()) + 1 + 32 + 8 + 8;
+    /// Length of a reward window in seconds.
+    pub const WINDOW: i64 = 24 * 60 * 60;
+
+    /// Starts a new window once the current one has run for `WINDOW` seconds.
+    pub fn roll_window(&mut self, now: i64) {
+        if now.saturating_sub(self.window_start) >= Self::WINDOW {
+            self.window_start = now;
+            self.rewarded = 0;
+        }
+    }
+
+    /// How much more can be rewarded in the current window under `daily_cap`.
+    pub fn remaining(&self, daily_cap: u64) -> u64 {
+        daily_cap.saturating_sub(self.rewarded)
+    }
+}
+
 impl Sealed for MovieCommentCounter {}
+impl Sealed for MovieStats {}
 impl Sealed for MovieAccountState {}
+impl Sealed for RewardConfig {}
+impl Sealed for Vote {}
+impl Sealed for RewardLedger {}
 
 impl IsInitialized for MovieAccountState {
     fn is_initialized(&self) -> bool {
@@ -68,8 +303,196 @@ impl IsInitialized for MovieCommentCounter {
         self.is_initialized
     }
 }
+impl IsInitialized for MovieStats {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
 impl IsInitialized for MovieComment {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
+
+impl IsInitialized for RewardConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for Vote {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for RewardLedger {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger(window_start: i64, rewarded: u64) -> RewardLedger {
+        RewardLedger {
+            discriminator: RewardLedger::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            user: Pubkey::new_unique(),
+            window_start,
+            rewarded,
+        }
+    }
+
+    #[test]
+    fn test_ledger_keeps_window_until_it_elapses() {
+        let mut ledger = ledger(1_000, 40);
+
+        ledger.roll_window(1_000 + RewardLedger::WINDOW - 1);
+
+        assert_eq!(ledger.window_start, 1_000);
+        assert_eq!(ledger.rewarded, 40);
+        assert_eq!(ledger.remaining(50), 10);
+        assert_eq!(ledger.remaining(30), 0);
+    }
+
+    #[test]
+    fn test_ledger_resets_after_window() {
+        let mut ledger = ledger(1_000, 50);
+
+        ledger.roll_window(1_000 + RewardLedger::WINDOW);
+
+        assert_eq!(ledger.window_start, 1_000 + RewardLedger::WINDOW);
+        assert_eq!(ledger.rewarded, 0);
+        assert_eq!(ledger.remaining(50), 50);
+    }
+
+    #[test]
+    fn test_schedule_amounts_follow_mint_decimals() {
+        let schedule = RewardSchedule::default();
+
+        assert_eq!(
+            schedule.amount_for(RewardAction::Review, 9),
+            Some(10_000_000_000)
+        );
+        assert_eq!(
+            schedule.amount_for(RewardAction::Comment, 6),
+            Some(5_000_000)
+        );
+        assert_eq!(schedule.amount_for(RewardAction::Reply, 0), Some(5));
+        assert_eq!(schedule.amount_for(RewardAction::Upvote, 2), Some(100));
+    }
+
+    #[test]
+    fn test_schedule_amount_overflow_is_none() {
+        let schedule = RewardSchedule {
+            review: u64::MAX,
+            ..RewardSchedule::default()
+        };
+
+        assert_eq!(schedule.amount_for(RewardAction::Review, 1), None);
+        assert_eq!(schedule.amount_for(RewardAction::Upvote, 20), None);
+        assert_eq!(
+            schedule.amount_for(RewardAction::Upvote, 19),
+            Some(10u64.pow(19))
+        );
+    }
+
+    #[test]
+    fn test_review_size_matches_serialized_length() {
+        let review = MovieAccountState {
+            discriminator: MovieAccountState::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            rating: 4,
+            title: "Captain America".to_string(),
+            description: "Liked the movie".to_string(),
+            reviewer: Pubkey::new_unique(),
+            upvotes: 7,
+            counted_in_stats: true,
+        };
+
+        let data = review.try_to_vec().unwrap();
+
+        assert_eq!(
+            data.len(),
+            MovieAccountState::get_account_size(review.title.clone(), review.description.clone())
+        );
+        assert!(data.len() < MovieAccountState::MAX_ACCOUNT_SIZE);
+        let decoded = MovieAccountState::try_from_slice(&data).unwrap();
+        assert_eq!(decoded.description, review.description);
+        assert_eq!(decoded.upvotes, 7);
+        assert!(decoded.counted_in_stats);
+    }
+
+    #[test]
+    fn test_padded_legacy_review_is_not_counted_in_stats() {
+        let reviewer = Pubkey::new_unique();
+        let mut data = (
+            MovieAccountState::DISCRIMINATOR.to_string(),
+            true,
+            4u8,
+            "Captain America".to_string(),
+            "Liked the movie".to_string(),
+            reviewer,
+        )
+            .try_to_vec()
+            .unwrap();
+        // Where the flag would be, as left behind by a longer description.
+        data.extend_from_slice(&[0; 8]);
+        data.push(1);
+        data.resize(MovieAccountState::MAX_ACCOUNT_SIZE, 0);
+
+        let review = MovieAccountState::deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(review.reviewer, reviewer);
+        assert!(!review.counted_in_stats);
+    }
+
+    #[test]
+    fn test_legacy_comment_defaults_trailing_fields() {
+        #[derive(BorshSerialize)]
+        struct LegacyComment {
+            discriminator: String,
+            is_initialized: bool,
+            review: Pubkey,
+            commenter: Pubkey,
+            comment: String,
+            count: u64,
+        }
+        let legacy = LegacyComment {
+            discriminator: MovieComment::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            review: Pubkey::new_unique(),
+            commenter: Pubkey::new_unique(),
+            comment: "Great movie".to_string(),
+            count: 3,
+        };
+        let data = legacy.try_to_vec().unwrap();
+        assert_eq!(
+            data.len(),
+            MovieComment::get_account_size(legacy.comment.clone()) - 32 - 1 - 8
+        );
+
+        let comment = MovieComment::try_from_slice(&data).unwrap();
+
+        assert_eq!(comment.review, legacy.review);
+        assert_eq!(comment.commenter, legacy.commenter);
+        assert_eq!(comment.comment, legacy.comment);
+        assert_eq!(comment.count, 3);
+        assert_eq!(comment.parent, Pubkey::default());
+        assert!(!comment.is_deleted);
+        assert_eq!(comment.upvotes, 0);
+    }
+
+    #[test]
+    fn test_new_ledger_opens_a_window() {
+        let mut ledger = ledger(0, 0);
+
+        ledger.roll_window(1_700_000_000);
+
+        assert_eq!(ledger.window_start, 1_700_000_000);
+    }
+}
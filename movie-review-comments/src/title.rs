@@ -0,0 +1,38 @@
+use solana_program::hash::hashv;
+use unicode_normalization::UnicodeNormalization;
+
+/// Canonical form of a movie title used for deriving per-title seeds.
+///
+/// Titles are trimmed, lowercased and put in Unicode NFC, so "Inception",
+/// " inception" and the decomposed "Ame\u{301}lie" / composed "Amélie" pairs
+/// resolve to the same movie. The original title is still stored for display.
+pub fn normalize_title(title: &str) -> String {
+    title.trim().to_lowercase().nfc().collect()
+}
+
+/// 32-byte seed derived from the normalized title.
+///
+/// Hashing keeps the seed within the 32-byte PDA seed limit regardless of the
+/// title length. Every program deriving review or stats PDAs must use this
+/// scheme: `sha256(normalize_title(title))`.
+pub fn title_hash(title: &str) -> [u8; 32] {
+    hashv(&[normalize_title(title).as_bytes()]).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_hash_matches_the_other_programs() {
+        // sha256("amélie"), also pinned in `smdb-src`.
+        let expected = [
+            0x5f, 0x78, 0xac, 0x01, 0xbd, 0xe9, 0x00, 0xc5, 0xe0, 0x1b, 0xe9, 0xf1, 0x2c, 0xd5,
+            0x65, 0xc6, 0x2c, 0x6e, 0x63, 0x63, 0x79, 0x6d, 0x30, 0xfa, 0x57, 0x48, 0xd0, 0x01,
+            0x87, 0x35, 0x7b, 0xe8,
+        ];
+        for title in ["Amélie", " amélie ", "Ame\u{301}lie", "AME\u{301}LIE"] {
+            assert_eq!(title_hash(title), expected, "{:?}", title);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::StakeError;
+use solana_program::account_info::AccountInfo;
+use std::slice::ChunksExact;
+
+/// Most NFTs one StakeMany, UnstakeMany or RedeemAll can touch. Eight
+/// StakeMany tuples plus the fixed accounts stay under the 64-account lock
+/// limit, and each freeze or thaw CPI costs tens of thousands of compute
+/// units, so callers should also raise the compute-unit limit.
+///
+/// Every account key takes 32 bytes of a legacy transaction, so only 4
+/// StakeMany, 5 UnstakeMany or all 8 RedeemAll tuples fit in its 1232 bytes.
+/// Fuller batches have to be sent as versioned transactions that load the
+/// per-NFT accounts from an address lookup table.
+pub const MAX_BATCH: usize = 8;
+
+/// Token account, mint, edition, stake state, metadata, multiplier.
+pub const STAKE_TUPLE_LEN: usize = 6;
+/// Token account, mint, edition, stake state.
+pub const UNSTAKE_TUPLE_LEN: usize = 4;
+/// Token account, stake state.
+pub const REDEEM_TUPLE_LEN: usize = 2;
+
+/// Splits the accounts after an instruction's fixed accounts into one
+/// `tuple_len` chunk per NFT.
+pub fn nft_tuples<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    tuple_len: usize,
+) -> Result<ChunksExact<'a, AccountInfo<'b>>, StakeError> {
+    let nft_count = accounts.len() / tuple_len;
+    if nft_count == 0 || nft_count * tuple_len != accounts.len() {
+        return Err(StakeError::InvalidBatchAccounts);
+    }
+    if nft_count > MAX_BATCH {
+        return Err(StakeError::BatchTooLarge);
+    }
+
+    Ok(accounts.chunks_exact(tuple_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        pubkey::Pubkey,
+    };
+
+    /// Largest serialized transaction a validator accepts.
+    const PACKET_DATA_SIZE: usize = 1232;
+
+    /// Size of a legacy transaction, signed by the user alone, that raises
+    /// the compute-unit limit and sends one batch instruction for
+    /// `nft_count` NFTs.
+    fn legacy_transaction_len(fixed_accounts: usize, tuple_len: usize, nft_count: usize) -> usize {
+        let user = Pubkey::new_unique();
+        let mut accounts = vec![AccountMeta::new(user, true)];
+        accounts.extend(
+            (1..fixed_accounts + tuple_len * nft_count)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false)),
+        );
+        let instructions = [
+            // SetComputeUnitLimit: a tag and a u32.
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[2, 0, 0, 0, 0], vec![]),
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[6, 0], accounts),
+        ];
+        let message = Message::new(&instructions, Some(&user));
+        // One signature and its compact-u16 length prefix.
+        1 + 64 + message.serialize().len()
+    }
+
+    fn legacy_batch_limit(fixed_accounts: usize, tuple_len: usize) -> usize {
+        (1..=MAX_BATCH)
+            .take_while(|&nft_count| {
+                legacy_transaction_len(fixed_accounts, tuple_len, nft_count) <= PACKET_DATA_SIZE
+            })
+            .count()
+    }
+
+    fn with_accounts(count: usize, test: impl FnOnce(&[AccountInfo])) {
+        let keys: Vec<Pubkey> = (0..count).map(|_| Pubkey::new_unique()).collect();
+        let owner = Pubkey::new_unique();
+        let mut lamports = vec![0u64; count];
+        let mut data = vec![Vec::<u8>::new(); count];
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &owner, false, 0)
+            })
+            .collect();
+        test(&accounts);
+    }
+
+    #[test]
+    fn splits_accounts_per_nft() {
+        with_accounts(3 * UNSTAKE_TUPLE_LEN, |accounts| {
+            let tuples: Vec<_> = nft_tuples(accounts, UNSTAKE_TUPLE_LEN).unwrap().collect();
+            assert_eq!(tuples.len(), 3);
+            assert_eq!(tuples[1][0].key, accounts[UNSTAKE_TUPLE_LEN].key);
+            assert_eq!(tuples[2][3].key, accounts[3 * UNSTAKE_TUPLE_LEN - 1].key);
+        });
+    }
+
+    #[test]
+    fn rejects_empty_and_partial_tuples() {
+        with_accounts(0, |accounts| {
+            assert!(matches!(
+                nft_tuples(accounts, REDEEM_TUPLE_LEN),
+                Err(StakeError::InvalidBatchAccounts)
+            ));
+        });
+        with_accounts(STAKE_TUPLE_LEN + 1, |accounts| {
+            assert!(matches!(
+                nft_tuples(accounts, STAKE_TUPLE_LEN),
+                Err(StakeError::InvalidBatchAccounts)
+            ));
+        });
+    }
+
+    #[test]
+    fn fuller_batches_need_a_lookup_table() {
+        // StakeMany, UnstakeMany and RedeemAll take 7, 10 and 8 fixed
+        // accounts before their tuples.
+        assert_eq!(legacy_batch_limit(7, STAKE_TUPLE_LEN), 4);
+        assert_eq!(legacy_batch_limit(10, UNSTAKE_TUPLE_LEN), 5);
+        assert_eq!(legacy_batch_limit(8, REDEEM_TUPLE_LEN), MAX_BATCH);
+    }
+
+    #[test]
+    fn caps_batch_size() {
+        with_accounts(MAX_BATCH * STAKE_TUPLE_LEN, |accounts| {
+            assert_eq!(
+                nft_tuples(accounts, STAKE_TUPLE_LEN).unwrap().len(),
+                MAX_BATCH
+            );
+        });
+        with_accounts((MAX_BATCH + 1) * REDEEM_TUPLE_LEN, |accounts| {
+            assert!(matches!(
+                nft_tuples(accounts, REDEEM_TUPLE_LEN),
+                Err(StakeError::BatchTooLarge)
+            ));
+        });
+    }
+}
@@ -14,6 +14,48 @@ pub enum StakeError {
 
     #[error("Invalid token account")]
     InvalidTokenAccount,
+
+    #[error("Account data could not be deserialized")]
+    CorruptAccountData,
+
+    #[error("Invalid NFT metadata account")]
+    InvalidMetadata,
+
+    #[error("NFT is not a verified member of the pool's collection")]
+    NftNotInCollection,
+
+    #[error("NFT has no verified creator allowed by the pool")]
+    CreatorNotAllowed,
+
+    #[error("Stake pool allows too many creators")]
+    TooManyCreators,
+
+    #[error("Reward calculation overflowed")]
+    RewardOverflow,
+
+    #[error("Multiplier must be greater than zero")]
+    InvalidMultiplier,
+
+    #[error("Signer is not the stake pool authority")]
+    Unauthorized,
+
+    #[error("Stake mint decimals do not match the stake pool")]
+    MintDecimalsMismatch,
+
+    #[error("Stake account is too small for its data")]
+    StakeAccountTooSmall,
+
+    #[error("Penalty cannot exceed 100%")]
+    InvalidPenalty,
+
+    #[error("NFT is still locked")]
+    StakeLocked,
+
+    #[error("Batch accounts must be whole per-NFT tuples")]
+    InvalidBatchAccounts,
+
+    #[error("Too many NFTs in one batch")]
+    BatchTooLarge,
 }
 
 impl From<StakeError> for ProgramError {
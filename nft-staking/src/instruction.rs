@@ -1,22 +1,108 @@
-use solana_program::program_error::ProgramError;
+use crate::state::{EarlyUnstake, LockTier};
+use borsh::BorshDeserialize;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
 pub enum StakeInstruction {
     InitializeStakeAccount,
-    Stake,
+    Stake {
+        lock_tier: LockTier,
+    },
     Redeem,
     Unstake,
+    InitializeStakePool {
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+        rate_per_second: u64,
+        decimals: u8,
+        early_unstake: EarlyUnstake,
+    },
+    SetNftMultiplier {
+        multiplier_bps: u16,
+    },
+    /// Followed by one `batch::STAKE_TUPLE_LEN` tuple per NFT.
+    StakeMany {
+        lock_tier: LockTier,
+    },
+    /// Followed by one `batch::UNSTAKE_TUPLE_LEN` tuple per NFT.
+    UnstakeMany,
+    /// Followed by one `batch::REDEEM_TUPLE_LEN` tuple per NFT.
+    RedeemAll,
+    /// Replaces the pool settings; only the stake pool authority may call it.
+    UpdateStakePool {
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+        rate_per_second: u64,
+        decimals: u8,
+        early_unstake: EarlyUnstake,
+    },
+}
+
+#[derive(BorshDeserialize)]
+struct StakePoolPayload {
+    collection: Pubkey,
+    creators: Vec<Pubkey>,
+    rate_per_second: u64,
+    decimals: u8,
+    early_unstake: EarlyUnstake,
+}
+
+#[derive(BorshDeserialize)]
+struct MultiplierPayload {
+    multiplier_bps: u16,
 }
 
 impl StakeInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&variant, _rest) = input
+        let (&variant, rest) = input
             .split_first()
             .ok_or(ProgramError::InvalidInstructionData)?;
         Ok(match variant {
             0 => Self::InitializeStakeAccount,
-            1 => Self::Stake,
+            // Clients that predate lock tiers send no payload.
+            1 if rest.is_empty() => Self::Stake {
+                lock_tier: LockTier::None,
+            },
+            1 => Self::Stake {
+                lock_tier: LockTier::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            },
             2 => Self::Redeem,
             3 => Self::Unstake,
+            4 => {
+                let payload = StakePoolPayload::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Self::InitializeStakePool {
+                    collection: payload.collection,
+                    creators: payload.creators,
+                    rate_per_second: payload.rate_per_second,
+                    decimals: payload.decimals,
+                    early_unstake: payload.early_unstake,
+                }
+            }
+            5 => {
+                let payload = MultiplierPayload::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Self::SetNftMultiplier {
+                    multiplier_bps: payload.multiplier_bps,
+                }
+            }
+            6 => Self::StakeMany {
+                lock_tier: LockTier::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?,
+            },
+            7 => Self::UnstakeMany,
+            8 => Self::RedeemAll,
+            9 => {
+                let payload = StakePoolPayload::try_from_slice(rest)
+                    .map_err(|_| ProgramError::InvalidInstructionData)?;
+                Self::UpdateStakePool {
+                    collection: payload.collection,
+                    creators: payload.creators,
+                    rate_per_second: payload.rate_per_second,
+                    decimals: payload.decimals,
+                    early_unstake: payload.early_unstake,
+                }
+            }
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
@@ -1,5 +1,7 @@
+pub mod batch;
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
 pub mod processor;
+pub mod rewards;
 pub mod state;
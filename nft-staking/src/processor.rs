@@ -1,22 +1,32 @@
+use crate::batch::{nft_tuples, REDEEM_TUPLE_LEN, STAKE_TUPLE_LEN, UNSTAKE_TUPLE_LEN};
 use crate::error::StakeError;
 use crate::instruction::StakeInstruction;
-use crate::state::{StakeState, UserStakeInfo};
+use crate::rewards::{apply_penalty, calculate_reward, combined_multiplier_bps, BPS_DENOMINATOR};
+use crate::state::{
+    EarlyUnstake, LockTier, NftMultiplier, StakePool, StakeState, UserStakeInfo, UserStakeSummary,
+};
 use borsh::BorshSerialize;
-use mpl_token_metadata::ID as METADATA_PROGRAM_ID;
+use mpl_token_metadata::{
+    pda::find_metadata_account,
+    state::{Metadata, TokenMetadataAccount},
+    ID as METADATA_PROGRAM_ID,
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     borsh::try_from_slice_unchecked,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
-    program_pack::IsInitialized,
+    program_pack::{IsInitialized, Pack},
+    program_utils::limited_deserialize,
     pubkey::Pubkey,
     system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
-use spl_token::ID as TOKEN_PROGRAM_ID;
+use spl_token::{state::Mint, ID as TOKEN_PROGRAM_ID};
 
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -29,9 +39,47 @@ pub fn process_instruction(
         StakeInstruction::InitializeStakeAccount => {
             process_initialize_stake_account(program_id, accounts)
         }
-        StakeInstruction::Stake => process_stake(program_id, accounts),
+        StakeInstruction::Stake { lock_tier } => process_stake(program_id, accounts, lock_tier),
         StakeInstruction::Redeem => process_redeem(program_id, accounts),
         StakeInstruction::Unstake => process_unstake(program_id, accounts),
+        StakeInstruction::InitializeStakePool {
+            collection,
+            creators,
+            rate_per_second,
+            decimals,
+            early_unstake,
+        } => process_initialize_stake_pool(
+            program_id,
+            accounts,
+            collection,
+            creators,
+            rate_per_second,
+            decimals,
+            early_unstake,
+        ),
+        StakeInstruction::SetNftMultiplier { multiplier_bps } => {
+            process_set_nft_multiplier(program_id, accounts, multiplier_bps)
+        }
+        StakeInstruction::StakeMany { lock_tier } => {
+            process_stake_many(program_id, accounts, lock_tier)
+        }
+        StakeInstruction::UnstakeMany => process_unstake_many(program_id, accounts),
+        StakeInstruction::RedeemAll => process_redeem_all(program_id, accounts),
+        StakeInstruction::UpdateStakePool {
+            collection,
+            creators,
+            rate_per_second,
+            decimals,
+            early_unstake,
+        } => process_update_stake_pool(
+            program_id,
+            accounts,
+            collection,
+            creators,
+            rate_per_second,
+            decimals,
+            early_unstake,
+        ),
     }
 }
 
@@ -53,10 +101,43 @@ fn process_initialize_stake_account(
         msg!("Invalid seeds for PDA");
         return Err(StakeError::InvalidPda.into());
     }
+    create_stake_state(
+        program_id,
+        user,
+        nft_token_account.key,
+        stake_state,
+        system_program,
+        bump_seed,
+    )?;
+
+    let mut account_data = try_from_slice_unchecked::<UserStakeInfo>(&stake_state.data.borrow())
+        .map_err(|_| StakeError::CorruptAccountData)?;
+    if account_data.is_initialized() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    account_data.token_account = *nft_token_account.key;
+    account_data.user_pubkey = *user.key;
+    account_data.stake_state = StakeState::Unstaked;
+    account_data.is_initialized = true;
+
+    account_data.serialize(&mut &mut stake_state.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn create_stake_state<'a>(
+    program_id: &Pubkey,
+    user: &AccountInfo<'a>,
+    nft_token_account: &Pubkey,
+    stake_state: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    bump_seed: u8,
+) -> ProgramResult {
     let rent = Rent::get()?;
     let rent_lamports = rent.minimum_balance(UserStakeInfo::SIZE);
 
-    msg!("Creating state account at {:?}", stake_state_pda);
+    msg!("Creating state account at {:?}", stake_state.key);
     invoke_signed(
         &system_instruction::create_account(
             user.key,
@@ -66,30 +147,74 @@ fn process_initialize_stake_account(
             program_id,
         ),
         &[user.clone(), stake_state.clone(), system_program.clone()],
-        &[&[
-            user.key.as_ref(),
-            nft_token_account.key.as_ref(),
-            &[bump_seed],
-        ]],
-    )?;
+        &[&[user.key.as_ref(), nft_token_account.as_ref(), &[bump_seed]]],
+    )
+}
 
-    let mut account_data =
-        try_from_slice_unchecked::<UserStakeInfo>(&stake_state.data.borrow()).unwrap();
-    if account_data.is_initialized() {
-        msg!("Account already initialized");
-        return Err(ProgramError::AccountAlreadyInitialized);
+/// Accounts every NFT in a stake or unstake call shares.
+struct Custody<'a, 'b> {
+    user: &'a AccountInfo<'b>,
+    program_authority: &'a AccountInfo<'b>,
+    authority_bump: u8,
+    token_program: &'a AccountInfo<'b>,
+    metadata_program: &'a AccountInfo<'b>,
+}
+
+impl<'a, 'b> Custody<'a, 'b> {
+    fn new(
+        program_id: &Pubkey,
+        user: &'a AccountInfo<'b>,
+        program_authority: &'a AccountInfo<'b>,
+        token_program: &'a AccountInfo<'b>,
+        metadata_program: &'a AccountInfo<'b>,
+    ) -> Result<Self, ProgramError> {
+        if !user.is_signer {
+            msg!("Missing required signature");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let (delegated_auth_pda, authority_bump) =
+            Pubkey::find_program_address(&[b"authority"], program_id);
+        if delegated_auth_pda != *program_authority.key {
+            msg!("Invalid seeds for PDA");
+            return Err(StakeError::InvalidPda.into());
+        }
+
+        Ok(Self {
+            user,
+            program_authority,
+            authority_bump,
+            token_program,
+            metadata_program,
+        })
     }
+}
 
-    account_data.token_account = *nft_token_account.key;
-    account_data.user_pubkey = *user.key;
-    account_data.stake_state = StakeState::Unstaked;
-    account_data.is_initialized = true;
+/// One NFT's accounts for Stake and StakeMany.
+struct StakeNftAccounts<'a, 'b> {
+    token_account: &'a AccountInfo<'b>,
+    mint: &'a AccountInfo<'b>,
+    edition: &'a AccountInfo<'b>,
+    stake_state: &'a AccountInfo<'b>,
+    metadata: &'a AccountInfo<'b>,
+    // Always required, even when it does not exist yet, so a multiplier below
+    // the base rate cannot be skipped by leaving it out.
+    multiplier: &'a AccountInfo<'b>,
+}
 
-    account_data.serialize(&mut &mut stake_state.data.borrow_mut()[..])?;
-    Ok(())
+/// One NFT's accounts for Unstake and UnstakeMany.
+struct UnstakeNftAccounts<'a, 'b> {
+    token_account: &'a AccountInfo<'b>,
+    mint: &'a AccountInfo<'b>,
+    edition: &'a AccountInfo<'b>,
+    stake_state: &'a AccountInfo<'b>,
 }
 
-fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+fn process_stake(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_tier: LockTier,
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let user = next_account_info(account_info_iter)?;
     let nft_token_account = next_account_info(account_info_iter)?;
@@ -99,79 +224,202 @@ fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult
     let program_authority = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let metadata_program = next_account_info(account_info_iter)?;
+    let nft_metadata = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let nft_multiplier = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
 
-    let (stake_state_pda, _bump_seed) = Pubkey::find_program_address(
-        &[user.key.as_ref(), nft_token_account.key.as_ref()],
+    let custody = Custody::new(
+        program_id,
+        user,
+        program_authority,
+        token_program,
+        metadata_program,
+    )?;
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
+
+    let nft = StakeNftAccounts {
+        token_account: nft_token_account,
+        mint: nft_mint,
+        edition: nft_edition,
+        stake_state,
+        metadata: nft_metadata,
+        multiplier: nft_multiplier,
+    };
+    stake_nft(
+        program_id,
+        &custody,
+        system_program,
+        &pool_data,
+        &nft,
+        lock_tier,
+        &clock,
+    )?;
+
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_stake(1, clock.unix_timestamp);
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn process_stake_many(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_tier: LockTier,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user = next_account_info(account_info_iter)?;
+    let program_authority = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let metadata_program = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
+
+    let custody = Custody::new(
+        program_id,
+        user,
+        program_authority,
+        token_program,
+        metadata_program,
+    )?;
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
+
+    let tuples = nft_tuples(account_info_iter.as_slice(), STAKE_TUPLE_LEN)?;
+    let nft_count = tuples.len() as u32;
+    msg!("Staking {} NFTs", nft_count);
+    for tuple in tuples {
+        let nft = StakeNftAccounts {
+            token_account: &tuple[0],
+            mint: &tuple[1],
+            edition: &tuple[2],
+            stake_state: &tuple[3],
+            metadata: &tuple[4],
+            multiplier: &tuple[5],
+        };
+        stake_nft(
+            program_id,
+            &custody,
+            system_program,
+            &pool_data,
+            &nft,
+            lock_tier,
+            &clock,
+        )?;
+    }
+
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_stake(nft_count, clock.unix_timestamp);
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn stake_nft<'a>(
+    program_id: &Pubkey,
+    custody: &Custody<'_, 'a>,
+    system_program: &AccountInfo<'a>,
+    pool_data: &StakePool,
+    nft: &StakeNftAccounts<'_, 'a>,
+    lock_tier: LockTier,
+    clock: &Clock,
+) -> ProgramResult {
+    let user = custody.user;
+
+    let (stake_state_pda, bump_seed) = Pubkey::find_program_address(
+        &[user.key.as_ref(), nft.token_account.key.as_ref()],
         program_id,
     );
-    if stake_state_pda != *stake_state.key {
+    if stake_state_pda != *nft.stake_state.key {
         msg!("Invalid seeds for PDA");
         return Err(StakeError::InvalidPda.into());
     }
 
+    let multiplier_bps = load_multiplier(program_id, nft.mint, nft.multiplier)?;
+
+    if *nft.metadata.key != find_metadata_account(nft.mint.key).0 {
+        msg!("Metadata account does not belong to the NFT mint");
+        return Err(StakeError::InvalidMetadata.into());
+    }
+    let metadata = Metadata::from_account_info(nft.metadata)?;
+
+    msg!("Checking NFT collection");
+    pool_data.check_nft(&metadata)?;
+
+    // NFTs staked for the first time get their stake state here, so StakeMany
+    // does not need an InitializeStakeAccount per NFT.
+    if nft.stake_state.data_is_empty() {
+        create_stake_state(
+            program_id,
+            user,
+            nft.token_account.key,
+            nft.stake_state,
+            system_program,
+            bump_seed,
+        )?;
+    } else if nft.stake_state.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
     msg!("Approving delegation");
     invoke(
         &spl_token::instruction::approve(
             &TOKEN_PROGRAM_ID,
-            nft_token_account.key,
-            program_authority.key,
+            nft.token_account.key,
+            custody.program_authority.key,
             user.key,
             &[user.key],
             1,
         )?,
         &[
-            nft_token_account.clone(),
-            program_authority.clone(),
+            nft.token_account.clone(),
+            custody.program_authority.clone(),
             user.clone(),
-            token_program.clone(),
+            custody.token_program.clone(),
         ],
     )?;
 
-    let (delegated_auth_pda, delegate_bump) =
-        Pubkey::find_program_address(&[b"authority"], program_id);
-
-    if delegated_auth_pda != *program_authority.key {
-        msg!("Invalid seeds for PDA");
-        return Err(StakeError::InvalidPda.into());
-    }
-
     msg!("Freezing NFT token account");
 
     invoke_signed(
         &mpl_token_metadata::instruction::freeze_delegated_account(
             METADATA_PROGRAM_ID,
-            *program_authority.key,
-            *nft_token_account.key,
-            *nft_edition.key,
-            *nft_mint.key,
+            *custody.program_authority.key,
+            *nft.token_account.key,
+            *nft.edition.key,
+            *nft.mint.key,
         ),
         &[
-            program_authority.clone(),
-            nft_token_account.clone(),
-            nft_edition.clone(),
-            nft_mint.clone(),
-            metadata_program.clone(),
+            custody.program_authority.clone(),
+            nft.token_account.clone(),
+            nft.edition.clone(),
+            nft.mint.clone(),
+            custody.metadata_program.clone(),
         ],
-        &[&[b"authority", &[delegate_bump]]],
+        &[&[b"authority", &[custody.authority_bump]]],
     )?;
 
-    let mut account_data =
-        try_from_slice_unchecked::<UserStakeInfo>(&stake_state.data.borrow()).unwrap();
-    if !account_data.is_initialized() {
-        msg!("Account not initialized");
-        return Err(StakeError::UninitializedAccount.into());
-    }
-
-    let clock = Clock::get()?;
+    let mut account_data = UserStakeInfo::unpack_from(&nft.stake_state.data.borrow())?;
+    grow_stake_account(user, nft.stake_state, system_program)?;
 
-    account_data.token_account = *nft_token_account.key;
+    account_data.token_account = *nft.token_account.key;
     account_data.user_pubkey = *user.key;
     account_data.stake_state = StakeState::Staked;
     account_data.stake_start_time = clock.unix_timestamp;
     account_data.last_stake_redeem = clock.unix_timestamp;
+    account_data.multiplier_bps = multiplier_bps;
+    account_data.lock_tier = lock_tier;
+    account_data.counted_in_summary = true;
     account_data.is_initialized = true;
 
-    account_data.serialize(&mut &mut stake_state.data.borrow_mut()[..])?;
+    msg!(
+        "Staked with {:?} lock until {}",
+        lock_tier,
+        account_data.lock_expires_at()
+    );
+    account_data.pack_into(&mut nft.stake_state.data.borrow_mut())?;
 
     Ok(())
 }
@@ -185,30 +433,113 @@ fn process_redeem(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     let stake_authority = next_account_info(account_info_iter)?;
     let user_stake_ata = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-    let (stake_state_pda, _bump_seed) = Pubkey::find_program_address(
-        &[user.key.as_ref(), nft_token_account.key.as_ref()],
-        program_id,
-    );
-    if stake_state_pda != *stake_state.key {
-        msg!("Invalid seeds for PDA");
-        return Err(StakeError::InvalidPda.into());
+    if !user.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let (stake_auth_pda, stake_auth_bump) = Pubkey::find_program_address(&[b"mint"], program_id);
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
 
-    if *stake_authority.key != stake_auth_pda {
-        msg!("Invalid seeds for PDA");
-        return Err(StakeError::InvalidPda.into());
-    }
+    let (accrued, redeem_amount) = redeem_nft(
+        program_id,
+        user,
+        &pool_data,
+        stake_mint,
+        nft_token_account,
+        stake_state,
+        &clock,
+    )?;
+
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_rewards(accrued, redeem_amount, clock.unix_timestamp)?;
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+
+    mint_reward(
+        program_id,
+        stake_mint,
+        stake_authority,
+        user_stake_ata,
+        token_program,
+        redeem_amount,
+    )
+}
+
+fn process_redeem_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user = next_account_info(account_info_iter)?;
+    let stake_mint = next_account_info(account_info_iter)?;
+    let stake_authority = next_account_info(account_info_iter)?;
+    let user_stake_ata = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
     if !user.is_signer {
         msg!("Missing required signature");
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut account_data =
-        try_from_slice_unchecked::<UserStakeInfo>(&stake_state.data.borrow()).unwrap();
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
+
+    let tuples = nft_tuples(account_info_iter.as_slice(), REDEEM_TUPLE_LEN)?;
+    msg!("Redeeming rewards for {} NFTs", tuples.len());
+    let mut accrued: u64 = 0;
+    let mut redeem_amount: u64 = 0;
+    for tuple in tuples {
+        let (nft_accrued, reward) = redeem_nft(
+            program_id, user, &pool_data, stake_mint, &tuple[0], &tuple[1], &clock,
+        )?;
+        accrued = accrued
+            .checked_add(nft_accrued)
+            .ok_or(StakeError::RewardOverflow)?;
+        redeem_amount = redeem_amount
+            .checked_add(reward)
+            .ok_or(StakeError::RewardOverflow)?;
+    }
+
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_rewards(accrued, redeem_amount, clock.unix_timestamp)?;
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+
+    mint_reward(
+        program_id,
+        stake_mint,
+        stake_authority,
+        user_stake_ata,
+        token_program,
+        redeem_amount,
+    )
+}
+
+/// Checks one staked NFT and marks its rewards as redeemed, returning the
+/// reward it accrued and the amount to mint for it after any early-unstake
+/// penalty.
+fn redeem_nft(
+    program_id: &Pubkey,
+    user: &AccountInfo,
+    pool_data: &StakePool,
+    stake_mint: &AccountInfo,
+    nft_token_account: &AccountInfo,
+    stake_state: &AccountInfo,
+    clock: &Clock,
+) -> Result<(u64, u64), ProgramError> {
+    let (stake_state_pda, _bump_seed) = Pubkey::find_program_address(
+        &[user.key.as_ref(), nft_token_account.key.as_ref()],
+        program_id,
+    );
+    if stake_state_pda != *stake_state.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    let mut account_data = UserStakeInfo::unpack_from(&stake_state.data.borrow())?;
     if !account_data.is_initialized() {
         msg!("Account not initialized");
         return Err(StakeError::UninitializedAccount.into());
@@ -227,33 +558,13 @@ fn process_redeem(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
         return Err(StakeError::InvalidTokenAccount.into());
     }
 
-    let clock = Clock::get()?;
-
-    let unix_time = clock.unix_timestamp - account_data.last_stake_redeem;
-    let redeem_amount = unix_time;
+    let accrued = pending_reward(pool_data, stake_mint, &account_data, clock)?;
+    let redeem_amount = claimable_reward(pool_data, &account_data, accrued, clock)?;
     msg!("Redeeming {} tokens", redeem_amount);
 
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            stake_mint.key,
-            user_stake_ata.key,
-            stake_authority.key,
-            &[stake_authority.key],
-            redeem_amount.try_into().unwrap(),
-        )?,
-        &[
-            stake_mint.clone(),
-            user_stake_ata.clone(),
-            stake_authority.clone(),
-            token_program.clone(),
-        ],
-        &[&[b"mint", &[stake_auth_bump]]],
-    )?;
-
     account_data.last_stake_redeem = clock.unix_timestamp;
-    account_data.serialize(&mut &mut stake_state.data.borrow_mut()[..])?;
-    Ok(())
+    account_data.pack_into(&mut stake_state.data.borrow_mut())?;
+    Ok((accrued, redeem_amount))
 }
 
 fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
@@ -269,31 +580,125 @@ fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
     let user_stake_ata = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let metadata_program = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
 
-    let (stake_state_pda, _bump_seed) = Pubkey::find_program_address(
-        &[user.key.as_ref(), nft_token_account.key.as_ref()],
+    let custody = Custody::new(
         program_id,
-    );
-    if stake_state_pda != *stake_state.key {
-        msg!("Invalid seeds for PDA");
-        return Err(StakeError::InvalidPda.into());
-    }
+        user,
+        program_authority,
+        token_program,
+        metadata_program,
+    )?;
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
 
-    if !user.is_signer {
-        msg!("Missing required signature");
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    let nft = UnstakeNftAccounts {
+        token_account: nft_token_account,
+        mint: nft_mint,
+        edition: nft_edition,
+        stake_state,
+    };
+    let (accrued, redeem_amount, counted) =
+        unstake_nft(program_id, &custody, &pool_data, stake_mint, &nft, &clock)?;
 
-    let (delegated_auth_pda, delegate_bump) =
-        Pubkey::find_program_address(&[b"authority"], program_id);
-    if delegated_auth_pda != *program_authority.key {
-        msg!("Invalid seeds for PDA");
-        return Err(StakeError::InvalidPda.into());
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_rewards(accrued, redeem_amount, clock.unix_timestamp)?;
+    summary.record_unstake(counted as u32, clock.unix_timestamp);
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+
+    mint_reward(
+        program_id,
+        stake_mint,
+        stake_authority,
+        user_stake_ata,
+        token_program,
+        redeem_amount,
+    )
+}
+
+fn process_unstake_many(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let user = next_account_info(account_info_iter)?;
+    let program_authority = next_account_info(account_info_iter)?;
+    let stake_mint = next_account_info(account_info_iter)?;
+    let stake_authority = next_account_info(account_info_iter)?;
+    let user_stake_ata = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let metadata_program = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let user_summary = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let custody = Custody::new(
+        program_id,
+        user,
+        program_authority,
+        token_program,
+        metadata_program,
+    )?;
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    let clock = Clock::get()?;
+
+    let tuples = nft_tuples(account_info_iter.as_slice(), UNSTAKE_TUPLE_LEN)?;
+    let nft_count = tuples.len() as u32;
+    msg!("Unstaking {} NFTs", nft_count);
+    let mut accrued: u64 = 0;
+    let mut redeem_amount: u64 = 0;
+    let mut counted_count: u32 = 0;
+    for tuple in tuples {
+        let nft = UnstakeNftAccounts {
+            token_account: &tuple[0],
+            mint: &tuple[1],
+            edition: &tuple[2],
+            stake_state: &tuple[3],
+        };
+        let (nft_accrued, reward, counted) =
+            unstake_nft(program_id, &custody, &pool_data, stake_mint, &nft, &clock)?;
+        counted_count += counted as u32;
+        accrued = accrued
+            .checked_add(nft_accrued)
+            .ok_or(StakeError::RewardOverflow)?;
+        redeem_amount = redeem_amount
+            .checked_add(reward)
+            .ok_or(StakeError::RewardOverflow)?;
     }
 
-    let (stake_auth_pda, auth_bump) = Pubkey::find_program_address(&[b"mint"], program_id);
-    if *stake_authority.key != stake_auth_pda {
-        msg!("Invalid stake mint authority!");
+    let mut summary = load_user_summary(program_id, user, user_summary, system_program)?;
+    summary.record_rewards(accrued, redeem_amount, clock.unix_timestamp)?;
+    summary.record_unstake(counted_count, clock.unix_timestamp);
+    summary.serialize(&mut &mut user_summary.data.borrow_mut()[..])?;
+
+    mint_reward(
+        program_id,
+        stake_mint,
+        stake_authority,
+        user_stake_ata,
+        token_program,
+        redeem_amount,
+    )
+}
+
+/// Releases one NFT and returns the reward it accrued, the amount to mint for
+/// it after any early-unstake penalty and whether its stake was counted in
+/// the user's summary.
+fn unstake_nft<'a>(
+    program_id: &Pubkey,
+    custody: &Custody<'_, 'a>,
+    pool_data: &StakePool,
+    stake_mint: &AccountInfo,
+    nft: &UnstakeNftAccounts<'_, 'a>,
+    clock: &Clock,
+) -> Result<(u64, u64, bool), ProgramError> {
+    let user = custody.user;
+
+    let (stake_state_pda, _bump_seed) = Pubkey::find_program_address(
+        &[user.key.as_ref(), nft.token_account.key.as_ref()],
+        program_id,
+    );
+    if stake_state_pda != *nft.stake_state.key {
+        msg!("Invalid seeds for PDA");
         return Err(StakeError::InvalidPda.into());
     }
 
@@ -301,38 +706,37 @@ fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
     invoke_signed(
         &mpl_token_metadata::instruction::thaw_delegated_account(
             METADATA_PROGRAM_ID,
-            *program_authority.key,
-            *nft_token_account.key,
-            *nft_edition.key,
-            *nft_mint.key,
+            *custody.program_authority.key,
+            *nft.token_account.key,
+            *nft.edition.key,
+            *nft.mint.key,
         ),
         &[
-            program_authority.clone(),
-            nft_token_account.clone(),
-            nft_edition.clone(),
-            nft_mint.clone(),
-            metadata_program.clone(),
+            custody.program_authority.clone(),
+            nft.token_account.clone(),
+            nft.edition.clone(),
+            nft.mint.clone(),
+            custody.metadata_program.clone(),
         ],
-        &[&[b"authority", &[delegate_bump]]],
+        &[&[b"authority", &[custody.authority_bump]]],
     )?;
 
     msg!("Revoke delegation");
     invoke(
         &spl_token::instruction::revoke(
             &TOKEN_PROGRAM_ID,
-            nft_token_account.key,
+            nft.token_account.key,
             user.key,
             &[user.key],
         )?,
         &[
-            nft_token_account.clone(),
+            nft.token_account.clone(),
             user.clone(),
-            token_program.clone(),
+            custody.token_program.clone(),
         ],
     )?;
 
-    let mut account_data =
-        try_from_slice_unchecked::<UserStakeInfo>(&stake_state.data.borrow()).unwrap();
+    let mut account_data = UserStakeInfo::unpack_from(&nft.stake_state.data.borrow())?;
     if !account_data.is_initialized() {
         msg!("Account not initialized");
         return Err(StakeError::UninitializedAccount.into());
@@ -343,11 +747,35 @@ fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
         return Err(ProgramError::InvalidArgument);
     }
 
-    let clock = Clock::get()?;
-
-    let unix_time = clock.unix_timestamp - account_data.last_stake_redeem;
-    let redeem_amount = 100 * unix_time;
+    let accrued = pending_reward(pool_data, stake_mint, &account_data, clock)?;
+    let redeem_amount = claimable_reward(pool_data, &account_data, accrued, clock)?;
     msg!("Redeeming {} tokens", redeem_amount);
+
+    msg!("Setting stake state to unstaked");
+    let counted = account_data.counted_in_summary;
+    account_data.stake_state = StakeState::Unstaked;
+    account_data.counted_in_summary = false;
+
+    account_data.pack_into(&mut nft.stake_state.data.borrow_mut())?;
+    Ok((accrued, redeem_amount, counted))
+}
+
+/// Mints `amount` reward tokens to the user in a single CPI.
+fn mint_reward<'a>(
+    program_id: &Pubkey,
+    stake_mint: &AccountInfo<'a>,
+    stake_authority: &AccountInfo<'a>,
+    user_stake_ata: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let (stake_auth_pda, stake_auth_bump) = Pubkey::find_program_address(&[b"mint"], program_id);
+    if *stake_authority.key != stake_auth_pda {
+        msg!("Invalid stake mint authority!");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    msg!("Minting {} reward tokens", amount);
     invoke_signed(
         &spl_token::instruction::mint_to(
             token_program.key,
@@ -355,7 +783,7 @@ fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
             user_stake_ata.key,
             stake_authority.key,
             &[stake_authority.key],
-            redeem_amount.try_into().unwrap(),
+            amount,
         )?,
         &[
             stake_mint.clone(),
@@ -363,12 +791,1242 @@ fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResu
             stake_authority.clone(),
             token_program.clone(),
         ],
-        &[&[b"mint", &[auth_bump]]],
-    )?;
+        &[&[b"mint", &[stake_auth_bump]]],
+    )
+}
 
-    msg!("Setting stake state to unstaked");
-    account_data.stake_state = StakeState::Unstaked;
+fn process_initialize_stake_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    collection: Pubkey,
+    creators: Vec<Pubkey>,
+    rate_per_second: u64,
+    decimals: u8,
+    early_unstake: EarlyUnstake,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
 
-    account_data.serialize(&mut &mut stake_state.data.borrow_mut()[..])?;
-    Ok(())
+    if !authority.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_upgrade_authority(program_id, authority, program_data)?;
+    check_pool_settings(&creators, early_unstake)?;
+
+    let (stake_pool_pda, bump_seed) = Pubkey::find_program_address(&[b"stake_pool"], program_id);
+    if stake_pool_pda != *stake_pool.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(StakePool::SIZE);
+
+    msg!("Creating stake pool at {:?}", stake_pool_pda);
+    invoke_signed(
+        &system_instruction::create_account(
+            authority.key,
+            stake_pool.key,
+            rent_lamports,
+            StakePool::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            authority.clone(),
+            stake_pool.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"stake_pool", &[bump_seed]]],
+    )?;
+
+    let mut pool_data = try_from_slice_unchecked::<StakePool>(&stake_pool.data.borrow())
+        .map_err(|_| StakeError::CorruptAccountData)?;
+    if pool_data.is_initialized() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    pool_data.authority = *authority.key;
+    pool_data.collection = collection;
+    pool_data.creators = creators;
+    pool_data.rate_per_second = rate_per_second;
+    pool_data.decimals = decimals;
+    pool_data.early_unstake = early_unstake;
+    pool_data.is_initialized = true;
+
+    msg!(
+        "Staking restricted to collection {:?}",
+        pool_data.collection
+    );
+    pool_data.serialize(&mut &mut stake_pool.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// Rate changes apply to rewards not yet redeemed, so they also reprice the
+/// time NFTs have already been staked.
+fn process_update_stake_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    collection: Pubkey,
+    creators: Vec<Pubkey>,
+    rate_per_second: u64,
+    decimals: u8,
+    early_unstake: EarlyUnstake,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+
+    if !authority.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_data = load_stake_pool(program_id, stake_pool)?;
+    if pool_data.authority != *authority.key {
+        msg!("Only the stake pool authority can update the pool");
+        return Err(StakeError::Unauthorized.into());
+    }
+
+    check_pool_settings(&creators, early_unstake)?;
+
+    pool_data.collection = collection;
+    pool_data.creators = creators;
+    pool_data.rate_per_second = rate_per_second;
+    pool_data.decimals = decimals;
+    pool_data.early_unstake = early_unstake;
+
+    msg!(
+        "Staking restricted to collection {:?}",
+        pool_data.collection
+    );
+    pool_data.serialize(&mut &mut stake_pool.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn check_pool_settings(creators: &[Pubkey], early_unstake: EarlyUnstake) -> ProgramResult {
+    if creators.len() > StakePool::MAX_CREATORS {
+        msg!(
+            "At most {} creators can be allowed",
+            StakePool::MAX_CREATORS
+        );
+        return Err(StakeError::TooManyCreators.into());
+    }
+
+    if let EarlyUnstake::Forfeit { penalty_bps } = early_unstake {
+        if u128::from(penalty_bps) > BPS_DENOMINATOR {
+            msg!("Penalty cannot exceed {} bps", BPS_DENOMINATOR);
+            return Err(StakeError::InvalidPenalty.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Only the upgrade authority recorded in this program's ProgramData account
+/// may create the stake pool and so become its authority.
+fn check_upgrade_authority(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    program_data: &AccountInfo,
+) -> ProgramResult {
+    let (program_data_pda, _bump_seed) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data_pda != *program_data.key {
+        msg!("Invalid program data account");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+    let data = program_data.data.borrow();
+    let upgrade_authority =
+        match limited_deserialize(&data[..metadata_len.min(data.len())], metadata_len as u64) {
+            Ok(UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address,
+                ..
+            }) => upgrade_authority_address,
+            _ => return Err(StakeError::CorruptAccountData.into()),
+        };
+
+    if !authority.is_signer || upgrade_authority != Some(*authority.key) {
+        msg!("Only the program's upgrade authority can create the stake pool");
+        return Err(StakeError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+fn load_stake_pool(
+    program_id: &Pubkey,
+    stake_pool: &AccountInfo,
+) -> Result<StakePool, ProgramError> {
+    let (stake_pool_pda, _bump_seed) = Pubkey::find_program_address(&[b"stake_pool"], program_id);
+    if stake_pool_pda != *stake_pool.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    if stake_pool.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let pool_data = try_from_slice_unchecked::<StakePool>(&stake_pool.data.borrow())
+        .map_err(|_| StakeError::CorruptAccountData)?;
+    if !pool_data.is_initialized() {
+        msg!("Stake pool not initialized");
+        return Err(StakeError::UninitializedAccount.into());
+    }
+
+    Ok(pool_data)
+}
+
+fn process_set_nft_multiplier(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    multiplier_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let authority = next_account_info(account_info_iter)?;
+    let stake_pool = next_account_info(account_info_iter)?;
+    let nft_mint = next_account_info(account_info_iter)?;
+    let nft_multiplier = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !authority.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pool_data = load_stake_pool(program_id, stake_pool)?;
+    if pool_data.authority != *authority.key {
+        msg!("Only the stake pool authority can set multipliers");
+        return Err(StakeError::Unauthorized.into());
+    }
+
+    if multiplier_bps == 0 {
+        msg!("Multiplier must be greater than zero");
+        return Err(StakeError::InvalidMultiplier.into());
+    }
+
+    let (multiplier_pda, bump_seed) =
+        Pubkey::find_program_address(&[b"multiplier", nft_mint.key.as_ref()], program_id);
+    if multiplier_pda != *nft_multiplier.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    if nft_multiplier.data_is_empty() {
+        let rent = Rent::get()?;
+        let rent_lamports = rent.minimum_balance(NftMultiplier::SIZE);
+
+        msg!("Creating multiplier account at {:?}", multiplier_pda);
+        invoke_signed(
+            &system_instruction::create_account(
+                authority.key,
+                nft_multiplier.key,
+                rent_lamports,
+                NftMultiplier::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                authority.clone(),
+                nft_multiplier.clone(),
+                system_program.clone(),
+            ],
+            &[&[b"multiplier", nft_mint.key.as_ref(), &[bump_seed]]],
+        )?;
+    } else if nft_multiplier.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut multiplier_data =
+        try_from_slice_unchecked::<NftMultiplier>(&nft_multiplier.data.borrow())
+            .map_err(|_| StakeError::CorruptAccountData)?;
+
+    multiplier_data.mint = *nft_mint.key;
+    multiplier_data.multiplier_bps = multiplier_bps;
+    multiplier_data.is_initialized = true;
+
+    msg!(
+        "Setting multiplier for {:?} to {} bps",
+        nft_mint.key,
+        multiplier_bps
+    );
+    multiplier_data.serialize(&mut &mut nft_multiplier.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// NFTs without a multiplier account earn the pool's base rate.
+fn load_multiplier(
+    program_id: &Pubkey,
+    nft_mint: &AccountInfo,
+    nft_multiplier: &AccountInfo,
+) -> Result<u16, ProgramError> {
+    let (multiplier_pda, _bump_seed) =
+        Pubkey::find_program_address(&[b"multiplier", nft_mint.key.as_ref()], program_id);
+    if multiplier_pda != *nft_multiplier.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    if nft_multiplier.data_is_empty() {
+        return Ok(NftMultiplier::BASE_BPS);
+    }
+
+    if nft_multiplier.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let multiplier_data = try_from_slice_unchecked::<NftMultiplier>(&nft_multiplier.data.borrow())
+        .map_err(|_| StakeError::CorruptAccountData)?;
+    if !multiplier_data.is_initialized() {
+        return Ok(NftMultiplier::BASE_BPS);
+    }
+
+    Ok(multiplier_data.multiplier_bps)
+}
+
+/// Tokens accrued by `stake_info` since its last redeem, before any
+/// early-unstake penalty.
+fn pending_reward(
+    pool_data: &StakePool,
+    stake_mint: &AccountInfo,
+    stake_info: &UserStakeInfo,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let mint = Mint::unpack(&stake_mint.data.borrow())?;
+    if mint.decimals != pool_data.decimals {
+        msg!(
+            "Stake mint has {} decimals, pool expects {}",
+            mint.decimals,
+            pool_data.decimals
+        );
+        return Err(StakeError::MintDecimalsMismatch.into());
+    }
+
+    Ok(calculate_reward(
+        pool_data.rate_per_second,
+        pool_data.decimals,
+        combined_multiplier_bps(
+            stake_info.multiplier_bps(),
+            stake_info.lock_tier.boost_bps(),
+        ),
+        stake_info.last_stake_redeem,
+        clock.unix_timestamp,
+    )?)
+}
+
+/// How much of `accrued` Redeem and Unstake pay. Both follow the pool's
+/// early-unstake policy until the lock expires, so redeeming along the way
+/// cannot collect the rewards an early unstake would forfeit.
+fn claimable_reward(
+    pool_data: &StakePool,
+    stake_info: &UserStakeInfo,
+    accrued: u64,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let lock_expires_at = stake_info.lock_expires_at();
+    if clock.unix_timestamp >= lock_expires_at {
+        return Ok(accrued);
+    }
+
+    match pool_data.early_unstake {
+        EarlyUnstake::Reject => {
+            msg!("NFT is locked until {}", lock_expires_at);
+            Err(StakeError::StakeLocked.into())
+        }
+        EarlyUnstake::Forfeit { penalty_bps } => {
+            msg!(
+                "Claiming before {} forfeits {} bps of rewards",
+                lock_expires_at,
+                penalty_bps
+            );
+            Ok(apply_penalty(accrued, penalty_bps)?)
+        }
+    }
+}
+
+/// Tops up rent and grows stake accounts created before the current layout.
+fn grow_stake_account<'a>(
+    payer: &AccountInfo<'a>,
+    stake_state: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    if stake_state.data_len() >= UserStakeInfo::SIZE {
+        return Ok(());
+    }
+
+    let rent = Rent::get()?;
+    let lamports_needed = rent
+        .minimum_balance(UserStakeInfo::SIZE)
+        .saturating_sub(stake_state.lamports());
+    if lamports_needed > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, stake_state.key, lamports_needed),
+            &[payer.clone(), stake_state.clone(), system_program.clone()],
+        )?;
+    }
+
+    msg!("Growing stake account to {} bytes", UserStakeInfo::SIZE);
+    stake_state.realloc(UserStakeInfo::SIZE, true)
+}
+
+/// Loads the user's summary, creating it on their first stake, redeem or
+/// unstake.
+fn load_user_summary<'a>(
+    program_id: &Pubkey,
+    user: &AccountInfo<'a>,
+    user_summary: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> Result<UserStakeSummary, ProgramError> {
+    let (summary_pda, bump_seed) =
+        Pubkey::find_program_address(&[b"user_summary", user.key.as_ref()], program_id);
+    if summary_pda != *user_summary.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StakeError::InvalidPda.into());
+    }
+
+    if user_summary.data_is_empty() {
+        let rent = Rent::get()?;
+        let rent_lamports = rent.minimum_balance(UserStakeSummary::SIZE);
+
+        msg!("Creating user summary at {:?}", summary_pda);
+        invoke_signed(
+            &system_instruction::create_account(
+                user.key,
+                user_summary.key,
+                rent_lamports,
+                UserStakeSummary::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[user.clone(), user_summary.clone(), system_program.clone()],
+            &[&[b"user_summary", user.key.as_ref(), &[bump_seed]]],
+        )?;
+    } else if user_summary.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut summary = try_from_slice_unchecked::<UserStakeSummary>(&user_summary.data.borrow())
+        .map_err(|_| StakeError::CorruptAccountData)?;
+    if !summary.is_initialized() {
+        summary.user = *user.key;
+        summary.is_initialized = true;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::MAX_BATCH;
+    use mpl_token_metadata::state::{Collection, Data, Key, MAX_METADATA_LEN};
+    use solana_program::{
+        entrypoint::SUCCESS,
+        instruction::Instruction,
+        program_option::COption,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        system_instruction::SystemInstruction,
+        system_program,
+    };
+    use spl_token::instruction::TokenInstruction;
+    use std::cell::{Cell, RefCell};
+    use std::sync::Once;
+
+    /// Runs `test` against accounts built from `(key, owner, data)` triples.
+    fn with_accounts(specs: Vec<(Pubkey, Pubkey, Vec<u8>)>, test: impl FnOnce(&[AccountInfo])) {
+        let mut lamports = vec![1_000_000u64; specs.len()];
+        let (keys_and_owners, mut data): (Vec<_>, Vec<_>) = specs
+            .into_iter()
+            .map(|(key, owner, data)| ((key, owner), data))
+            .unzip();
+        let accounts: Vec<AccountInfo> = keys_and_owners
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|(((key, owner), lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, owner, false, 0)
+            })
+            .collect();
+        test(&accounts);
+    }
+
+    #[test]
+    fn truncated_stake_pool_is_corrupt() {
+        let program_id = Pubkey::new_unique();
+        let (stake_pool, _) = Pubkey::find_program_address(&[b"stake_pool"], &program_id);
+        with_accounts(vec![(stake_pool, program_id, vec![1, 2, 3])], |accounts| {
+            assert_eq!(
+                load_stake_pool(&program_id, &accounts[0]).unwrap_err(),
+                StakeError::CorruptAccountData.into()
+            );
+        });
+    }
+
+    #[test]
+    fn truncated_multiplier_is_corrupt() {
+        let program_id = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let (multiplier, _) =
+            Pubkey::find_program_address(&[b"multiplier", nft_mint.as_ref()], &program_id);
+        with_accounts(
+            vec![
+                (nft_mint, spl_token::id(), vec![]),
+                (multiplier, program_id, vec![1]),
+            ],
+            |accounts| {
+                assert_eq!(
+                    load_multiplier(&program_id, &accounts[0], &accounts[1]).unwrap_err(),
+                    StakeError::CorruptAccountData.into()
+                );
+            },
+        );
+        with_accounts(
+            vec![
+                (nft_mint, spl_token::id(), vec![]),
+                (multiplier, program_id, vec![]),
+            ],
+            |accounts| {
+                assert_eq!(
+                    load_multiplier(&program_id, &accounts[0], &accounts[1]).unwrap(),
+                    NftMultiplier::BASE_BPS
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn truncated_user_summary_is_corrupt() {
+        let program_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let (summary, _) =
+            Pubkey::find_program_address(&[b"user_summary", user.as_ref()], &program_id);
+        with_accounts(
+            vec![
+                (user, Pubkey::default(), vec![]),
+                (summary, program_id, vec![1; 8]),
+                (Pubkey::default(), Pubkey::default(), vec![]),
+            ],
+            |accounts| {
+                assert_eq!(
+                    load_user_summary(&program_id, &accounts[0], &accounts[1], &accounts[2])
+                        .unwrap_err(),
+                    StakeError::CorruptAccountData.into()
+                );
+            },
+        );
+    }
+
+    fn program_data(upgrade_authority: Option<Pubkey>) -> Vec<u8> {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        match upgrade_authority {
+            Some(authority) => {
+                data.push(1);
+                data.extend_from_slice(authority.as_ref());
+            }
+            None => data.push(0),
+        }
+        data
+    }
+
+    #[test]
+    fn only_the_upgrade_authority_creates_the_pool() {
+        let program_id = Pubkey::new_unique();
+        let (program_data_pda, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        let upgrade_authority = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let check = |signer: Pubkey, key: Pubkey, owner: Pubkey, data: Vec<u8>| {
+            let mut result = Ok(());
+            with_accounts(
+                vec![(signer, Pubkey::default(), vec![]), (key, owner, data)],
+                |accounts| {
+                    let mut authority = accounts[0].clone();
+                    authority.is_signer = true;
+                    result = check_upgrade_authority(&program_id, &authority, &accounts[1]);
+                },
+            );
+            result
+        };
+        let loader = bpf_loader_upgradeable::id();
+
+        assert_eq!(
+            check(
+                upgrade_authority,
+                program_data_pda,
+                loader,
+                program_data(Some(upgrade_authority))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                other,
+                program_data_pda,
+                loader,
+                program_data(Some(upgrade_authority))
+            ),
+            Err(StakeError::Unauthorized.into())
+        );
+        assert_eq!(
+            check(
+                upgrade_authority,
+                program_data_pda,
+                loader,
+                program_data(None)
+            ),
+            Err(StakeError::Unauthorized.into())
+        );
+        assert_eq!(
+            check(
+                upgrade_authority,
+                other,
+                loader,
+                program_data(Some(upgrade_authority))
+            ),
+            Err(StakeError::InvalidPda.into())
+        );
+        assert_eq!(
+            check(
+                upgrade_authority,
+                program_data_pda,
+                program_id,
+                program_data(Some(upgrade_authority))
+            ),
+            Err(ProgramError::IllegalOwner)
+        );
+        assert_eq!(
+            check(upgrade_authority, program_data_pda, loader, vec![3, 0]),
+            Err(StakeError::CorruptAccountData.into())
+        );
+    }
+
+    #[test]
+    fn only_the_pool_authority_updates_the_pool() {
+        let program_id = Pubkey::new_unique();
+        let (stake_pool, _) = Pubkey::find_program_address(&[b"stake_pool"], &program_id);
+        let authority = Pubkey::new_unique();
+        let mut pool_data = StakePool {
+            is_initialized: true,
+            authority,
+            collection: Pubkey::new_unique(),
+            creators: vec![],
+            rate_per_second: 1,
+            decimals: 2,
+            early_unstake: EarlyUnstake::Reject,
+        }
+        .try_to_vec()
+        .unwrap();
+        pool_data.resize(StakePool::SIZE, 0);
+        let collection = Pubkey::new_unique();
+        let creators = vec![Pubkey::new_unique()];
+        let early_unstake = EarlyUnstake::Forfeit { penalty_bps: 2_500 };
+
+        for signer in [Pubkey::new_unique(), authority] {
+            with_accounts(
+                vec![
+                    (signer, Pubkey::default(), vec![]),
+                    (stake_pool, program_id, pool_data.clone()),
+                ],
+                |accounts| {
+                    let mut accounts = accounts.to_vec();
+                    accounts[0].is_signer = true;
+                    let result = process_update_stake_pool(
+                        &program_id,
+                        &accounts,
+                        collection,
+                        creators.clone(),
+                        5,
+                        6,
+                        early_unstake,
+                    );
+                    if signer != authority {
+                        assert_eq!(result, Err(StakeError::Unauthorized.into()));
+                        return;
+                    }
+
+                    assert_eq!(result, Ok(()));
+                    let updated = load_stake_pool(&program_id, &accounts[1]).unwrap();
+                    assert_eq!(updated.authority, authority);
+                    assert_eq!(updated.collection, collection);
+                    assert_eq!(updated.creators, creators);
+                    assert_eq!(updated.rate_per_second, 5);
+                    assert_eq!(updated.decimals, 6);
+                    assert_eq!(updated.early_unstake, early_unstake);
+
+                    let too_many_creators = vec![Pubkey::new_unique(); StakePool::MAX_CREATORS + 1];
+                    assert_eq!(
+                        process_update_stake_pool(
+                            &program_id,
+                            &accounts,
+                            collection,
+                            too_many_creators,
+                            5,
+                            6,
+                            early_unstake,
+                        ),
+                        Err(StakeError::TooManyCreators.into())
+                    );
+                },
+            );
+        }
+    }
+
+    fn locked_stake(clock: &Clock) -> UserStakeInfo {
+        UserStakeInfo {
+            is_initialized: true,
+            token_account: Pubkey::new_unique(),
+            stake_start_time: clock.unix_timestamp,
+            last_stake_redeem: clock.unix_timestamp,
+            user_pubkey: Pubkey::new_unique(),
+            stake_state: StakeState::Staked,
+            multiplier_bps: 0,
+            lock_tier: LockTier::ThirtyDays,
+            counted_in_summary: true,
+        }
+    }
+
+    fn pool_with(early_unstake: EarlyUnstake) -> StakePool {
+        StakePool {
+            is_initialized: true,
+            authority: Pubkey::new_unique(),
+            collection: Pubkey::new_unique(),
+            creators: vec![],
+            // One whole token per second.
+            rate_per_second: crate::rewards::RATE_SCALE as u64,
+            decimals: 0,
+            early_unstake,
+        }
+    }
+
+    fn at(unix_timestamp: i64) -> Clock {
+        Clock {
+            unix_timestamp,
+            ..Clock::default()
+        }
+    }
+
+    // Mirrors redeem_nft and unstake_nft without the account checks.
+    fn claim(
+        pool: &StakePool,
+        stake: &mut UserStakeInfo,
+        clock: &Clock,
+    ) -> Result<u64, ProgramError> {
+        let accrued = crate::rewards::calculate_reward(
+            pool.rate_per_second,
+            pool.decimals,
+            combined_multiplier_bps(stake.multiplier_bps(), stake.lock_tier.boost_bps()),
+            stake.last_stake_redeem,
+            clock.unix_timestamp,
+        )?;
+        let amount = claimable_reward(pool, stake, accrued, clock)?;
+        stake.last_stake_redeem = clock.unix_timestamp;
+        Ok(amount)
+    }
+
+    #[test]
+    fn redeeming_before_unstaking_early_still_forfeits() {
+        let pool = pool_with(EarlyUnstake::Forfeit { penalty_bps: 5_000 });
+        let mut stake = locked_stake(&at(0));
+
+        // The 30 day lock boosts 100 seconds to 125 tokens, half forfeited.
+        let redeemed = claim(&pool, &mut stake, &at(100)).unwrap();
+        assert_eq!(redeemed, 63);
+        let unstaked = claim(&pool, &mut stake, &at(300)).unwrap();
+        assert_eq!(unstaked, 125);
+
+        // Same as unstaking early in one go.
+        let mut unredeemed = locked_stake(&at(0));
+        assert_eq!(
+            redeemed + unstaked,
+            claim(&pool, &mut unredeemed, &at(300)).unwrap()
+        );
+
+        let expires_at = stake.lock_expires_at();
+        stake.last_stake_redeem = expires_at - 100;
+        assert_eq!(claim(&pool, &mut stake, &at(expires_at)).unwrap(), 125);
+    }
+
+    #[test]
+    fn redeeming_a_locked_nft_is_rejected() {
+        let pool = pool_with(EarlyUnstake::Reject);
+        let mut stake = locked_stake(&at(0));
+        let expires_at = stake.lock_expires_at();
+
+        assert_eq!(
+            claim(&pool, &mut stake, &at(expires_at - 1)).unwrap_err(),
+            StakeError::StakeLocked.into()
+        );
+        assert_eq!(stake.last_stake_redeem, 0);
+        assert_eq!(
+            claim(&pool, &mut stake, &at(expires_at)).unwrap(),
+            expires_at as u64 * 5 / 4
+        );
+    }
+
+    thread_local! {
+        static NOW: Cell<i64> = const { Cell::new(0) };
+        static INVOKED: RefCell<Vec<Instruction>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Stands in for the runtime: serves the clock and rent sysvars, records
+    /// every CPI and carries out the system program's CreateAccount.
+    struct TestSyscalls;
+
+    impl SyscallStubs for TestSyscalls {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = at(NOW.with(Cell::get));
+            unsafe { *(var_addr as *mut Clock) = clock };
+            SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            if instruction.program_id == system_program::id() {
+                if let Ok(SystemInstruction::CreateAccount {
+                    lamports, space, ..
+                }) = limited_deserialize(&instruction.data, 1024)
+                {
+                    let to = account_infos
+                        .iter()
+                        .find(|account| *account.key == instruction.accounts[1].pubkey)
+                        .unwrap();
+                    **to.lamports.borrow_mut() = lamports;
+                    *to.data.borrow_mut() = Box::leak(vec![0; space as usize].into_boxed_slice());
+                }
+            }
+            INVOKED.with(|invoked| invoked.borrow_mut().push(instruction.clone()));
+            Ok(())
+        }
+    }
+
+    /// Runs the next instruction on this thread at `now`, with no CPIs yet.
+    fn start_at(now: i64) {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(TestSyscalls));
+        });
+        NOW.with(|cell| cell.set(now));
+        INVOKED.with(|invoked| invoked.borrow_mut().clear());
+    }
+
+    fn invoked(program_id: &Pubkey) -> Vec<Instruction> {
+        INVOKED.with(|invoked| {
+            invoked
+                .borrow()
+                .iter()
+                .filter(|instruction| instruction.program_id == *program_id)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn minted() -> Vec<u64> {
+        invoked(&TOKEN_PROGRAM_ID)
+            .iter()
+            .filter_map(
+                |instruction| match TokenInstruction::unpack(&instruction.data) {
+                    Ok(TokenInstruction::MintTo { amount }) => Some(amount),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    /// A user staking from two token accounts into a pool paying one token per
+    /// second for mints with no decimals.
+    struct BatchFixture {
+        program_id: Pubkey,
+        user: Pubkey,
+        pool: StakePool,
+        token_accounts: Vec<Pubkey>,
+    }
+
+    impl BatchFixture {
+        fn new(early_unstake: EarlyUnstake) -> Self {
+            BatchFixture {
+                program_id: Pubkey::new_unique(),
+                user: Pubkey::new_unique(),
+                pool: pool_with(early_unstake),
+                token_accounts: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            }
+        }
+
+        fn pda(&self, seeds: &[&[u8]]) -> Pubkey {
+            Pubkey::find_program_address(seeds, &self.program_id).0
+        }
+
+        fn user(&self) -> (Pubkey, Pubkey, Vec<u8>) {
+            (self.user, Pubkey::default(), vec![])
+        }
+
+        fn program(&self, id: Pubkey) -> (Pubkey, Pubkey, Vec<u8>) {
+            (id, Pubkey::default(), vec![])
+        }
+
+        fn stake_pool(&self) -> (Pubkey, Pubkey, Vec<u8>) {
+            let mut data = self.pool.try_to_vec().unwrap();
+            data.resize(StakePool::SIZE, 0);
+            (self.pda(&[b"stake_pool"]), self.program_id, data)
+        }
+
+        fn user_summary(&self, staked_count: u32) -> (Pubkey, Pubkey, Vec<u8>) {
+            let summary = self.pda(&[b"user_summary", self.user.as_ref()]);
+            if staked_count == 0 {
+                return (summary, Pubkey::default(), vec![]);
+            }
+            let data = UserStakeSummary {
+                is_initialized: true,
+                user: self.user,
+                staked_count,
+                total_accrued: 0,
+                total_claimed: 0,
+                last_activity: 0,
+            }
+            .try_to_vec()
+            .unwrap();
+            (summary, self.program_id, data)
+        }
+
+        fn stake_mint(&self) -> [(Pubkey, Pubkey, Vec<u8>); 2] {
+            let stake_authority = self.pda(&[b"mint"]);
+            let mut data = vec![0; Mint::LEN];
+            Mint {
+                mint_authority: COption::Some(stake_authority),
+                decimals: self.pool.decimals,
+                is_initialized: true,
+                ..Mint::default()
+            }
+            .pack_into_slice(&mut data);
+            [
+                (Pubkey::new_unique(), TOKEN_PROGRAM_ID, data),
+                (stake_authority, Pubkey::default(), vec![]),
+            ]
+        }
+
+        fn stake_state(&self, token_account: &Pubkey) -> Pubkey {
+            self.pda(&[self.user.as_ref(), token_account.as_ref()])
+        }
+
+        /// A stake state for `token_account`, staked at time zero.
+        fn staked(&self, token_account: &Pubkey, lock_tier: LockTier) -> (Pubkey, Pubkey, Vec<u8>) {
+            let (key, owner, data) = self.legacy_staked(token_account);
+            let mut stake_info = UserStakeInfo::unpack_from(&data).unwrap();
+            stake_info.lock_tier = lock_tier;
+            stake_info.counted_in_summary = true;
+            (key, owner, stake_info.try_to_vec().unwrap())
+        }
+
+        /// A stake state for `token_account` staked at time zero, before the
+        /// layout grew and the summary counted stakes.
+        fn legacy_staked(&self, token_account: &Pubkey) -> (Pubkey, Pubkey, Vec<u8>) {
+            let mut data = UserStakeInfo {
+                is_initialized: true,
+                token_account: *token_account,
+                stake_start_time: 0,
+                last_stake_redeem: 0,
+                user_pubkey: self.user,
+                stake_state: StakeState::Staked,
+                multiplier_bps: 0,
+                lock_tier: LockTier::None,
+                counted_in_summary: false,
+            }
+            .try_to_vec()
+            .unwrap();
+            data.truncate(UserStakeInfo::LEGACY_SIZE);
+            (self.stake_state(token_account), self.program_id, data)
+        }
+
+        /// The StakeMany tuple for an NFT in the pool's collection that has
+        /// never been staked.
+        fn new_nft(&self, token_account: &Pubkey) -> Vec<(Pubkey, Pubkey, Vec<u8>)> {
+            let mint = Pubkey::new_unique();
+            let metadata = Metadata {
+                key: Key::MetadataV1,
+                update_authority: Pubkey::new_unique(),
+                mint,
+                data: Data::default(),
+                primary_sale_happened: false,
+                is_mutable: true,
+                edition_nonce: None,
+                token_standard: None,
+                collection: Some(Collection {
+                    verified: true,
+                    key: self.pool.collection,
+                }),
+                uses: None,
+                collection_details: None,
+                programmable_config: None,
+            };
+            let mut metadata_data = vec![0; MAX_METADATA_LEN];
+            metadata.save(&mut metadata_data).unwrap();
+            vec![
+                (*token_account, TOKEN_PROGRAM_ID, vec![]),
+                (mint, TOKEN_PROGRAM_ID, vec![]),
+                (Pubkey::new_unique(), METADATA_PROGRAM_ID, vec![]),
+                (self.stake_state(token_account), Pubkey::default(), vec![]),
+                (
+                    find_metadata_account(&mint).0,
+                    METADATA_PROGRAM_ID,
+                    metadata_data,
+                ),
+                (
+                    self.pda(&[b"multiplier", mint.as_ref()]),
+                    Pubkey::default(),
+                    vec![],
+                ),
+            ]
+        }
+
+        /// Runs `data` against `specs` with the user, always the first
+        /// account, signing, then hands the accounts to `check`.
+        fn process(
+            &self,
+            specs: Vec<(Pubkey, Pubkey, Vec<u8>)>,
+            data: &[u8],
+            check: impl FnOnce(ProgramResult, &[AccountInfo]),
+        ) {
+            with_accounts(specs, |accounts| {
+                let mut accounts = accounts.to_vec();
+                accounts[0].is_signer = true;
+                let result = process_instruction(&self.program_id, &accounts, data);
+                check(result, &accounts);
+            });
+        }
+    }
+
+    #[test]
+    fn stake_many_creates_missing_stake_states() {
+        let fixture = BatchFixture::new(EarlyUnstake::Reject);
+        let mut specs = vec![
+            fixture.user(),
+            (fixture.pda(&[b"authority"]), Pubkey::default(), vec![]),
+            fixture.program(TOKEN_PROGRAM_ID),
+            fixture.program(METADATA_PROGRAM_ID),
+            fixture.stake_pool(),
+            fixture.program(system_program::id()),
+            fixture.user_summary(0),
+        ];
+        for token_account in &fixture.token_accounts {
+            specs.extend(fixture.new_nft(token_account));
+        }
+
+        start_at(1_000);
+        fixture.process(specs, &[6, 1], |result, accounts| {
+            assert_eq!(result, Ok(()));
+
+            let stake_states = accounts[7..].iter().skip(3).step_by(STAKE_TUPLE_LEN);
+            for (token_account, stake_state) in fixture.token_accounts.iter().zip(stake_states) {
+                assert_eq!(stake_state.data_len(), UserStakeInfo::SIZE);
+                let stake_info = UserStakeInfo::unpack_from(&stake_state.data.borrow()).unwrap();
+                assert!(stake_info.is_initialized);
+                assert_eq!(stake_info.token_account, *token_account);
+                assert_eq!(stake_info.user_pubkey, fixture.user);
+                assert_eq!(stake_info.stake_state, StakeState::Staked);
+                assert_eq!(stake_info.stake_start_time, 1_000);
+                assert_eq!(stake_info.lock_tier, LockTier::ThirtyDays);
+                assert_eq!(stake_info.multiplier_bps(), NftMultiplier::BASE_BPS);
+                assert!(stake_info.counted_in_summary);
+            }
+
+            let summary =
+                try_from_slice_unchecked::<UserStakeSummary>(&accounts[6].data.borrow()).unwrap();
+            assert_eq!(summary.staked_count, 2);
+        });
+        // Two stake states and the summary.
+        assert_eq!(invoked(&system_program::id()).len(), 3);
+        assert_eq!(invoked(&METADATA_PROGRAM_ID).len(), 2);
+    }
+
+    #[test]
+    fn stake_many_rejects_partial_and_oversized_batches() {
+        let fixture = BatchFixture::new(EarlyUnstake::Reject);
+        let fixed = vec![
+            fixture.user(),
+            (fixture.pda(&[b"authority"]), Pubkey::default(), vec![]),
+            fixture.program(TOKEN_PROGRAM_ID),
+            fixture.program(METADATA_PROGRAM_ID),
+            fixture.stake_pool(),
+            fixture.program(system_program::id()),
+            fixture.user_summary(0),
+        ];
+        let token_account = fixture.token_accounts[0];
+
+        let mut partial = fixed.clone();
+        partial.extend(fixture.new_nft(&token_account).into_iter().take(5));
+        let mut oversized = fixed;
+        for _ in 0..=MAX_BATCH {
+            oversized.extend(fixture.new_nft(&Pubkey::new_unique()));
+        }
+
+        start_at(1_000);
+        for (specs, expected) in [
+            (partial, StakeError::InvalidBatchAccounts),
+            (oversized, StakeError::BatchTooLarge),
+        ] {
+            fixture.process(specs, &[6, 0], |result, _| {
+                assert_eq!(result, Err(expected.into()));
+            });
+        }
+        assert!(invoked(&METADATA_PROGRAM_ID).is_empty());
+    }
+
+    #[test]
+    fn redeem_all_mints_every_nft_in_one_cpi() {
+        let fixture = BatchFixture::new(EarlyUnstake::Reject);
+        let [stake_mint, stake_authority] = fixture.stake_mint();
+        let mut specs = vec![
+            fixture.user(),
+            stake_mint,
+            stake_authority,
+            (Pubkey::new_unique(), TOKEN_PROGRAM_ID, vec![]),
+            fixture.program(TOKEN_PROGRAM_ID),
+            fixture.stake_pool(),
+            fixture.user_summary(2),
+            fixture.program(system_program::id()),
+        ];
+        for token_account in &fixture.token_accounts {
+            specs.push((*token_account, TOKEN_PROGRAM_ID, vec![]));
+            specs.push(fixture.staked(token_account, LockTier::None));
+        }
+
+        start_at(100);
+        fixture.process(specs, &[8], |result, accounts| {
+            assert_eq!(result, Ok(()));
+
+            for stake_state in accounts[8..].iter().skip(1).step_by(REDEEM_TUPLE_LEN) {
+                let stake_info = UserStakeInfo::unpack_from(&stake_state.data.borrow()).unwrap();
+                assert_eq!(stake_info.stake_state, StakeState::Staked);
+                assert_eq!(stake_info.last_stake_redeem, 100);
+            }
+
+            let summary =
+                try_from_slice_unchecked::<UserStakeSummary>(&accounts[6].data.borrow()).unwrap();
+            assert_eq!(summary.staked_count, 2);
+            assert_eq!(summary.total_accrued, 200);
+            assert_eq!(summary.total_claimed, 200);
+        });
+        assert_eq!(minted(), vec![200]);
+    }
+
+    #[test]
+    fn unstake_many_releases_every_nft_under_the_lock_policy() {
+        let fixture = BatchFixture::new(EarlyUnstake::Forfeit { penalty_bps: 5_000 });
+        let [stake_mint, stake_authority] = fixture.stake_mint();
+        let mut specs = vec![
+            fixture.user(),
+            (fixture.pda(&[b"authority"]), Pubkey::default(), vec![]),
+            stake_mint,
+            stake_authority,
+            (Pubkey::new_unique(), TOKEN_PROGRAM_ID, vec![]),
+            fixture.program(TOKEN_PROGRAM_ID),
+            fixture.program(METADATA_PROGRAM_ID),
+            fixture.stake_pool(),
+            fixture.user_summary(2),
+            fixture.program(system_program::id()),
+        ];
+        for token_account in &fixture.token_accounts {
+            specs.push((*token_account, TOKEN_PROGRAM_ID, vec![]));
+            specs.push((Pubkey::new_unique(), TOKEN_PROGRAM_ID, vec![]));
+            specs.push((Pubkey::new_unique(), METADATA_PROGRAM_ID, vec![]));
+            specs.push(fixture.staked(token_account, LockTier::ThirtyDays));
+        }
+
+        start_at(100);
+        fixture.process(specs, &[7], |result, accounts| {
+            assert_eq!(result, Ok(()));
+
+            for stake_state in accounts[10..].iter().skip(3).step_by(UNSTAKE_TUPLE_LEN) {
+                let stake_info = UserStakeInfo::unpack_from(&stake_state.data.borrow()).unwrap();
+                assert_eq!(stake_info.stake_state, StakeState::Unstaked);
+                assert!(!stake_info.counted_in_summary);
+            }
+
+            // Each NFT's 125 boosted tokens lose half to the early unstake.
+            let summary =
+                try_from_slice_unchecked::<UserStakeSummary>(&accounts[8].data.borrow()).unwrap();
+            assert_eq!(summary.staked_count, 0);
+            assert_eq!(summary.total_accrued, 250);
+            assert_eq!(summary.total_claimed, 126);
+        });
+        assert_eq!(invoked(&METADATA_PROGRAM_ID).len(), 2);
+        assert_eq!(minted(), vec![126]);
+    }
+
+    #[test]
+    fn unstaking_legacy_stakes_leaves_the_summary_count_alone() {
+        let fixture = BatchFixture::new(EarlyUnstake::Reject);
+        let unstake_many = |token_accounts: &[Pubkey], legacy: bool| {
+            let [stake_mint, stake_authority] = fixture.stake_mint();
+            let mut specs = vec![
+                fixture.user(),
+                (fixture.pda(&[b"authority"]), Pubkey::default(), vec![]),
+                stake_mint,
+                stake_authority,
+                (Pubkey::new_unique(), TOKEN_PROGRAM_ID, vec![]),
+                fixture.program(TOKEN_PROGRAM_ID),
+                fixture.program(METADATA_PROGRAM_ID),
+                fixture.stake_pool(),
+                // Only the one NFT staked since the summary existed.
+                fixture.user_summary(1),
+                fixture.program(system_program::id()),
+            ];
+            for token_account in token_accounts {
+                specs.push((*token_account, TOKEN_PROGRAM_ID, vec![]));
+                specs.push((Pubkey::new_unique(), TOKEN_PROGRAM_ID, vec![]));
+                specs.push((Pubkey::new_unique(), METADATA_PROGRAM_ID, vec![]));
+                specs.push(if legacy {
+                    fixture.legacy_staked(token_account)
+                } else {
+                    fixture.staked(token_account, LockTier::None)
+                });
+            }
+            specs
+        };
+        let legacy: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+
+        for (specs, staked_count) in [
+            (unstake_many(&legacy, true), 1),
+            (unstake_many(&fixture.token_accounts[..1], false), 0),
+        ] {
+            start_at(100);
+            fixture.process(specs, &[7], |result, accounts| {
+                assert_eq!(result, Ok(()));
+                let summary =
+                    try_from_slice_unchecked::<UserStakeSummary>(&accounts[8].data.borrow())
+                        .unwrap();
+                assert_eq!(summary.staked_count, staked_count);
+            });
+        }
+    }
+
+    #[test]
+    fn unknown_stake_state_is_corrupt() {
+        let mut data = vec![0; UserStakeInfo::SIZE];
+        data[UserStakeInfo::LEGACY_SIZE - 1] = 2;
+        assert!(matches!(
+            UserStakeInfo::unpack_from(&data),
+            Err(StakeError::CorruptAccountData)
+        ));
+    }
 }
@@ -1,5 +1,8 @@
+use crate::error::StakeError;
 use borsh::{BorshDeserialize, BorshSerialize};
+use mpl_token_metadata::state::Metadata;
 use solana_program::{
+    borsh::try_from_slice_unchecked,
     clock::UnixTimestamp,
     program_pack::{IsInitialized, Sealed},
     pubkey::Pubkey,
@@ -13,10 +16,58 @@ pub struct UserStakeInfo {
     pub last_stake_redeem: UnixTimestamp,
     pub user_pubkey: Pubkey,
     pub stake_state: StakeState,
+    /// Per-NFT reward multiplier in basis points, copied from the NFT's
+    /// `NftMultiplier` when it is staked. Zero means no multiplier.
+    pub multiplier_bps: u16,
+    /// Lock chosen when the NFT was staked, counted from `stake_start_time`.
+    pub lock_tier: LockTier,
+    /// Whether the current stake is counted in the user's
+    /// `UserStakeSummary::staked_count`. Stakes made before the summary
+    /// existed are not, so unstaking them leaves the count alone.
+    pub counted_in_summary: bool,
 }
 
 impl UserStakeInfo {
-    pub const SIZE: usize = 1 + 32 + 8 + 8 + 32 + 1;
+    /// Size of accounts created before `multiplier_bps`, `lock_tier` and
+    /// `counted_in_summary` were added.
+    pub const LEGACY_SIZE: usize = 1 + 32 + 8 + 8 + 32 + 1;
+    pub const SIZE: usize = UserStakeInfo::LEGACY_SIZE + 2 + 1 + 1;
+
+    /// Reads legacy accounts too, treating their missing fields as zero.
+    pub fn unpack_from(data: &[u8]) -> Result<Self, StakeError> {
+        let mut buf = data.to_vec();
+        if buf.len() < Self::SIZE {
+            buf.resize(Self::SIZE, 0);
+        }
+        try_from_slice_unchecked(&buf).map_err(|_| StakeError::CorruptAccountData)
+    }
+
+    /// Writes into `dst`, which may be a legacy account as long as every
+    /// field that does not fit is still zero.
+    pub fn pack_into(&self, dst: &mut [u8]) -> Result<(), StakeError> {
+        let data = self
+            .try_to_vec()
+            .map_err(|_| StakeError::CorruptAccountData)?;
+        let len = data.len().min(dst.len());
+        if data[len..].iter().any(|byte| *byte != 0) {
+            return Err(StakeError::StakeAccountTooSmall);
+        }
+        dst[..len].copy_from_slice(&data[..len]);
+        Ok(())
+    }
+
+    pub fn multiplier_bps(&self) -> u16 {
+        if self.multiplier_bps == 0 {
+            NftMultiplier::BASE_BPS
+        } else {
+            self.multiplier_bps
+        }
+    }
+
+    pub fn lock_expires_at(&self) -> UnixTimestamp {
+        self.stake_start_time
+            .saturating_add(self.lock_tier.duration_seconds())
+    }
 }
 
 impl Sealed for UserStakeInfo {}
@@ -27,8 +78,346 @@ impl IsInitialized for UserStakeInfo {
     }
 }
 
+/// Pins which NFTs may be staked, seeded by `[b"stake_pool"]`.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct StakePool {
+    pub is_initialized: bool,
+    pub authority: Pubkey,
+    /// Verified Metaplex collection every staked NFT must belong to.
+    pub collection: Pubkey,
+    /// When not empty, staked NFTs must also have one of these as a verified
+    /// creator.
+    pub creators: Vec<Pubkey>,
+    /// Whole reward tokens earned per staked NFT per second, scaled by
+    /// `rewards::RATE_SCALE`.
+    pub rate_per_second: u64,
+    /// Decimals of the reward mint.
+    pub decimals: u8,
+    /// What happens when an NFT is redeemed or unstaked before its lock
+    /// expires.
+    pub early_unstake: EarlyUnstake,
+}
+
+impl StakePool {
+    /// Metaplex allows at most 5 creators per NFT.
+    pub const MAX_CREATORS: usize = 5;
+    pub const SIZE: usize = 1 + 32 + 32 + (4 + 32 * StakePool::MAX_CREATORS) + 8 + 1 + 3;
+
+    pub fn check_nft(&self, metadata: &Metadata) -> Result<(), StakeError> {
+        match &metadata.collection {
+            Some(collection) if collection.verified && collection.key == self.collection => {}
+            _ => return Err(StakeError::NftNotInCollection),
+        }
+
+        if self.creators.is_empty() {
+            return Ok(());
+        }
+
+        let has_allowed_creator = metadata
+            .data
+            .creators
+            .iter()
+            .flatten()
+            .any(|creator| creator.verified && self.creators.contains(&creator.address));
+        if !has_allowed_creator {
+            return Err(StakeError::CreatorNotAllowed);
+        }
+
+        Ok(())
+    }
+}
+
+impl Sealed for StakePool {}
+
+impl IsInitialized for StakePool {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Running totals across all of a user's NFTs, seeded by
+/// `[b"user_summary", user]`, so dashboards need a single fetch.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct UserStakeSummary {
+    pub is_initialized: bool,
+    pub user: Pubkey,
+    pub staked_count: u32,
+    /// Rewards earned before any early-unstake penalty.
+    pub total_accrued: u64,
+    /// Rewards actually minted to the user.
+    pub total_claimed: u64,
+    pub last_activity: UnixTimestamp,
+}
+
+impl UserStakeSummary {
+    pub const SIZE: usize = 1 + 32 + 4 + 8 + 8 + 8;
+
+    pub fn record_stake(&mut self, nfts: u32, now: UnixTimestamp) {
+        self.staked_count = self.staked_count.saturating_add(nfts);
+        self.last_activity = now;
+    }
+
+    /// `nfts` must only count stakes marked `counted_in_summary`.
+    pub fn record_unstake(&mut self, nfts: u32, now: UnixTimestamp) {
+        self.staked_count = self.staked_count.saturating_sub(nfts);
+        self.last_activity = now;
+    }
+
+    pub fn record_rewards(
+        &mut self,
+        accrued: u64,
+        claimed: u64,
+        now: UnixTimestamp,
+    ) -> Result<(), StakeError> {
+        self.total_accrued = self
+            .total_accrued
+            .checked_add(accrued)
+            .ok_or(StakeError::RewardOverflow)?;
+        self.total_claimed = self
+            .total_claimed
+            .checked_add(claimed)
+            .ok_or(StakeError::RewardOverflow)?;
+        self.last_activity = now;
+        Ok(())
+    }
+}
+
+impl Sealed for UserStakeSummary {}
+
+impl IsInitialized for UserStakeSummary {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Reward multiplier for a single NFT, seeded by `[b"multiplier", nft_mint]`.
+/// Attributes live in the off-chain metadata JSON, so the pool authority
+/// publishes the multiplier each NFT's attributes earn.
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct NftMultiplier {
+    pub is_initialized: bool,
+    pub mint: Pubkey,
+    pub multiplier_bps: u16,
+}
+
+impl NftMultiplier {
+    pub const SIZE: usize = 1 + 32 + 2;
+    /// Multiplier for NFTs without an `NftMultiplier` account.
+    pub const BASE_BPS: u16 = 10_000;
+}
+
+impl Sealed for NftMultiplier {}
+
+impl IsInitialized for NftMultiplier {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// Minimum staking period chosen at `Stake`; longer locks earn a boosted rate.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LockTier {
+    None,
+    ThirtyDays,
+    NinetyDays,
+    OneEightyDays,
+}
+
+impl LockTier {
+    pub fn duration_seconds(self) -> i64 {
+        const DAY: i64 = 24 * 60 * 60;
+        match self {
+            LockTier::None => 0,
+            LockTier::ThirtyDays => 30 * DAY,
+            LockTier::NinetyDays => 90 * DAY,
+            LockTier::OneEightyDays => 180 * DAY,
+        }
+    }
+
+    /// Reward boost in basis points, applied on top of the NFT's multiplier.
+    pub fn boost_bps(self) -> u16 {
+        match self {
+            LockTier::None => 10_000,
+            LockTier::ThirtyDays => 12_500,
+            LockTier::NinetyDays => 15_000,
+            LockTier::OneEightyDays => 20_000,
+        }
+    }
+}
+
+/// Pool policy for redeeming or unstaking before a lock expires.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq)]
+pub enum EarlyUnstake {
+    Reject,
+    /// Both are allowed but forfeit this share of the pending reward.
+    Forfeit {
+        penalty_bps: u16,
+    },
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 pub enum StakeState {
     Staked,
     Unstaked,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mpl_token_metadata::state::{Collection, Creator, Data, Key};
+
+    fn pool(creators: Vec<Pubkey>) -> StakePool {
+        StakePool {
+            is_initialized: true,
+            authority: Pubkey::new_unique(),
+            collection: Pubkey::new_unique(),
+            creators,
+            rate_per_second: 0,
+            decimals: 0,
+            early_unstake: EarlyUnstake::Reject,
+        }
+    }
+
+    fn metadata(collection: Option<Collection>, creators: Option<Vec<Creator>>) -> Metadata {
+        Metadata {
+            key: Key::MetadataV1,
+            update_authority: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            data: Data {
+                creators,
+                ..Data::default()
+            },
+            primary_sale_happened: false,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection,
+            uses: None,
+            collection_details: None,
+            programmable_config: None,
+        }
+    }
+
+    fn member_of(pool: &StakePool, verified: bool) -> Option<Collection> {
+        Some(Collection {
+            verified,
+            key: pool.collection,
+        })
+    }
+
+    fn creator(address: Pubkey, verified: bool) -> Creator {
+        Creator {
+            address,
+            verified,
+            share: 100,
+        }
+    }
+
+    #[test]
+    fn check_nft_requires_a_verified_collection() {
+        let pool = pool(vec![]);
+        assert!(pool
+            .check_nft(&metadata(member_of(&pool, true), None))
+            .is_ok());
+
+        for collection in [
+            None,
+            member_of(&pool, false),
+            Some(Collection {
+                verified: true,
+                key: Pubkey::new_unique(),
+            }),
+        ] {
+            assert!(matches!(
+                pool.check_nft(&metadata(collection, None)),
+                Err(StakeError::NftNotInCollection)
+            ));
+        }
+    }
+
+    #[test]
+    fn check_nft_requires_a_verified_allowed_creator() {
+        let allowed = Pubkey::new_unique();
+        let pool = pool(vec![Pubkey::new_unique(), allowed]);
+        let other = creator(Pubkey::new_unique(), true);
+
+        assert!(pool
+            .check_nft(&metadata(
+                member_of(&pool, true),
+                Some(vec![other.clone(), creator(allowed, true)])
+            ))
+            .is_ok());
+
+        for creators in [
+            None,
+            Some(vec![]),
+            Some(vec![other]),
+            Some(vec![creator(allowed, false)]),
+        ] {
+            assert!(matches!(
+                pool.check_nft(&metadata(member_of(&pool, true), creators)),
+                Err(StakeError::CreatorNotAllowed)
+            ));
+        }
+    }
+
+    #[test]
+    fn check_nft_checks_the_collection_before_creators() {
+        let allowed = Pubkey::new_unique();
+        let pool = pool(vec![allowed]);
+        assert!(matches!(
+            pool.check_nft(&metadata(
+                member_of(&pool, false),
+                Some(vec![creator(allowed, true)])
+            )),
+            Err(StakeError::NftNotInCollection)
+        ));
+    }
+
+    fn summary() -> UserStakeSummary {
+        UserStakeSummary {
+            is_initialized: true,
+            user: Pubkey::new_unique(),
+            staked_count: 0,
+            total_accrued: 0,
+            total_claimed: 0,
+            last_activity: 0,
+        }
+    }
+
+    #[test]
+    fn summary_tracks_staked_count_and_activity() {
+        let mut summary = summary();
+        summary.record_stake(3, 100);
+        summary.record_unstake(1, 200);
+        assert_eq!(summary.staked_count, 2);
+        assert_eq!(summary.last_activity, 200);
+
+        // Unstaking more NFTs than were counted cannot underflow.
+        summary.record_unstake(5, 300);
+        assert_eq!(summary.staked_count, 0);
+    }
+
+    #[test]
+    fn summary_separates_accrued_from_claimed() {
+        let mut summary = summary();
+        summary.record_rewards(1_000, 1_000, 100).unwrap();
+        summary.record_rewards(400, 200, 200).unwrap();
+        assert_eq!(summary.total_accrued, 1_400);
+        assert_eq!(summary.total_claimed, 1_200);
+        assert_eq!(summary.last_activity, 200);
+
+        assert!(matches!(
+            summary.record_rewards(u64::MAX, 0, 300),
+            Err(StakeError::RewardOverflow)
+        ));
+    }
+
+    #[test]
+    fn summary_size_matches_serialized_len() {
+        assert_eq!(
+            summary().try_to_vec().unwrap().len(),
+            UserStakeSummary::SIZE
+        );
+    }
+}
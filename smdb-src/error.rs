@@ -11,6 +11,10 @@ pub enum ReviewError {
     InvalidDataLength,
     #[error("Rating greater than 5 or less than 1")]
     InvalidRating,
+    #[error("Instruction payload could not be deserialized")]
+    InvalidInstructionPayload,
+    #[error("Account data could not be deserialized")]
+    CorruptAccountData,
 }
 
 impl From<ReviewError> for ProgramError {
@@ -1,6 +1,8 @@
-use borsh::BorshDeserialize;
+use crate::error::ReviewError;
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_error::ProgramError;
 
+#[derive(Clone, Debug, PartialEq)]
 pub enum MovieInstruction {
     AddMovieReview {
         title: String,
@@ -12,6 +14,7 @@ pub enum MovieInstruction {
         rating: u8,
         description: String,
     },
+    CloseMovieReview,
 }
 
 #[derive(BorshDeserialize)]
@@ -21,24 +24,126 @@ struct MovieReviewPayload {
     description: String,
 }
 
+/// Instruction tags, the first byte of every instruction. Tags are part of the
+/// wire format: an existing tag is never renumbered or reused, new
+/// instructions take the next free value.
+///
+/// There is no separate version byte; the tag carries the version. A tag's
+/// payload is frozen once deployed, so changing an instruction's payload means
+/// adding a new tag for the new layout and keeping the old one decodable for
+/// clients that still send it.
+pub mod tag {
+    pub const ADD_MOVIE_REVIEW: u8 = 0;
+    pub const UPDATE_MOVIE_REVIEW: u8 = 1;
+    pub const CLOSE_MOVIE_REVIEW: u8 = 2;
+}
+
 impl MovieInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (&variant, rest) = input
             .split_first()
             .ok_or(ProgramError::InvalidInstructionData)?;
-        let payload = MovieReviewPayload::try_from_slice(rest).unwrap();
         Ok(match variant {
-            0 => Self::AddMovieReview {
-                title: payload.title,
-                rating: payload.rating,
-                description: payload.description,
-            },
-            1 => Self::UpdateMovieReview {
-                title: payload.title,
-                rating: payload.rating,
-                description: payload.description,
-            },
+            tag::ADD_MOVIE_REVIEW => {
+                let payload = MovieReviewPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::AddMovieReview {
+                    title: payload.title,
+                    rating: payload.rating,
+                    description: payload.description,
+                }
+            }
+            tag::UPDATE_MOVIE_REVIEW => {
+                let payload = MovieReviewPayload::try_from_slice(rest)
+                    .map_err(|_| ReviewError::InvalidInstructionPayload)?;
+                Self::UpdateMovieReview {
+                    title: payload.title,
+                    rating: payload.rating,
+                    description: payload.description,
+                }
+            }
+            tag::CLOSE_MOVIE_REVIEW => Self::CloseMovieReview,
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::AddMovieReview {
+                title,
+                rating,
+                description,
+            } => {
+                buf.push(tag::ADD_MOVIE_REVIEW);
+                (title, rating, description).serialize(&mut buf).unwrap();
+            }
+            Self::UpdateMovieReview {
+                title,
+                rating,
+                description,
+            } => {
+                buf.push(tag::UPDATE_MOVIE_REVIEW);
+                (title, rating, description).serialize(&mut buf).unwrap();
+            }
+            Self::CloseMovieReview => buf.push(tag::CLOSE_MOVIE_REVIEW),
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, proptest::prelude::*};
+
+    fn movie_instruction() -> impl Strategy<Value = MovieInstruction> {
+        prop_oneof![
+            (any::<String>(), any::<u8>(), any::<String>()).prop_map(
+                |(title, rating, description)| MovieInstruction::AddMovieReview {
+                    title,
+                    rating,
+                    description,
+                }
+            ),
+            (any::<String>(), any::<u8>(), any::<String>()).prop_map(
+                |(title, rating, description)| MovieInstruction::UpdateMovieReview {
+                    title,
+                    rating,
+                    description,
+                }
+            ),
+            Just(MovieInstruction::CloseMovieReview),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_pack_unpack_round_trip(instruction in movie_instruction()) {
+            let packed = instruction.pack();
+            prop_assert_eq!(MovieInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn test_truncated_payloads_are_rejected(
+            title in any::<String>(),
+            rating in any::<u8>(),
+            description in any::<String>(),
+            cut in 1usize..=4,
+        ) {
+            let packed = MovieInstruction::AddMovieReview { title, rating, description }.pack();
+            let truncated = &packed[..packed.len() - cut];
+            prop_assert_eq!(
+                MovieInstruction::unpack(truncated).unwrap_err(),
+                ReviewError::InvalidInstructionPayload.into()
+            );
+        }
+
+        #[test]
+        fn test_unknown_tags_are_rejected(variant in (tag::CLOSE_MOVIE_REVIEW + 1)..=u8::MAX) {
+            prop_assert_eq!(
+                MovieInstruction::unpack(&[variant]).unwrap_err(),
+                ProgramError::InvalidInstructionData
+            );
+        }
+    }
 }
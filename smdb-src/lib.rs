@@ -2,4 +2,5 @@ pub mod entrypoint;
 pub mod instruction;
 pub mod processor;
 pub mod state;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod title;
\ No newline at end of file
@@ -1,7 +1,8 @@
 use borsh::BorshSerialize;
 use crate::error::ReviewError;
 use crate::instruction::MovieInstruction;
-use crate::state::MovieAccountState;
+use crate::state::{MovieAccountState, MovieStats};
+use crate::title::title_hash;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     borsh::try_from_slice_unchecked,
@@ -9,7 +10,7 @@ use solana_program::{
     msg,
     program::invoke_signed,
     program_error::ProgramError,
-    pubkey::Pubkey,
+    pubkey::{Pubkey, MAX_SEED_LEN},
     system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
@@ -32,6 +33,7 @@ pub fn process_instruction(
             rating,
             description,
         } => update_movie_review(program_id, accounts, title, rating, description),
+        MovieInstruction::CloseMovieReview => close_movie_review(program_id, accounts),
     }
 }
 
@@ -51,6 +53,7 @@ pub fn add_movie_review(
 
     let initializer = next_account_info(account_info_iter)?;
     let pda_account = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
 
     if !initializer.is_signer {
@@ -59,7 +62,7 @@ pub fn add_movie_review(
     }
 
     let (pda, bump_seed) = Pubkey::find_program_address(
-        &[initializer.key.as_ref(), title.as_bytes().as_ref()],
+        &[initializer.key.as_ref(), title_hash(&title).as_ref()],
         program_id,
     );
 
@@ -75,7 +78,7 @@ pub fn add_movie_review(
 
     let account_len = 1000;
 
-    let total_len: usize = 1 + 1 + (4 + title.len()) + (4 + description.len());
+    let total_len: usize = 1 + 1 + (4 + title.len()) + (4 + description.len()) + 1;
     if total_len > 1000 {
         msg!("Data length is larger than 1000 bytes");
         return Err(ReviewError::InvalidDataLength.into());
@@ -99,7 +102,7 @@ pub fn add_movie_review(
         ],
         &[&[
             initializer.key.as_ref(),
-            title.as_bytes().as_ref(),
+            title_hash(&title).as_ref(),
             &[bump_seed],
         ]],
     )?;
@@ -108,18 +111,56 @@ pub fn add_movie_review(
 
     msg!("unpacking state account");
     let mut account_data =
-        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow()).unwrap();
+        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
     msg!("borrowed account data");
 
-    account_data.title = title;
+    account_data.title = title.clone();
     account_data.rating = rating;
     account_data.description = description;
     account_data.is_initialized = true;
+    account_data.counted_in_stats = true;
 
     msg!("serializing account");
     account_data.serialize(&mut &mut pda_account.data.borrow_mut()[..])?;
     msg!("state account serialized");
 
+    let (stats_pda, stats_bump_seed) =
+        Pubkey::find_program_address(&[b"stats", title_hash(&title).as_ref()], program_id);
+
+    if stats_pda != *pda_stats.key {
+        msg!("Invalid seeds for stats PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if pda_stats.data_is_empty() {
+        msg!("Creating movie stats account");
+        invoke_signed(
+            &system_instruction::create_account(
+                initializer.key,
+                pda_stats.key,
+                rent.minimum_balance(MovieStats::SIZE),
+                MovieStats::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                initializer.clone(),
+                pda_stats.clone(),
+                system_program.clone(),
+            ],
+            &[&[b"stats", title_hash(&title).as_ref(), &[stats_bump_seed]]],
+        )?;
+    } else if pda_stats.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut stats_data = try_from_slice_unchecked::<MovieStats>(&pda_stats.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+    stats_data.is_initialized = true;
+    stats_data.add_rating(rating);
+    stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
+    msg!("Movie stats updated: {} reviews", stats_data.review_count);
+
     Ok(())
 }
 
@@ -136,6 +177,7 @@ pub fn update_movie_review(
 
     let initializer = next_account_info(account_info_iter)?;
     let pda_account = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
 
     if pda_account.owner != program_id {
         return Err(ProgramError::IllegalOwner);
@@ -147,17 +189,15 @@ pub fn update_movie_review(
 
     msg!("Unpacking state account...");
     let mut account_data =
-        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow()).unwrap();
+        try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+            .map_err(|_| ReviewError::CorruptAccountData)?;
 
-    let (pda, bump_seed) = Pubkey::find_program_address(
-        &[
-            initializer.key.as_ref(),
-            account_data.title.as_bytes().as_ref(),
-        ],
+    if !is_review_address(
         program_id,
-    );
-
-    if pda != *pda_account.key {
+        initializer.key,
+        &account_data.title,
+        pda_account.key,
+    ) {
         msg!("Invalid seeds for PDA");
         return Err(ReviewError::InvalidPDA.into());
     }
@@ -172,12 +212,18 @@ pub fn update_movie_review(
         return Err(ReviewError::InvalidRating.into());
     }
 
-    let total_len = 1 + (4 + account_data.title.len()) + (4 + description.len());
+    let total_len = 1 + 1 + (4 + account_data.title.len()) + (4 + description.len()) + 1;
     if total_len > 1000 {
         msg!("Data length is larger than 1000 bytes");
         return Err(ReviewError::InvalidDataLength.into());
     }
 
+    if let Some(mut stats_data) = load_stats(program_id, pda_stats, &account_data)? {
+        stats_data.remove_rating(account_data.rating);
+        stats_data.add_rating(rating);
+        stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
+    }
+
     account_data.rating = rating;
     account_data.description = description;
 
@@ -185,3 +231,261 @@ pub fn update_movie_review(
 
     Ok(())
 }
+
+pub fn close_movie_review(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Closing movie review...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let initializer = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let pda_stats = next_account_info(account_info_iter)?;
+
+    if pda_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    if !initializer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    msg!("Unpacking state account...");
+    let account_data = try_from_slice_unchecked::<MovieAccountState>(&pda_account.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !account_data.is_initialized {
+        msg!("Account is not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if !is_review_address(
+        program_id,
+        initializer.key,
+        &account_data.title,
+        pda_account.key,
+    ) {
+        msg!("Signer is not the reviewer of this account");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if let Some(mut stats_data) = load_stats(program_id, pda_stats, &account_data)? {
+        stats_data.remove_rating(account_data.rating);
+        stats_data.serialize(&mut &mut pda_stats.data.borrow_mut()[..])?;
+    }
+
+    let lamports = pda_account.lamports();
+    **pda_account.try_borrow_mut_lamports()? = 0;
+    **initializer.try_borrow_mut_lamports()? += lamports;
+    pda_account.data.borrow_mut().fill(0);
+
+    msg!("Movie review closed, refunded {} lamports", lamports);
+
+    Ok(())
+}
+
+/// Whether `review` is the review `reviewer` posted under `title`, seeded with
+/// the title hash or, for reviews created before that, the raw title. Raw
+/// titles longer than a seed never made it on chain.
+fn is_review_address(program_id: &Pubkey, reviewer: &Pubkey, title: &str, review: &Pubkey) -> bool {
+    let (pda, _bump_seed) =
+        Pubkey::find_program_address(&[reviewer.as_ref(), title_hash(title).as_ref()], program_id);
+    if pda == *review {
+        return true;
+    }
+
+    if title.len() > MAX_SEED_LEN {
+        return false;
+    }
+    let (legacy_pda, _bump_seed) =
+        Pubkey::find_program_address(&[reviewer.as_ref(), title.as_bytes()], program_id);
+    legacy_pda == *review
+}
+
+/// Loads the stats that count `review`, or `None` if it was posted before
+/// stats were tracked. Its rating was never tallied, so there is nothing to
+/// adjust even if the title has stats from newer reviews.
+fn load_stats(
+    program_id: &Pubkey,
+    pda_stats: &AccountInfo,
+    review: &MovieAccountState,
+) -> Result<Option<MovieStats>, ProgramError> {
+    let (stats_pda, _stats_bump_seed) =
+        Pubkey::find_program_address(&[b"stats", title_hash(&review.title).as_ref()], program_id);
+
+    if stats_pda != *pda_stats.key {
+        msg!("Invalid seeds for stats PDA");
+        return Err(ReviewError::InvalidPDA.into());
+    }
+
+    if !review.counted_in_stats {
+        msg!("Review is not counted in the title's stats");
+        return Ok(None);
+    }
+
+    if pda_stats.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let stats_data = try_from_slice_unchecked::<MovieStats>(&pda_stats.data.borrow())
+        .map_err(|_| ReviewError::CorruptAccountData)?;
+
+    if !stats_data.is_initialized {
+        msg!("Stats are not initialized");
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    Ok(Some(stats_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLE: &str = "The Godfather";
+
+    fn review_data(title: &str) -> Vec<u8> {
+        MovieAccountState {
+            is_initialized: true,
+            rating: 4,
+            title: title.to_string(),
+            description: "Classic".to_string(),
+            counted_in_stats: true,
+        }
+        .try_to_vec()
+        .unwrap()
+    }
+
+    /// Runs `test` against the reviewer, review and stats accounts, all
+    /// owned by `program_id` except the reviewer.
+    fn with_accounts(
+        program_id: Pubkey,
+        review: Vec<u8>,
+        stats: Vec<u8>,
+        test: impl FnOnce(&[AccountInfo]),
+    ) {
+        let reviewer = Pubkey::new_unique();
+        let (review_key, _) = Pubkey::find_program_address(
+            &[reviewer.as_ref(), title_hash(TITLE).as_ref()],
+            &program_id,
+        );
+        let (stats_key, _) =
+            Pubkey::find_program_address(&[b"stats", title_hash(TITLE).as_ref()], &program_id);
+        let system_program = Pubkey::default();
+
+        let (mut reviewer_lamports, mut review_lamports, mut stats_lamports) = (0, 1_000, 1_000);
+        let (mut reviewer_data, mut review, mut stats) = (vec![], review, stats);
+        let accounts = [
+            AccountInfo::new(
+                &reviewer,
+                true,
+                true,
+                &mut reviewer_lamports,
+                &mut reviewer_data,
+                &system_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &review_key,
+                false,
+                true,
+                &mut review_lamports,
+                &mut review,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &stats_key,
+                false,
+                true,
+                &mut stats_lamports,
+                &mut stats,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        test(&accounts);
+    }
+
+    fn stats_data(ratings: &[u8]) -> Vec<u8> {
+        let mut stats = MovieStats {
+            is_initialized: true,
+            review_count: 0,
+            rating_sum: 0,
+            rating_histogram: [0; 5],
+        };
+        for &rating in ratings {
+            stats.add_rating(rating);
+        }
+        stats.try_to_vec().unwrap()
+    }
+
+    fn corrupt() -> ProgramError {
+        ReviewError::CorruptAccountData.into()
+    }
+
+    #[test]
+    fn test_corrupt_review_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        // A title whose length prefix runs past the end of the account.
+        let review = vec![1, 4, 0xff, 0xff, 0xff, 0x7f, b'x'];
+
+        with_accounts(program_id, review.clone(), vec![], |accounts| {
+            let err = update_movie_review(&program_id, accounts, TITLE.into(), 5, "".into());
+            assert_eq!(err.unwrap_err(), corrupt());
+        });
+        with_accounts(program_id, review, vec![], |accounts| {
+            let err = close_movie_review(&program_id, accounts);
+            assert_eq!(err.unwrap_err(), corrupt());
+        });
+    }
+
+    #[test]
+    fn test_corrupt_stats_are_rejected() {
+        let program_id = Pubkey::new_unique();
+        // Stats cut short after the review count.
+        let stats = vec![1; 1 + 8];
+
+        with_accounts(program_id, review_data(TITLE), stats.clone(), |accounts| {
+            let err = update_movie_review(&program_id, accounts, TITLE.into(), 5, "".into());
+            assert_eq!(err.unwrap_err(), corrupt());
+        });
+        with_accounts(program_id, review_data(TITLE), stats, |accounts| {
+            let err = close_movie_review(&program_id, accounts);
+            assert_eq!(err.unwrap_err(), corrupt());
+            // Nothing was refunded.
+            assert_eq!(accounts[1].lamports(), 1_000);
+        });
+    }
+
+    #[test]
+    fn test_legacy_reviews_stay_out_of_stats_created_later() {
+        let program_id = Pubkey::new_unique();
+        // Posted before stats were tracked, so it has no counted marker.
+        let mut legacy = (true, 4u8, TITLE.to_string(), "Classic".to_string())
+            .try_to_vec()
+            .unwrap();
+        legacy.resize(1000, 0);
+        // The title's stats count a single newer review rated 5.
+        let stats = stats_data(&[5]);
+
+        with_accounts(program_id, legacy.clone(), stats.clone(), |accounts| {
+            update_movie_review(&program_id, accounts, TITLE.into(), 1, "Meh".into()).unwrap();
+            assert_eq!(accounts[2].data.borrow().as_ref(), stats.as_slice());
+        });
+        with_accounts(program_id, legacy, stats.clone(), |accounts| {
+            close_movie_review(&program_id, accounts).unwrap();
+            assert_eq!(accounts[2].data.borrow().as_ref(), stats.as_slice());
+            assert_eq!(accounts[0].lamports(), 1_000);
+        });
+
+        // A counted review still comes out of the stats when closed.
+        let counted = review_data(TITLE);
+        with_accounts(program_id, counted, stats_data(&[5, 4]), |accounts| {
+            close_movie_review(&program_id, accounts).unwrap();
+            assert_eq!(accounts[2].data.borrow().as_ref(), stats.as_slice());
+        });
+    }
+}
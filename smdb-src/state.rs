@@ -1,18 +1,135 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_pack::{IsInitialized, Sealed};
+use std::io;
 
-#[derive(BorshSerialize, BorshDeserialize)]
 pub struct MovieAccountState {
     pub is_initialized: bool,
     pub rating: u8,
     pub title: String,
     pub description: String,
+    /// Whether `rating` is tallied in the title's `MovieStats`. Reviews posted
+    /// before stats were tracked are not, even once the title has stats.
+    pub counted_in_stats: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieStats {
+    pub is_initialized: bool,
+    pub review_count: u64,
+    pub rating_sum: u64,
+    pub rating_histogram: [u64; 5],
+}
+
+impl MovieAccountState {
+    /// Follows the description when the review is counted in stats. Reviews
+    /// are 1000 bytes and, in those posted before stats were tracked, the
+    /// description is followed by zero padding or leftovers of a longer
+    /// description. 0xff never occurs in UTF-8, so leftovers can't pass for it.
+    pub const COUNTED_IN_STATS: u8 = 0xff;
+}
+
+impl BorshSerialize for MovieAccountState {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.is_initialized.serialize(writer)?;
+        self.rating.serialize(writer)?;
+        self.title.serialize(writer)?;
+        self.description.serialize(writer)?;
+        let marker = if self.counted_in_stats {
+            MovieAccountState::COUNTED_IN_STATS
+        } else {
+            0
+        };
+        marker.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for MovieAccountState {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(MovieAccountState {
+            is_initialized: bool::deserialize(buf)?,
+            rating: u8::deserialize(buf)?,
+            title: String::deserialize(buf)?,
+            description: String::deserialize(buf)?,
+            counted_in_stats: buf.first() == Some(&MovieAccountState::COUNTED_IN_STATS),
+        })
+    }
+}
+
+impl MovieStats {
+    pub const SIZE: usize = 1 + 8 + 8 + (8 * 5);
+
+    pub fn add_rating(&mut self, rating: u8) {
+        self.review_count += 1;
+        self.rating_sum += rating as u64;
+        self.rating_histogram[(rating - 1) as usize] += 1;
+    }
+
+    pub fn remove_rating(&mut self, rating: u8) {
+        self.review_count = self.review_count.saturating_sub(1);
+        self.rating_sum = self.rating_sum.saturating_sub(rating as u64);
+        let bucket = &mut self.rating_histogram[(rating - 1) as usize];
+        *bucket = bucket.saturating_sub(1);
+    }
 }
 
 impl Sealed for MovieAccountState {}
+impl Sealed for MovieStats {}
 
 impl IsInitialized for MovieAccountState {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
+
+impl IsInitialized for MovieStats {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(description: &str, counted_in_stats: bool) -> MovieAccountState {
+        MovieAccountState {
+            is_initialized: true,
+            rating: 4,
+            title: "The Godfather".to_string(),
+            description: description.to_string(),
+            counted_in_stats,
+        }
+    }
+
+    #[test]
+    fn test_counted_flag_round_trips() {
+        for counted_in_stats in [true, false] {
+            let mut data = review("Classic", counted_in_stats).try_to_vec().unwrap();
+            data.resize(1000, 0);
+
+            let decoded = MovieAccountState::deserialize(&mut data.as_slice()).unwrap();
+            assert_eq!(decoded.description, "Classic");
+            assert_eq!(decoded.counted_in_stats, counted_in_stats);
+        }
+    }
+
+    #[test]
+    fn test_legacy_review_with_leftovers_is_not_counted() {
+        // A review posted before stats were tracked, whose description was
+        // later shortened in place. A bool flag would read the leftover 0x01
+        // after the new description as counted.
+        let legacy = |description: &str| {
+            (true, 4u8, "Up".to_string(), description.to_string())
+                .try_to_vec()
+                .unwrap()
+        };
+        let mut data = legacy("Classic\u{1} with leftovers");
+        data.resize(1000, 0);
+        let shortened = legacy("Classic");
+        data[..shortened.len()].copy_from_slice(&shortened);
+
+        let decoded = MovieAccountState::deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded.description, "Classic");
+        assert!(!decoded.counted_in_stats);
+    }
+}
@@ -11,6 +11,18 @@ pub enum StudentIntroError {
     InvalidDataLength,
     #[error("Accounts do not match")]
     IncorrectAccountError,
+    #[error("Instruction payload could not be deserialized")]
+    InvalidInstructionPayload,
+    #[error("Account data could not be deserialized")]
+    CorruptAccountData,
+    #[error("GitHub handle is invalid")]
+    InvalidGithubHandle,
+    #[error("Website URL is invalid")]
+    InvalidWebsiteUrl,
+    #[error("Signer is not allowed to perform this action")]
+    Unauthorized,
+    #[error("Too many moderators")]
+    TooManyModerators,
 }
 
 impl From<StudentIntroError> for ProgramError {
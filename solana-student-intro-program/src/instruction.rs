@@ -1,11 +1,33 @@
+use crate::error::StudentIntroError;
+use crate::state::StudentProfile;
 use borsh::BorshDeserialize;
-use solana_program::program_error::ProgramError;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
 pub enum IntroInstruction {
-    InitUserInput { name: String, message: String },
-    UpdateStudentIntro { name: String, message: String },
-    AddReply { reply: String },
-    InitializeMint,
+    InitUserInput {
+        name: String,
+        message: String,
+        profile: Option<StudentProfile>,
+    },
+    UpdateStudentIntro {
+        name: String,
+        message: String,
+        profile: Option<StudentProfile>,
+    },
+    AddReply {
+        reply: String,
+    },
+    InitializeMint {
+        freeze_authority: Option<Pubkey>,
+    },
+    InitializeConfig {
+        moderators: Vec<Pubkey>,
+    },
+    SetModerators {
+        moderators: Vec<Pubkey>,
+    },
+    HideReply,
+    UnhideReply,
 }
 
 #[derive(BorshDeserialize, Debug)]
@@ -19,6 +41,30 @@ struct ReplyPayload {
     reply: String,
 }
 
+#[derive(BorshDeserialize, Debug)]
+struct ModeratorsPayload {
+    moderators: Vec<Pubkey>,
+}
+
+/// Older clients send only `name` and `message`; the profile fields are an
+/// optional trailing section.
+fn unpack_intro(
+    input: &[u8],
+) -> Result<(StudentIntroPayload, Option<StudentProfile>), ProgramError> {
+    let mut rest = input;
+    let payload = StudentIntroPayload::deserialize(&mut rest)
+        .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
+
+    if rest.is_empty() {
+        return Ok((payload, None));
+    }
+
+    let profile = StudentProfile::try_from_slice(rest)
+        .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
+
+    Ok((payload, Some(profile)))
+}
+
 impl IntroInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (variant, rest) = input
@@ -27,28 +73,56 @@ impl IntroInstruction {
 
         Ok(match variant {
             0 => {
-                let payload = StudentIntroPayload::try_from_slice(rest).unwrap();
+                let (payload, profile) = unpack_intro(rest)?;
 
                 Self::InitUserInput {
                     name: payload.name,
                     message: payload.message,
+                    profile,
                 }
             }
             1 => {
-                let payload = StudentIntroPayload::try_from_slice(rest).unwrap();
+                let (payload, profile) = unpack_intro(rest)?;
 
                 Self::UpdateStudentIntro {
                     name: payload.name,
                     message: payload.message,
+                    profile,
                 }
             }
             2 => {
-                let payload = ReplyPayload::try_from_slice(rest).unwrap();
+                let payload = ReplyPayload::try_from_slice(rest)
+                    .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
                 Self::AddReply {
                     reply: payload.reply,
                 }
             }
-            3 => Self::InitializeMint,
+            3 => {
+                // Older clients send no payload and get a mint without a freeze authority.
+                let freeze_authority = if rest.is_empty() {
+                    None
+                } else {
+                    Option::<Pubkey>::try_from_slice(rest)
+                        .map_err(|_| StudentIntroError::InvalidInstructionPayload)?
+                };
+                Self::InitializeMint { freeze_authority }
+            }
+            4 => {
+                let payload = ModeratorsPayload::try_from_slice(rest)
+                    .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
+                Self::InitializeConfig {
+                    moderators: payload.moderators,
+                }
+            }
+            5 => {
+                let payload = ModeratorsPayload::try_from_slice(rest)
+                    .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
+                Self::SetModerators {
+                    moderators: payload.moderators,
+                }
+            }
+            6 => Self::HideReply,
+            7 => Self::UnhideReply,
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
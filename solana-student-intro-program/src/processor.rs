@@ -1,25 +1,27 @@
 use crate::instruction::IntroInstruction;
-use crate::state::{StudentInfo, StudentIntroReply};
+use crate::state::{
+    Config, ReplierCounter, ReplierIndex, StudentInfo, StudentIntroReply, StudentProfile,
+};
 use crate::{error::StudentIntroError, state::StudentReplyCounter};
 use borsh::BorshSerialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     borsh::try_from_slice_unchecked,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     entrypoint::ProgramResult,
     msg,
     native_token::LAMPORTS_PER_SOL,
-    program::invoke_signed,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
-    program_pack::IsInitialized,
+    program_pack::{IsInitialized, Pack},
+    program_utils::limited_deserialize,
     pubkey::Pubkey,
     system_instruction,
-    sysvar::{rent::Rent, Sysvar},
+    system_program::ID as SYSTEM_PROGRAM_ID,
+    sysvar::{rent::Rent, rent::ID as RENT_PROGRAM_ID, Sysvar},
 };
 use spl_associated_token_account::get_associated_token_address;
-use spl_token::{
-    instruction::{initialize_mint, mint_to},
-    ID as TOKEN_PROGRAM_ID,
-};
+use spl_token::{instruction::initialize_mint, state::Mint, ID as TOKEN_PROGRAM_ID};
 use std::convert::TryInto;
 
 pub fn process_instruction(
@@ -29,14 +31,28 @@ pub fn process_instruction(
 ) -> ProgramResult {
     let instruction = IntroInstruction::unpack(instruction_data)?;
     match instruction {
-        IntroInstruction::InitUserInput { name, message } => {
-            add_student_intro(program_id, accounts, name, message)
+        IntroInstruction::InitUserInput {
+            name,
+            message,
+            profile,
+        } => add_student_intro(program_id, accounts, name, message, profile),
+        IntroInstruction::UpdateStudentIntro {
+            name,
+            message,
+            profile,
+        } => update_student_intro(program_id, accounts, name, message, profile),
+        IntroInstruction::AddReply { reply } => add_reply(program_id, accounts, reply),
+        IntroInstruction::InitializeMint { freeze_authority } => {
+            initialize_token_mint(program_id, accounts, freeze_authority)
         }
-        IntroInstruction::UpdateStudentIntro { name, message } => {
-            update_student_intro(program_id, accounts, name, message)
+        IntroInstruction::InitializeConfig { moderators } => {
+            initialize_config(program_id, accounts, moderators)
         }
-        IntroInstruction::AddReply { reply } => add_reply(program_id, accounts, reply),
-        IntroInstruction::InitializeMint => initialize_token_mint(program_id, accounts),
+        IntroInstruction::SetModerators { moderators } => {
+            set_moderators(program_id, accounts, moderators)
+        }
+        IntroInstruction::HideReply => set_reply_hidden(program_id, accounts, true),
+        IntroInstruction::UnhideReply => set_reply_hidden(program_id, accounts, false),
     }
 }
 
@@ -45,6 +61,7 @@ pub fn add_student_intro(
     accounts: &[AccountInfo],
     name: String,
     message: String,
+    profile: Option<StudentProfile>,
 ) -> ProgramResult {
     msg!("Adding student intro...");
     msg!("Name: {}", name);
@@ -100,10 +117,13 @@ pub fn add_student_intro(
         return Err(StudentIntroError::IncorrectAccountError.into());
     }
 
-    let account_len: usize = 1000;
+    let profile = profile.unwrap_or_default();
+    profile.validate()?;
+
+    let account_len: usize = StudentInfo::ACCOUNT_SIZE;
 
-    if StudentInfo::get_account_size(name.clone(), message.clone()) > 1000 {
-        msg!("Data length is larger than 1000 bytes");
+    if StudentInfo::get_account_size(name.clone(), message.clone(), &profile) > account_len {
+        msg!("Data length is larger than {} bytes", account_len);
         return Err(StudentIntroError::InvalidDataLength.into());
     }
 
@@ -129,8 +149,8 @@ pub fn add_student_intro(
     msg!("PDA created: {}", pda);
 
     msg!("Unpacking state account...");
-    let mut account_data =
-        try_from_slice_unchecked::<StudentInfo>(&user_account.data.borrow()).unwrap();
+    let mut account_data = try_from_slice_unchecked::<StudentInfo>(&user_account.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
     msg!("Borrowed account data successfully.");
 
     msg!("Checking if account is already initialized...");
@@ -142,6 +162,8 @@ pub fn add_student_intro(
     account_data.discriminator = StudentInfo::DISCRIMINATOR.to_string();
     account_data.name = name;
     account_data.msg = message;
+    account_data.version = StudentInfo::CURRENT_VERSION;
+    account_data.set_profile(profile);
     account_data.is_initialized = true;
 
     msg!("Serializing account...");
@@ -178,7 +200,8 @@ pub fn add_student_intro(
     msg!("Reply counter created.");
 
     let mut counter_data =
-        try_from_slice_unchecked::<StudentReplyCounter>(&pda_counter.data.borrow()).unwrap();
+        try_from_slice_unchecked::<StudentReplyCounter>(&pda_counter.data.borrow())
+            .map_err(|_| StudentIntroError::CorruptAccountData)?;
 
     msg!("Checking is counter is already initialized");
     if counter_data.is_initialized() {
@@ -219,6 +242,7 @@ pub fn update_student_intro(
     accounts: &[AccountInfo],
     name: String,
     message: String,
+    profile: Option<StudentProfile>,
 ) -> ProgramResult {
     msg!("Updating student intro...");
     msg!("Name: {}", name);
@@ -229,8 +253,8 @@ pub fn update_student_intro(
     let user_account = next_account_info(account_info_iter)?;
 
     msg!("unpacking state account");
-    let mut account_data =
-        try_from_slice_unchecked::<StudentInfo>(&user_account.data.borrow()).unwrap();
+    let mut account_data = try_from_slice_unchecked::<StudentInfo>(&user_account.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
     msg!("borrowed account data");
 
     msg!("checking if account is initialized");
@@ -248,16 +272,37 @@ pub fn update_student_intro(
         msg!("Invalid seeds for PDA");
         return Err(StudentIntroError::InvalidPDA.into());
     }
-    let update_len: usize = 1 + (4 + account_data.name.len()) + (4 + message.len());
-    if update_len > 1000 {
-        msg!("Data length is larger than 1000 bytes");
+
+    let profile = profile.unwrap_or_else(|| account_data.profile());
+    profile.validate()?;
+
+    let update_len =
+        StudentInfo::get_account_size(account_data.name.clone(), message.clone(), &profile);
+    if update_len > StudentInfo::ACCOUNT_SIZE {
+        msg!(
+            "Data length is larger than {} bytes",
+            StudentInfo::ACCOUNT_SIZE
+        );
         return Err(StudentIntroError::InvalidDataLength.into());
     }
 
-    account_data.name = account_data.name;
+    if account_data.version < StudentInfo::CURRENT_VERSION {
+        msg!(
+            "Migrating account from version {} to {}",
+            account_data.version,
+            StudentInfo::CURRENT_VERSION
+        );
+        account_data.version = StudentInfo::CURRENT_VERSION;
+    }
+
     account_data.msg = message;
+    account_data.set_profile(profile);
     msg!("serializing account");
-    account_data.serialize(&mut &mut user_account.data.borrow_mut()[..])?;
+    let mut data = user_account.data.borrow_mut();
+    let mut unused = &mut data[..];
+    account_data.serialize(&mut unused)?;
+    // Clear what a longer previous message left behind.
+    unused.fill(0);
     msg!("state account serialized");
 
     Ok(())
@@ -275,9 +320,24 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
     let user_ata = next_account_info(account_info_iter)?;
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
+    let pda_replier_counter = next_account_info(account_info_iter)?;
+    let pda_replier_index = next_account_info(account_info_iter)?;
+
+    if pda_intro.owner != program_id || pda_counter.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let (counter_pda, _counter_bump_seed) =
+        Pubkey::find_program_address(&[pda_intro.key.as_ref(), "reply".as_ref()], program_id);
+
+    if counter_pda != *pda_counter.key {
+        msg!("Invalid seeds for counter PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
 
     let mut counter_data =
-        try_from_slice_unchecked::<StudentReplyCounter>(&pda_counter.data.borrow()).unwrap();
+        try_from_slice_unchecked::<StudentReplyCounter>(&pda_counter.data.borrow())
+            .map_err(|_| StudentIntroError::CorruptAccountData)?;
 
     msg!("Deriving mint authority");
     let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], program_id);
@@ -317,7 +377,7 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
         program_id,
     );
 
-    if pda != *pda_counter.key {
+    if pda != *pda_reply.key {
         msg!("Invalid seeds for PDA");
         return Err(StudentIntroError::InvalidPDA.into());
     }
@@ -340,8 +400,8 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
 
     msg!("Created reply account.");
 
-    let mut reply_data =
-        try_from_slice_unchecked::<StudentIntroReply>(&pda_reply.data.borrow()).unwrap();
+    let mut reply_data = try_from_slice_unchecked::<StudentIntroReply>(&pda_reply.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
 
     msg!("Checking if reply account is alreadt initialized...");
     if reply_data.is_initialized() {
@@ -353,6 +413,7 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
     reply_data.reply = *pda_reply.key;
     reply_data.replier = *replier.key;
     reply_data.reply_message = reply;
+    reply_data.hidden = false;
     reply_data.is_initialized = true;
 
     reply_data.serialize(&mut &mut pda_reply.data.borrow_mut()[..])?;
@@ -362,6 +423,100 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
 
     counter_data.serialize(&mut &mut pda_counter.data.borrow_mut()[..])?;
 
+    let (replier_counter_pda, replier_counter_bump) =
+        Pubkey::find_program_address(&[replier.key.as_ref(), "replier".as_ref()], program_id);
+
+    if replier_counter_pda != *pda_replier_counter.key {
+        msg!("Invalid seeds for replier counter PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    if pda_replier_counter.data_is_empty() {
+        msg!("Creating replier counter account...");
+        invoke_signed(
+            &system_instruction::create_account(
+                replier.key,
+                pda_replier_counter.key,
+                rent.minimum_balance(ReplierCounter::SIZE),
+                ReplierCounter::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                replier.clone(),
+                pda_replier_counter.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                replier.key.as_ref(),
+                "replier".as_ref(),
+                &[replier_counter_bump],
+            ]],
+        )?;
+    } else if pda_replier_counter.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let mut replier_counter_data =
+        try_from_slice_unchecked::<ReplierCounter>(&pda_replier_counter.data.borrow())
+            .map_err(|_| StudentIntroError::CorruptAccountData)?;
+
+    if !replier_counter_data.is_initialized() {
+        replier_counter_data.discriminator = ReplierCounter::DISCRIMINATOR.to_string();
+        replier_counter_data.counter = 0;
+        replier_counter_data.is_initialized = true;
+    }
+
+    let sequence = replier_counter_data.counter;
+    let (replier_index_pda, replier_index_bump) = Pubkey::find_program_address(
+        &[
+            replier.key.as_ref(),
+            "replier".as_ref(),
+            sequence.to_be_bytes().as_ref(),
+        ],
+        program_id,
+    );
+
+    if replier_index_pda != *pda_replier_index.key {
+        msg!("Invalid seeds for replier index PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            replier.key,
+            pda_replier_index.key,
+            rent.minimum_balance(ReplierIndex::SIZE),
+            ReplierIndex::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            replier.clone(),
+            pda_replier_index.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            replier.key.as_ref(),
+            "replier".as_ref(),
+            sequence.to_be_bytes().as_ref(),
+            &[replier_index_bump],
+        ]],
+    )?;
+
+    let index_data = ReplierIndex {
+        discriminator: ReplierIndex::DISCRIMINATOR.to_string(),
+        is_initialized: true,
+        replier: *replier.key,
+        sequence,
+        intro: *pda_intro.key,
+        reply: *pda_reply.key,
+    };
+    index_data.serialize(&mut &mut pda_replier_index.data.borrow_mut()[..])?;
+
+    replier_counter_data.counter += 1;
+    msg!("Replier reply count: {}", replier_counter_data.counter);
+
+    replier_counter_data.serialize(&mut &mut pda_replier_counter.data.borrow_mut()[..])?;
+
     msg!("Minting 5 tokens to user's ATA");
 
     invoke_signed(
@@ -380,7 +535,11 @@ pub fn add_reply(program_id: &Pubkey, accounts: &[AccountInfo], reply: String) -
     Ok(())
 }
 
-pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+pub fn initialize_token_mint(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    freeze_authority: Option<Pubkey>,
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let initializer = next_account_info(account_info_iter)?;
@@ -389,13 +548,17 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
     let system_program = next_account_info(account_info_iter)?;
     let token_program = next_account_info(account_info_iter)?;
     let sysvar_rent = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+
+    // The caller picks the freeze authority for every holder's tokens.
+    check_upgrade_authority(program_id, initializer, program_data)?;
 
     let (mint_pda, mint_bump_seed) = Pubkey::find_program_address(&[b"token_mint"], program_id);
-    let (mint_auth_pda, mint_auth_bump_seed) =
+    let (mint_auth_pda, _mint_auth_bump_seed) =
         Pubkey::find_program_address(&[b"token_auth"], program_id);
 
     msg!("Token Mint: {:?}", mint_pda);
-    msg!("Mint Authority: {:?}", mint_auth);
+    msg!("Mint Authority: {:?}", mint_auth_pda);
 
     if mint_pda != *token_mint.key {
         msg!("Incorrent token mint");
@@ -412,15 +575,25 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
         return Err(StudentIntroError::IncorrectAccountError.into());
     }
 
+    if *system_program.key != SYSTEM_PROGRAM_ID {
+        msg!("Incorrect system program");
+        return Err(StudentIntroError::IncorrectAccountError.into());
+    }
+
+    if *sysvar_rent.key != RENT_PROGRAM_ID {
+        msg!("Incorrect rent program");
+        return Err(StudentIntroError::IncorrectAccountError.into());
+    }
+
     let rent = Rent::get()?;
-    let rent_lamports = rent.minimum_balance(82);
+    let rent_lamports = rent.minimum_balance(Mint::LEN);
 
     invoke_signed(
         &system_instruction::create_account(
             initializer.key,
             token_mint.key,
             rent_lamports,
-            82,
+            Mint::LEN.try_into().unwrap(),
             token_program.key,
         ),
         &[
@@ -433,19 +606,637 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
 
     msg!("Token mint account created.");
 
-    // invoke_signed(
-    //     &initialize_mint(
-    //         token_program.key,
-    //         token_mint.key,
-    //         mint_auth.key,
-    //         Option::None,
-    //         9,
-    //     )?,
-    //     &[token_mint.clone(), sysvar_rent.clone(), mint_auth.clone()],
-    //     &[&[b"token_mint", &[mint_bump_seed]]],
-    // )?;
+    invoke(
+        &initialize_mint(
+            token_program.key,
+            token_mint.key,
+            mint_auth.key,
+            freeze_authority.as_ref(),
+            9,
+        )?,
+        &[
+            token_mint.clone(),
+            sysvar_rent.clone(),
+            token_program.clone(),
+        ],
+    )?;
 
-    // msg!("Initialized token mint");
+    msg!("Initialized token mint");
+    if let Some(freeze_authority) = freeze_authority {
+        msg!("Freeze Authority: {:?}", freeze_authority);
+    }
 
     Ok(())
 }
+
+// Checks that `authority` signed and is the upgrade authority recorded in this
+// program's ProgramData account.
+fn check_upgrade_authority(
+    program_id: &Pubkey,
+    authority: &AccountInfo,
+    program_data: &AccountInfo,
+) -> ProgramResult {
+    let (program_data_address, _program_data_bump) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+
+    if *program_data.key != program_data_address {
+        msg!("Incorrect program data account");
+        return Err(StudentIntroError::IncorrectAccountError.into());
+    }
+
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+    let data = program_data.data.borrow();
+    let upgrade_authority =
+        match limited_deserialize(&data[..metadata_len.min(data.len())], metadata_len as u64) {
+            Ok(UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address,
+                ..
+            }) => upgrade_authority_address,
+            _ => return Err(StudentIntroError::CorruptAccountData.into()),
+        };
+
+    if !authority.is_signer || upgrade_authority != Some(*authority.key) {
+        msg!("Signer is not the program's upgrade authority");
+        return Err(StudentIntroError::Unauthorized.into());
+    }
+
+    Ok(())
+}
+
+pub fn initialize_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    moderators: Vec<Pubkey>,
+) -> ProgramResult {
+    msg!("Initializing config...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = next_account_info(account_info_iter)?;
+    let pda_config = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let program_data = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // Otherwise anyone could claim the config right after deployment.
+    check_upgrade_authority(program_id, admin, program_data)?;
+
+    let (config_pda, config_bump) = Pubkey::find_program_address(&[b"config"], program_id);
+    if config_pda != *pda_config.key {
+        msg!("Invalid seeds for config PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    if moderators.len() > Config::MAX_MODERATORS {
+        msg!("At most {} moderators are allowed", Config::MAX_MODERATORS);
+        return Err(StudentIntroError::TooManyModerators.into());
+    }
+
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(Config::SIZE);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            pda_config.key,
+            rent_lamports,
+            Config::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[admin.clone(), pda_config.clone(), system_program.clone()],
+        &[&[b"config", &[config_bump]]],
+    )?;
+
+    msg!("Config PDA created: {}", config_pda);
+
+    let mut config_data = try_from_slice_unchecked::<Config>(&pda_config.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
+
+    if config_data.is_initialized() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    config_data.discriminator = Config::DISCRIMINATOR.to_string();
+    config_data.admin = *admin.key;
+    config_data.moderators = moderators;
+    config_data.is_initialized = true;
+
+    msg!("Admin: {}", config_data.admin);
+    msg!("Moderators: {}", config_data.moderators.len());
+
+    config_data.serialize(&mut &mut pda_config.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+pub fn set_moderators(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    moderators: Vec<Pubkey>,
+) -> ProgramResult {
+    msg!("Updating moderators...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = next_account_info(account_info_iter)?;
+    let pda_config = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_config.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let (config_pda, _config_bump) = Pubkey::find_program_address(&[b"config"], program_id);
+    if config_pda != *pda_config.key {
+        msg!("Invalid seeds for config PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    let mut config_data = try_from_slice_unchecked::<Config>(&pda_config.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
+
+    if !config_data.is_initialized() {
+        msg!("Account is not initialized");
+        return Err(StudentIntroError::UninitializedAccount.into());
+    }
+
+    if config_data.admin != *admin.key {
+        msg!("Only the admin can change moderators");
+        return Err(StudentIntroError::Unauthorized.into());
+    }
+
+    if moderators.len() > Config::MAX_MODERATORS {
+        msg!("At most {} moderators are allowed", Config::MAX_MODERATORS);
+        return Err(StudentIntroError::TooManyModerators.into());
+    }
+
+    config_data.moderators = moderators;
+    msg!("Moderators: {}", config_data.moderators.len());
+
+    // The list may have shrunk, clear the stale tail before writing it back.
+    pda_config.data.borrow_mut().fill(0);
+    config_data.serialize(&mut &mut pda_config.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+pub fn set_reply_hidden(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    hidden: bool,
+) -> ProgramResult {
+    msg!("Setting reply hidden: {}", hidden);
+
+    let account_info_iter = &mut accounts.iter();
+
+    let moderator = next_account_info(account_info_iter)?;
+    let pda_config = next_account_info(account_info_iter)?;
+    let pda_reply = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !moderator.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if pda_config.owner != program_id || pda_reply.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let (config_pda, _config_bump) = Pubkey::find_program_address(&[b"config"], program_id);
+    if config_pda != *pda_config.key {
+        msg!("Invalid seeds for config PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    let config_data = try_from_slice_unchecked::<Config>(&pda_config.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
+
+    if !config_data.is_moderator(moderator.key) {
+        msg!("Signer is not a moderator");
+        return Err(StudentIntroError::Unauthorized.into());
+    }
+
+    let mut reply_data = try_from_slice_unchecked::<StudentIntroReply>(&pda_reply.data.borrow())
+        .map_err(|_| StudentIntroError::CorruptAccountData)?;
+
+    if !reply_data.is_initialized() || reply_data.discriminator != StudentIntroReply::DISCRIMINATOR
+    {
+        msg!("Account is not a reply");
+        return Err(StudentIntroError::UninitializedAccount.into());
+    }
+
+    let account_len = StudentIntroReply::get_account_size(reply_data.reply_message.clone());
+
+    // Replies created before `hidden` existed are one byte short.
+    if pda_reply.data_len() < account_len {
+        let rent = Rent::get()?;
+        let rent_lamports = rent.minimum_balance(account_len);
+
+        if rent_lamports > pda_reply.lamports() {
+            msg!("Topping up rent for hidden flag");
+            invoke(
+                &system_instruction::transfer(
+                    moderator.key,
+                    pda_reply.key,
+                    rent_lamports - pda_reply.lamports(),
+                ),
+                &[moderator.clone(), pda_reply.clone(), system_program.clone()],
+            )?;
+        }
+
+        pda_reply.realloc(account_len, false)?;
+    }
+
+    reply_data.hidden = hidden;
+    reply_data.serialize(&mut &mut pda_reply.data.borrow_mut()[..])?;
+
+    msg!("Reply {} hidden: {}", pda_reply.key, reply_data.hidden);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_program::{
+            instruction::{AccountMeta, Instruction, InstructionError},
+            program_option::COption,
+        },
+        solana_program_test::*,
+        solana_sdk::{
+            account::Account,
+            signature::{Keypair, Signer},
+            signer::keypair::keypair_from_seed,
+            transaction::{Transaction, TransactionError},
+        },
+        spl_associated_token_account::instruction::create_associated_token_account,
+        spl_token::state::Account as TokenAccount,
+    };
+
+    // Stands in for the program's upgrade authority, the only key that may
+    // create the reward mint and the moderator config.
+    fn upgrade_authority() -> Keypair {
+        keypair_from_seed(&[7; 32]).unwrap()
+    }
+
+    // Records `upgrade_authority()` in a ProgramData account for `program_id`
+    // and funds it. Tests load the program natively, so nothing else creates it.
+    fn program_test(program_id: Pubkey) -> ProgramTest {
+        let mut program_test = ProgramTest::new(
+            "solana_student_intro_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        // ProgramData metadata as the upgradeable loader serializes it with
+        // bincode: the variant index, the last deploy slot and the authority.
+        let mut program_data = 3u32.to_le_bytes().to_vec();
+        program_data.extend_from_slice(&0u64.to_le_bytes());
+        program_data.push(1);
+        program_data.extend_from_slice(upgrade_authority().pubkey().as_ref());
+
+        program_test.add_account(
+            program_data_address(&program_id),
+            Account {
+                lamports: LAMPORTS_PER_SOL,
+                data: program_data,
+                owner: bpf_loader_upgradeable::id(),
+                ..Account::default()
+            },
+        );
+        program_test.add_account(
+            upgrade_authority().pubkey(),
+            Account {
+                lamports: 10 * LAMPORTS_PER_SOL,
+                ..Account::default()
+            },
+        );
+        program_test
+    }
+
+    fn program_data_address(program_id: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+    }
+
+    fn initialize_mint_ix(
+        program_id: Pubkey,
+        initializer: &Pubkey,
+        freeze_authority: Option<Pubkey>,
+    ) -> Instruction {
+        let (mint, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], &program_id);
+        let (mint_auth, _mint_auth_bump) =
+            Pubkey::find_program_address(&[b"token_auth"], &program_id);
+
+        let mut data = vec![3];
+        data.extend(freeze_authority.try_to_vec().unwrap());
+
+        Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(*initializer, true),
+                AccountMeta::new(mint, false),
+                AccountMeta::new_readonly(mint_auth, false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(RENT_PROGRAM_ID, false),
+                AccountMeta::new_readonly(program_data_address(&program_id), false),
+            ],
+            data,
+        }
+    }
+
+    async fn assert_unauthorized(
+        banks_client: &mut BanksClient,
+        payer: &Keypair,
+        signer: &Keypair,
+        ix: Instruction,
+    ) {
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
+        txn.sign(&[payer, signer], recent_blockhash);
+        let err = banks_client
+            .process_transaction(txn)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            err,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(StudentIntroError::Unauthorized as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_the_upgrade_authority_initializes_the_mint() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, _) = program_test(program_id).start().await;
+
+        let intruder = Keypair::new();
+        assert_unauthorized(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            initialize_mint_ix(program_id, &intruder.pubkey(), Some(intruder.pubkey())),
+        )
+        .await;
+
+        let (mint, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], &program_id);
+        assert_eq!(banks_client.get_account(mint).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_upgrade_authority_initializes_the_config() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let (mut banks_client, payer, _) = program_test(program_id).start().await;
+
+        let (config_pda, _config_bump) = Pubkey::find_program_address(&[b"config"], &program_id);
+        let moderator = Pubkey::new_unique();
+        let initialize_config_ix = |admin: &Pubkey| {
+            let mut data = vec![4];
+            data.extend(vec![moderator].try_to_vec().unwrap());
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(*admin, true),
+                    AccountMeta::new(config_pda, false),
+                    AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                    AccountMeta::new_readonly(program_data_address(&program_id), false),
+                ],
+                data,
+            }
+        };
+
+        let intruder = Keypair::new();
+        assert_unauthorized(
+            &mut banks_client,
+            &payer,
+            &intruder,
+            initialize_config_ix(&intruder.pubkey()),
+        )
+        .await;
+
+        let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        let mut txn = Transaction::new_with_payer(
+            &[initialize_config_ix(&authority.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+        banks_client.process_transaction(txn).await.unwrap();
+
+        let config_account = banks_client.get_account(config_pda).await.unwrap().unwrap();
+        let config_data = try_from_slice_unchecked::<Config>(&config_account.data).unwrap();
+        assert_eq!(config_data.admin, authority.pubkey());
+        assert_eq!(config_data.moderators, vec![moderator]);
+    }
+
+    #[tokio::test]
+    async fn test_intro_and_reply_mint_rewards() {
+        let program_id = Pubkey::new_unique();
+        let authority = upgrade_authority();
+        let (mut banks_client, payer, recent_blockhash) = program_test(program_id).start().await;
+
+        let freeze_authority = Pubkey::new_unique();
+        let (mint, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], &program_id);
+        let (mint_auth, _mint_auth_bump) =
+            Pubkey::find_program_address(&[b"token_auth"], &program_id);
+        let user_ata = get_associated_token_address(&payer.pubkey(), &mint);
+
+        let (intro_pda, _bump) =
+            Pubkey::find_program_address(&[payer.pubkey().as_ref()], &program_id);
+        let (counter_pda, _bump) =
+            Pubkey::find_program_address(&[intro_pda.as_ref(), b"reply"], &program_id);
+        let (reply_pda, _bump) = Pubkey::find_program_address(
+            &[intro_pda.as_ref(), 0u64.to_be_bytes().as_ref()],
+            &program_id,
+        );
+        let (replier_counter_pda, _bump) =
+            Pubkey::find_program_address(&[payer.pubkey().as_ref(), b"replier"], &program_id);
+        let (replier_index_pda, _bump) = Pubkey::find_program_address(
+            &[
+                payer.pubkey().as_ref(),
+                b"replier",
+                0u64.to_be_bytes().as_ref(),
+            ],
+            &program_id,
+        );
+
+        let mut intro_data = vec![0];
+        intro_data.extend(
+            ("Alice".to_owned(), "Hello, world!".to_owned())
+                .try_to_vec()
+                .unwrap(),
+        );
+
+        let mut reply_data = vec![2];
+        reply_data.extend("Welcome!".to_owned().try_to_vec().unwrap());
+
+        let mut txn = Transaction::new_with_payer(
+            &[
+                initialize_mint_ix(program_id, &authority.pubkey(), Some(freeze_authority)),
+                create_associated_token_account(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &mint,
+                    &TOKEN_PROGRAM_ID,
+                ),
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new(intro_pda, false),
+                        AccountMeta::new(counter_pda, false),
+                        AccountMeta::new(mint, false),
+                        AccountMeta::new_readonly(mint_auth, false),
+                        AccountMeta::new(user_ata, false),
+                        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                    ],
+                    data: intro_data,
+                },
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(intro_pda, false),
+                        AccountMeta::new(counter_pda, false),
+                        AccountMeta::new(reply_pda, false),
+                        AccountMeta::new(mint, false),
+                        AccountMeta::new_readonly(mint_auth, false),
+                        AccountMeta::new(user_ata, false),
+                        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                        AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+                        AccountMeta::new(replier_counter_pda, false),
+                        AccountMeta::new(replier_index_pda, false),
+                    ],
+                    data: reply_data,
+                },
+            ],
+            Some(&payer.pubkey()),
+        );
+        txn.sign(&[&payer, &authority], recent_blockhash);
+
+        banks_client.process_transaction(txn).await.unwrap();
+
+        let mint_account = banks_client.get_account(mint).await.unwrap().unwrap();
+        let mint_data = Mint::unpack(&mint_account.data).unwrap();
+        assert_eq!(mint_data.mint_authority, COption::Some(mint_auth));
+        assert_eq!(mint_data.freeze_authority, COption::Some(freeze_authority));
+        assert_eq!(mint_data.supply, 15 * LAMPORTS_PER_SOL);
+
+        let ata_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
+        let token_account = TokenAccount::unpack(&ata_account.data).unwrap();
+        assert_eq!(token_account.amount, 15 * LAMPORTS_PER_SOL);
+
+        let counter_account = banks_client
+            .get_account(counter_pda)
+            .await
+            .unwrap()
+            .unwrap();
+        let counter_data =
+            try_from_slice_unchecked::<StudentReplyCounter>(&counter_account.data).unwrap();
+        assert_eq!(counter_data.counter, 1);
+
+        let replier_counter_account = banks_client
+            .get_account(replier_counter_pda)
+            .await
+            .unwrap()
+            .unwrap();
+        let replier_counter_data =
+            try_from_slice_unchecked::<ReplierCounter>(&replier_counter_account.data).unwrap();
+        assert_eq!(replier_counter_data.counter, 1);
+
+        let replier_index_account = banks_client
+            .get_account(replier_index_pda)
+            .await
+            .unwrap()
+            .unwrap();
+        let index_data =
+            try_from_slice_unchecked::<ReplierIndex>(&replier_index_account.data).unwrap();
+        assert_eq!(index_data.sequence, 0);
+        assert_eq!(index_data.intro, intro_pda);
+        assert_eq!(index_data.reply, reply_pda);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_payloads_are_rejected() {
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "solana_student_intro_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        let intro_payload = ("Alice".to_owned(), "Hello, world!".to_owned())
+            .try_to_vec()
+            .unwrap();
+        let reply_payload = "Welcome!".to_owned().try_to_vec().unwrap();
+        let moderators_payload = vec![Pubkey::new_unique()].try_to_vec().unwrap();
+
+        let cases: [(u8, &Vec<u8>); 5] = [
+            (0, &intro_payload),
+            (1, &intro_payload),
+            (2, &reply_payload),
+            (4, &moderators_payload),
+            (5, &moderators_payload),
+        ];
+
+        for (variant, payload) in cases {
+            let mut malformed: Vec<Vec<u8>> = (0..payload.len())
+                .map(|len| payload[..len].to_vec())
+                .collect();
+            let mut oversized = payload.clone();
+            oversized.extend_from_slice(&[0xff; 16]);
+            malformed.push(oversized);
+
+            for data in malformed {
+                let mut instruction_data = vec![variant];
+                instruction_data.extend_from_slice(&data);
+
+                let mut txn = Transaction::new_with_payer(
+                    &[Instruction {
+                        program_id,
+                        accounts: vec![AccountMeta::new(payer.pubkey(), true)],
+                        data: instruction_data,
+                    }],
+                    Some(&payer.pubkey()),
+                );
+                txn.sign(&[&payer], recent_blockhash);
+
+                let err = banks_client
+                    .process_transaction(txn)
+                    .await
+                    .unwrap_err()
+                    .unwrap();
+                assert_eq!(
+                    err,
+                    TransactionError::InstructionError(
+                        0,
+                        InstructionError::Custom(
+                            StudentIntroError::InvalidInstructionPayload as u32
+                        )
+                    )
+                );
+            }
+        }
+    }
+}
@@ -1,24 +1,70 @@
+use crate::error::StudentIntroError;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     program_pack::{IsInitialized, Sealed},
     pubkey::Pubkey,
 };
+use std::io;
 
-#[derive(BorshSerialize, BorshDeserialize)]
+/// Version 0 accounts end after `msg`. Later versions append
+/// `StudentInfo::VERSIONED` and the version, then that version's fields.
+#[derive(Debug, PartialEq)]
 pub struct StudentInfo {
     pub discriminator: String,
     pub is_initialized: bool,
     pub name: String,
     pub msg: String,
+    pub version: u8,
+    pub github: Option<String>,
+    pub website: Option<String>,
+    pub avatar_mint: Option<Pubkey>,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct StudentProfile {
+    pub github: Option<String>,
+    pub website: Option<String>,
+    pub avatar_mint: Option<Pubkey>,
+}
+
+/// `hidden` was appended after the first release; replies created before
+/// that have no byte for it and read as visible.
+#[derive(BorshSerialize)]
 pub struct StudentIntroReply {
     pub discriminator: String,
     pub is_initialized: bool,
     pub reply_message: String,
     pub reply: Pubkey,
     pub replier: Pubkey,
+    pub hidden: bool,
+}
+
+/// Number of replies a wallet has written, seeded by `[replier, "replier"]`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReplierCounter {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub counter: u64,
+}
+
+/// Points from `[replier, "replier", sequence]` to the reply written at that
+/// position, so a wallet's replies can be paged by sequence.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReplierIndex {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub replier: Pubkey,
+    pub sequence: u64,
+    pub intro: Pubkey,
+    pub reply: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Config {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub admin: Pubkey,
+    pub moderators: Vec<Pubkey>,
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -40,17 +86,165 @@ impl IsInitialized for StudentIntroReply {
     }
 }
 
+impl IsInitialized for ReplierCounter {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for ReplierIndex {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
 impl IsInitialized for StudentReplyCounter {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
 
+impl BorshSerialize for StudentInfo {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.discriminator.serialize(writer)?;
+        self.is_initialized.serialize(writer)?;
+        self.name.serialize(writer)?;
+        self.msg.serialize(writer)?;
+        if self.version == 0 {
+            return Ok(());
+        }
+        StudentInfo::VERSIONED.serialize(writer)?;
+        self.version.serialize(writer)?;
+        self.github.serialize(writer)?;
+        self.website.serialize(writer)?;
+        self.avatar_mint.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for StudentInfo {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let mut info = StudentInfo {
+            discriminator: String::deserialize(buf)?,
+            is_initialized: bool::deserialize(buf)?,
+            name: String::deserialize(buf)?,
+            msg: String::deserialize(buf)?,
+            version: 0,
+            github: None,
+            website: None,
+            avatar_mint: None,
+        };
+
+        // Whatever follows `msg` in a version 0 account is zero padding or
+        // leftovers of a longer message, neither of which can start with the
+        // marker.
+        if buf.first() == Some(&StudentInfo::VERSIONED) {
+            *buf = &buf[1..];
+            info.version = u8::deserialize(buf)?;
+            if info.version != StudentInfo::CURRENT_VERSION {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown StudentInfo version",
+                ));
+            }
+            info.github = Option::<String>::deserialize(buf)?;
+            info.website = Option::<String>::deserialize(buf)?;
+            info.avatar_mint = Option::<Pubkey>::deserialize(buf)?;
+        }
+
+        Ok(info)
+    }
+}
+
 impl StudentInfo {
     pub const DISCRIMINATOR: &'static str = "info";
+    pub const CURRENT_VERSION: u8 = 1;
+    /// Follows `msg` in accounts newer than version 0. 0xff never occurs in
+    /// UTF-8, so leftovers of a longer message can't pass for it.
+    pub const VERSIONED: u8 = 0xff;
+    pub const ACCOUNT_SIZE: usize = 1000;
+
+    pub fn get_account_size(name: String, msg: String, profile: &StudentProfile) -> usize {
+        return (4 + StudentInfo::DISCRIMINATOR.len())
+            + 1
+            + (4 + name.len())
+            + (4 + msg.len())
+            + 1
+            + 1
+            + (1 + profile.github.as_ref().map_or(0, |github| 4 + github.len()))
+            + (1 + profile
+                .website
+                .as_ref()
+                .map_or(0, |website| 4 + website.len()))
+            + (1 + profile.avatar_mint.map_or(0, |_| 32));
+    }
 
-    pub fn get_account_size(name: String, msg: String) -> usize {
-        return (4 + StudentInfo::DISCRIMINATOR.len()) + 1 + (4 + name.len()) + (4 + msg.len());
+    pub fn profile(&self) -> StudentProfile {
+        StudentProfile {
+            github: self.github.clone(),
+            website: self.website.clone(),
+            avatar_mint: self.avatar_mint,
+        }
+    }
+
+    pub fn set_profile(&mut self, profile: StudentProfile) {
+        self.github = profile.github;
+        self.website = profile.website;
+        self.avatar_mint = profile.avatar_mint;
+    }
+}
+
+impl StudentProfile {
+    pub const MAX_GITHUB_LEN: usize = 39;
+    pub const MAX_WEBSITE_LEN: usize = 100;
+
+    pub fn validate(&self) -> Result<(), StudentIntroError> {
+        if let Some(github) = &self.github {
+            if github.is_empty()
+                || github.len() > StudentProfile::MAX_GITHUB_LEN
+                || github.starts_with('-')
+                || !github
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            {
+                return Err(StudentIntroError::InvalidGithubHandle);
+            }
+        }
+
+        if let Some(website) = &self.website {
+            if website.len() > StudentProfile::MAX_WEBSITE_LEN
+                || !(website.starts_with("https://") || website.starts_with("http://"))
+                || !website
+                    .bytes()
+                    .all(|b| b.is_ascii_graphic() && !b"\"<>\\^`{|}".contains(&b))
+            {
+                return Err(StudentIntroError::InvalidWebsiteUrl);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BorshDeserialize for StudentIntroReply {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        Ok(StudentIntroReply {
+            discriminator: String::deserialize(buf)?,
+            is_initialized: bool::deserialize(buf)?,
+            reply_message: String::deserialize(buf)?,
+            reply: Pubkey::deserialize(buf)?,
+            replier: Pubkey::deserialize(buf)?,
+            hidden: if buf.is_empty() {
+                false
+            } else {
+                bool::deserialize(buf)?
+            },
+        })
     }
 }
 
@@ -62,7 +256,31 @@ impl StudentIntroReply {
             + 1
             + (4 + reply_message.len())
             + 32
-            + 32;
+            + 32
+            + 1;
+    }
+}
+
+impl ReplierCounter {
+    pub const DISCRIMINATOR: &'static str = "replier_counter";
+
+    pub const SIZE: usize = (4 + ReplierCounter::DISCRIMINATOR.len()) + 1 + 8;
+}
+
+impl ReplierIndex {
+    pub const DISCRIMINATOR: &'static str = "replier_index";
+
+    pub const SIZE: usize = (4 + ReplierIndex::DISCRIMINATOR.len()) + 1 + 32 + 8 + 32 + 32;
+}
+
+impl Config {
+    pub const DISCRIMINATOR: &'static str = "config";
+    pub const MAX_MODERATORS: usize = 10;
+    pub const SIZE: usize =
+        (4 + Config::DISCRIMINATOR.len()) + 1 + 32 + (4 + 32 * Config::MAX_MODERATORS);
+
+    pub fn is_moderator(&self, key: &Pubkey) -> bool {
+        self.admin == *key || self.moderators.contains(key)
     }
 }
 
@@ -74,3 +292,168 @@ impl StudentReplyCounter {
 
 impl Sealed for StudentInfo {}
 impl Sealed for StudentReplyCounter {}
+impl Sealed for Config {}
+impl Sealed for ReplierCounter {}
+impl Sealed for ReplierIndex {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_info() -> StudentInfo {
+        StudentInfo {
+            discriminator: StudentInfo::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            name: "Alice".to_string(),
+            msg: "Hello, world!".to_string(),
+            version: 0,
+            github: None,
+            website: None,
+            avatar_mint: None,
+        }
+    }
+
+    fn legacy_bytes(info: &StudentInfo) -> Vec<u8> {
+        (
+            &info.discriminator,
+            info.is_initialized,
+            &info.name,
+            &info.msg,
+        )
+            .try_to_vec()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_version_0_account_deserializes_without_profile() {
+        let info = legacy_info();
+        let mut data = legacy_bytes(&info);
+        data.resize(StudentInfo::ACCOUNT_SIZE, 0);
+
+        assert_eq!(
+            StudentInfo::deserialize(&mut data.as_slice()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn test_version_0_account_ignores_leftover_message_bytes() {
+        let mut info = legacy_info();
+        info.msg = "A much longer message that was later shortened".to_string();
+        let mut data = legacy_bytes(&info);
+        data.resize(StudentInfo::ACCOUNT_SIZE, 0);
+
+        info.msg = "Short".to_string();
+        let shortened = legacy_bytes(&info);
+        data[..shortened.len()].copy_from_slice(&shortened);
+
+        assert_eq!(
+            StudentInfo::deserialize(&mut data.as_slice()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn test_version_0_account_ignores_a_leftover_version_byte() {
+        // The message once continued with a 0x01, the byte version 1 would
+        // follow `msg` with.
+        let mut info = legacy_info();
+        info.msg = "Short\u{1} and then some".to_string();
+        let mut data = legacy_bytes(&info);
+        data.resize(StudentInfo::ACCOUNT_SIZE, 0);
+
+        info.msg = "Short".to_string();
+        let shortened = legacy_bytes(&info);
+        data[..shortened.len()].copy_from_slice(&shortened);
+        assert_eq!(data[shortened.len()], StudentInfo::CURRENT_VERSION);
+
+        assert_eq!(
+            StudentInfo::deserialize(&mut data.as_slice()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn test_version_1_account_round_trips() {
+        let mut info = legacy_info();
+        info.version = StudentInfo::CURRENT_VERSION;
+        info.set_profile(StudentProfile {
+            github: Some("alice".to_string()),
+            website: Some("https://alice.dev".to_string()),
+            avatar_mint: Some(Pubkey::new_unique()),
+        });
+        let mut data = info.try_to_vec().unwrap();
+        assert_eq!(
+            data.len(),
+            StudentInfo::get_account_size(info.name.clone(), info.msg.clone(), &info.profile())
+        );
+        data.resize(StudentInfo::ACCOUNT_SIZE, 0);
+
+        assert_eq!(
+            StudentInfo::deserialize(&mut data.as_slice()).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn test_reply_without_hidden_byte_reads_as_visible() {
+        let reply = Pubkey::new_unique();
+        let replier = Pubkey::new_unique();
+        let data = (
+            StudentIntroReply::DISCRIMINATOR.to_string(),
+            true,
+            "Welcome!".to_string(),
+            reply,
+            replier,
+        )
+            .try_to_vec()
+            .unwrap();
+        assert_eq!(
+            data.len() + 1,
+            StudentIntroReply::get_account_size("Welcome!".to_string())
+        );
+
+        let reply_data = StudentIntroReply::deserialize(&mut data.as_slice()).unwrap();
+        assert_eq!(reply_data.replier, replier);
+        assert!(!reply_data.hidden);
+    }
+
+    #[test]
+    fn test_profile_validation() {
+        let valid = StudentProfile {
+            github: Some("alice-dev".to_string()),
+            website: Some("https://alice.dev/about?lang=en".to_string()),
+            avatar_mint: None,
+        };
+        assert!(valid.validate().is_ok());
+
+        for github in ["", "-alice", "alice_dev", "alice dev", &"a".repeat(40)] {
+            let profile = StudentProfile {
+                github: Some(github.to_string()),
+                ..StudentProfile::default()
+            };
+            assert!(matches!(
+                profile.validate(),
+                Err(StudentIntroError::InvalidGithubHandle)
+            ));
+        }
+
+        let too_long = format!("https://{}", "a".repeat(StudentProfile::MAX_WEBSITE_LEN));
+        for website in [
+            "alice.dev",
+            "ftp://alice.dev",
+            "https://alice .dev",
+            "https://alice.dev/<script>",
+            too_long.as_str(),
+        ] {
+            let profile = StudentProfile {
+                website: Some(website.to_string()),
+                ..StudentProfile::default()
+            };
+            assert!(matches!(
+                profile.validate(),
+                Err(StudentIntroError::InvalidWebsiteUrl)
+            ));
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use solana_program::program_error::ProgramError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StudentIntroError {
+    #[error("Instruction payload could not be deserialized")]
+    InvalidInstructionPayload,
+    #[error("PDA derived does not equal PDA passed in")]
+    InvalidPDA,
+    #[error("Input data exceeds max length")]
+    InvalidDataLength,
+    #[error("Account data could not be deserialized")]
+    CorruptAccountData,
+}
+
+impl From<StudentIntroError> for ProgramError {
+    fn from(e: StudentIntroError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::StudentIntroError;
 use borsh::BorshDeserialize;
 use solana_program::program_error::ProgramError;
 
@@ -11,7 +12,8 @@ impl StudentInstruction {
             .split_first()
             .ok_or(ProgramError::InvalidInstructionData)?;
 
-        let payload = StudentIntroPayload::try_from_slice(rest).unwrap();
+        let payload = StudentIntroPayload::try_from_slice(rest)
+            .map_err(|_| StudentIntroError::InvalidInstructionPayload)?;
 
         Ok(match variant {
             0 => Self::AddStudentIntro {
@@ -1,8 +1,23 @@
+use borsh::BorshSerialize;
 use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg, pubkey::Pubkey,
+    account_info::{next_account_info, AccountInfo},
+    entrypoint,
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{rent::Rent, Sysvar},
 };
+use std::convert::TryInto;
+pub mod error;
 pub mod instruction;
+pub mod state;
+use error::StudentIntroError;
 use instruction::StudentInstruction;
+use state::StudentInfo;
 
 entrypoint!(process_instruction);
 
@@ -30,5 +45,72 @@ pub fn add_student_intro(
     msg!("Name: {}", name);
     msg!("Message: {}", message);
 
+    let account_info_iter = &mut accounts.iter();
+
+    let initializer = next_account_info(account_info_iter)?;
+    let pda_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    if !initializer.is_signer {
+        msg!("Missing required signature");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (pda, bump_seed) = Pubkey::find_program_address(&[initializer.key.as_ref()], program_id);
+    if pda != *pda_account.key {
+        msg!("Invalid seeds for PDA");
+        return Err(StudentIntroError::InvalidPDA.into());
+    }
+
+    let account_len = StudentInfo::get_account_size(&name, &message);
+    if account_len > StudentInfo::MAX_ACCOUNT_SIZE {
+        msg!(
+            "Data length is larger than {} bytes",
+            StudentInfo::MAX_ACCOUNT_SIZE
+        );
+        return Err(StudentIntroError::InvalidDataLength.into());
+    }
+
+    if !pda_account.data_is_empty() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let rent = Rent::get()?;
+    let rent_lamports = rent.minimum_balance(account_len);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            initializer.key,
+            pda_account.key,
+            rent_lamports,
+            account_len.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            initializer.clone(),
+            pda_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[initializer.key.as_ref(), &[bump_seed]]],
+    )?;
+
+    msg!("PDA created: {}", pda);
+
+    let mut account_data = StudentInfo::unpack_from(&pda_account.data.borrow())?;
+
+    if account_data.is_initialized() {
+        msg!("Account already initialized");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    account_data.discriminator = StudentInfo::DISCRIMINATOR.to_string();
+    account_data.name = name;
+    account_data.msg = message;
+    account_data.is_initialized = true;
+
+    account_data.serialize(&mut &mut pda_account.data.borrow_mut()[..])?;
+    msg!("State account serialized");
+
     Ok(())
 }
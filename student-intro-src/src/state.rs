@@ -0,0 +1,75 @@
+use crate::error::StudentIntroError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    borsh::try_from_slice_unchecked,
+    program_pack::{IsInitialized, Sealed},
+};
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct StudentInfo {
+    pub discriminator: String,
+    pub is_initialized: bool,
+    pub name: String,
+    pub msg: String,
+}
+
+impl StudentInfo {
+    pub const DISCRIMINATOR: &'static str = "info";
+    pub const MAX_ACCOUNT_SIZE: usize = 1000;
+
+    pub fn get_account_size(name: &str, msg: &str) -> usize {
+        (4 + StudentInfo::DISCRIMINATOR.len()) + 1 + (4 + name.len()) + (4 + msg.len())
+    }
+
+    pub fn unpack_from(data: &[u8]) -> Result<Self, StudentIntroError> {
+        try_from_slice_unchecked(data).map_err(|_| StudentIntroError::CorruptAccountData)
+    }
+}
+
+impl Sealed for StudentInfo {}
+
+impl IsInitialized for StudentInfo {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_account_is_uninitialized() {
+        let data = vec![0; StudentInfo::get_account_size("Ada", "Hello")];
+        let info = StudentInfo::unpack_from(&data).unwrap();
+        assert!(!info.is_initialized());
+        assert!(info.discriminator.is_empty());
+    }
+
+    #[test]
+    fn truncated_account_is_corrupt() {
+        // The discriminator claims 4 bytes but the account ends after 2.
+        let data = [4, 0, 0, 0, b'i', b'n'];
+        assert!(matches!(
+            StudentInfo::unpack_from(&data),
+            Err(StudentIntroError::CorruptAccountData)
+        ));
+    }
+
+    #[test]
+    fn invalid_flag_is_corrupt() {
+        let mut data = StudentInfo {
+            discriminator: StudentInfo::DISCRIMINATOR.to_string(),
+            is_initialized: true,
+            name: "Ada".to_string(),
+            msg: "Hello".to_string(),
+        }
+        .try_to_vec()
+        .unwrap();
+        data[4 + StudentInfo::DISCRIMINATOR.len()] = 2;
+        assert!(matches!(
+            StudentInfo::unpack_from(&data),
+            Err(StudentIntroError::CorruptAccountData)
+        ));
+    }
+}
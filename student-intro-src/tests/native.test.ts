@@ -1,89 +1,228 @@
 // No imports needed: web3, borsh, pg and more are globally available
 
 /**
- * The state of a greeting account managed by the hello world program
+ * The AddStudentIntro instruction data: a variant byte followed by the intro
  */
-class GreetingAccount {
-  counter = 0;
-  constructor(fields: { counter: number } | undefined = undefined) {
+class AddStudentIntroInstruction {
+  variant = 0;
+  name = "";
+  message = "";
+  constructor(fields: { name: string; message: string }) {
+    this.name = fields.name;
+    this.message = fields.message;
+  }
+}
+
+const InstructionSchema = new Map([
+  [
+    AddStudentIntroInstruction,
+    {
+      kind: "struct",
+      fields: [
+        ["variant", "u8"],
+        ["name", "string"],
+        ["message", "string"],
+      ],
+    },
+  ],
+]);
+
+/**
+ * The state of a student intro account managed by the program
+ */
+class StudentInfo {
+  discriminator = "";
+  isInitialized = 0;
+  name = "";
+  msg = "";
+  constructor(
+    fields:
+      | { discriminator: string; isInitialized: number; name: string; msg: string }
+      | undefined = undefined
+  ) {
     if (fields) {
-      this.counter = fields.counter;
+      Object.assign(this, fields);
     }
   }
 }
 
+const StudentInfoSchema = new Map([
+  [
+    StudentInfo,
+    {
+      kind: "struct",
+      fields: [
+        ["discriminator", "string"],
+        ["isInitialized", "u8"],
+        ["name", "string"],
+        ["msg", "string"],
+      ],
+    },
+  ],
+]);
+
 /**
- * Borsh schema definition for greeting accounts
+ * Mirrors `StudentInfo::get_account_size` and `MAX_ACCOUNT_SIZE`
  */
-const GreetingSchema = new Map([
-  [GreetingAccount, { kind: "struct", fields: [["counter", "u32"]] }],
-]);
+const accountSize = (name: string, message: string) =>
+  4 + "info".length + 1 + 4 + Buffer.byteLength(name) + 4 + Buffer.byteLength(message);
+const MAX_ACCOUNT_SIZE = 1000;
+
+const findIntroAddress = (student: web3.PublicKey) =>
+  web3.PublicKey.findProgramAddressSync([student.toBuffer()], pg.PROGRAM_ID)[0];
+
+const addStudentIntroIx = (
+  student: web3.PublicKey,
+  intro: web3.PublicKey,
+  name: string,
+  message: string
+) =>
+  new web3.TransactionInstruction({
+    keys: [
+      { pubkey: student, isSigner: true, isWritable: true },
+      { pubkey: intro, isSigner: false, isWritable: true },
+      { pubkey: web3.SystemProgram.programId, isSigner: false, isWritable: false },
+    ],
+    programId: pg.PROGRAM_ID,
+    data: Buffer.from(
+      borsh.serialize(
+        InstructionSchema,
+        new AddStudentIntroInstruction({ name, message })
+      )
+    ),
+  });
 
 /**
- * The expected size of each greeting account.
+ * A new student funded by the wallet, so every test gets its own intro PDA
  */
-const GREETING_SIZE = borsh.serialize(
-  GreetingSchema,
-  new GreetingAccount()
-).length;
-
-describe("Test", () => {
-  it("greet", async () => {
-    // Create greetings account instruction
-    const greetingAccountKp = new web3.Keypair();
-    const lamports = await pg.connection.getMinimumBalanceForRentExemption(
-      GREETING_SIZE
-    );
-    const createGreetingAccountIx = web3.SystemProgram.createAccount({
+const newStudent = async () => {
+  const student = new web3.Keypair();
+  const tx = new web3.Transaction().add(
+    web3.SystemProgram.transfer({
       fromPubkey: pg.wallet.publicKey,
-      lamports,
-      newAccountPubkey: greetingAccountKp.publicKey,
-      programId: pg.PROGRAM_ID,
-      space: GREETING_SIZE,
-    });
-
-    // Create greet instruction
-    const greetIx = new web3.TransactionInstruction({
-      keys: [
-        {
-          pubkey: greetingAccountKp.publicKey,
-          isSigner: false,
-          isWritable: true,
-        },
-      ],
-      programId: pg.PROGRAM_ID,
-    });
-
-    // Create transaction and add the instructions
-    const tx = new web3.Transaction();
-    tx.add(createGreetingAccountIx, greetIx);
-
-    // Send and confirm the transaction
-    const txHash = await web3.sendAndConfirmTransaction(pg.connection, tx, [
-      pg.wallet.keypair,
-      greetingAccountKp,
-    ]);
+      toPubkey: student.publicKey,
+      lamports: 0.02 * web3.LAMPORTS_PER_SOL,
+    })
+  );
+  await web3.sendAndConfirmTransaction(pg.connection, tx, [pg.wallet.keypair]);
+  return student;
+};
+
+/**
+ * Sends `ix` and checks that it fails with `expectedLog` in the program logs
+ */
+const assertFails = async (
+  ix: web3.TransactionInstruction,
+  signers: web3.Keypair[],
+  expectedLog: string
+) => {
+  try {
+    await web3.sendAndConfirmTransaction(
+      pg.connection,
+      new web3.Transaction().add(ix),
+      signers
+    );
+  } catch (err) {
+    const logs: string[] = err.logs ?? [];
+    assert(
+      logs.some((log) => log.includes(expectedLog)),
+      `expected "${expectedLog}" in ${JSON.stringify(logs)}`
+    );
+    return;
+  }
+  assert.fail(`expected the transaction to fail with "${expectedLog}"`);
+};
+
+describe("Student intro", () => {
+  it("stores the intro at the PDA seeded by the signer", async () => {
+    const student = await newStudent();
+    const intro = findIntroAddress(student.publicKey);
+    const name = "Ada";
+    const message = "Hello from the first programmer";
+
+    const txHash = await web3.sendAndConfirmTransaction(
+      pg.connection,
+      new web3.Transaction().add(
+        addStudentIntroIx(student.publicKey, intro, name, message)
+      ),
+      [student]
+    );
     console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
 
-    // Fetch the greetings account
-    const greetingAccount = await pg.connection.getAccountInfo(
-      greetingAccountKp.publicKey
+    const introAccount = await pg.connection.getAccountInfo(intro);
+    assert(introAccount.owner.equals(pg.PROGRAM_ID));
+    assert.equal(introAccount.data.length, accountSize(name, message));
+    assert.equal(
+      introAccount.lamports,
+      await pg.connection.getMinimumBalanceForRentExemption(
+        accountSize(name, message)
+      )
     );
 
-    // Deserialize the account data
-    const deserializedAccountData = borsh.deserialize(
-      GreetingSchema,
-      GreetingAccount,
-      greetingAccount.data
+    const studentInfo = borsh.deserialize(
+      StudentInfoSchema,
+      StudentInfo,
+      introAccount.data
     );
+    assert.equal(studentInfo.discriminator, "info");
+    assert.equal(studentInfo.isInitialized, 1);
+    assert.equal(studentInfo.name, name);
+    assert.equal(studentInfo.msg, message);
+  });
 
-    // Assertions
-    assert.equal(greetingAccount.lamports, lamports);
+  it("rejects a PDA seeded by another key", async () => {
+    const student = await newStudent();
+    const otherIntro = findIntroAddress(new web3.Keypair().publicKey);
 
-    assert(greetingAccount.owner.equals(pg.PROGRAM_ID));
+    await assertFails(
+      addStudentIntroIx(student.publicKey, otherIntro, "Ada", "Hello"),
+      [student],
+      "Invalid seeds for PDA"
+    );
+  });
 
-    assert.deepEqual(greetingAccount.data, Buffer.from([1, 0, 0, 0]));
+  it("rejects intros larger than the maximum account size", async () => {
+    // One byte over the limit, which still fits in a transaction.
+    const name = "A";
+    const message = "x".repeat(MAX_ACCOUNT_SIZE - accountSize(name, "") + 1);
+    assert.equal(accountSize(name, message), MAX_ACCOUNT_SIZE + 1);
 
-    assert.equal(deserializedAccountData.counter, 1);
+    await assertFails(
+      addStudentIntroIx(
+        pg.wallet.publicKey,
+        findIntroAddress(pg.wallet.publicKey),
+        name,
+        message
+      ),
+      [pg.wallet.keypair],
+      `Data length is larger than ${MAX_ACCOUNT_SIZE} bytes`
+    );
+  });
+
+  it("does not overwrite an existing intro", async () => {
+    const student = await newStudent();
+    const intro = findIntroAddress(student.publicKey);
+
+    await web3.sendAndConfirmTransaction(
+      pg.connection,
+      new web3.Transaction().add(
+        addStudentIntroIx(student.publicKey, intro, "Ada", "Hello")
+      ),
+      [student]
+    );
+
+    await assertFails(
+      addStudentIntroIx(student.publicKey, intro, "Mallory", "Overwritten"),
+      [student],
+      "Account already initialized"
+    );
+
+    const studentInfo = borsh.deserialize(
+      StudentInfoSchema,
+      StudentInfo,
+      (await pg.connection.getAccountInfo(intro)).data
+    );
+    assert.equal(studentInfo.name, "Ada");
+    assert.equal(studentInfo.msg, "Hello");
   });
 });